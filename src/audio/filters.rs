@@ -1,23 +1,85 @@
 use bincode::{Decode, Encode};
+use crate::audio::GB_SAMPLE_RATE;
 use crate::audio::sample::AudioSample;
+use crate::cycles::MachineCycles;
 
-#[derive(Debug, Clone, PartialEq, Default, Decode, Encode)]
+/// Charge factor of the DMG high-pass filter capacitor per CPU clock
+/// https://gbdev.gg8.se/wiki/articles/Gameboy_sound_hardware#Obscure_Behavior
+const DMG_CHARGE_FACTOR: f64 = 0.999958;
+
+#[derive(Debug, Clone, PartialEq, Decode, Encode)]
 pub struct CapacitanceFilter {
+    charge_factor: f32,
     capacitor_left: f32,
     capacitor_right: f32,
 }
 
+impl Default for CapacitanceFilter {
+    fn default() -> Self {
+        Self::new(GB_SAMPLE_RATE)
+    }
+}
+
 impl CapacitanceFilter {
+    /// Create a high-pass filter for samples processed at the specified sample rate
+    pub fn new(sample_rate: usize) -> Self {
+        let clocks_per_sample = MachineCycles::CPU_FREQ as f64 / sample_rate as f64;
+        Self {
+            charge_factor: DMG_CHARGE_FACTOR.powf(clocks_per_sample) as f32,
+            capacitor_left: 0.0,
+            capacitor_right: 0.0,
+        }
+    }
+
     pub fn process(&mut self, input: AudioSample) -> AudioSample {
         AudioSample {
-            left: Self::process_channel(input.left, &mut self.capacitor_left),
-            right: Self::process_channel(input.right, &mut self.capacitor_right),
+            left: Self::process_channel(input.left, &mut self.capacitor_left, self.charge_factor),
+            right: Self::process_channel(input.right, &mut self.capacitor_right, self.charge_factor),
         }
     }
 
-    fn process_channel(input: f32, capacitor: &mut f32) -> f32 {
+    fn process_channel(input: f32, capacitor: &mut f32, charge_factor: f32) -> f32 {
         let output = input - *capacitor;
-        *capacitor = input - output * 0.999832011; // Simple feedback to simulate capacitor behavior
+        *capacitor = input - output * charge_factor; // Simple feedback to simulate capacitor behavior
         output
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: AudioSample = AudioSample { left: 1.0, right: 1.0 };
+
+    fn step_response(filter: &mut CapacitanceFilter, samples: usize) -> f32 {
+        let mut output = AudioSample::ZERO;
+        for _ in 0..samples {
+            output = filter.process(STEP);
+        }
+        output.left
+    }
+
+    #[test]
+    fn dmg_default() {
+        let filter = CapacitanceFilter::default();
+        assert!((filter.charge_factor - 0.999832).abs() < 1e-6);
+    }
+
+    #[test]
+    fn decay_tracks_sample_rate() {
+        let mut native = CapacitanceFilter::new(GB_SAMPLE_RATE);
+        let mut device = CapacitanceFilter::new(44100);
+
+        // the output of a step input decays by the charge factor every sample
+        assert_eq!(step_response(&mut native, 2), native.charge_factor);
+        assert_eq!(step_response(&mut device, 2), device.charge_factor);
+        assert!(device.charge_factor < native.charge_factor);
+
+        // after the same amount of time, both filters have decayed by roughly the same amount
+        let mut native = CapacitanceFilter::new(GB_SAMPLE_RATE);
+        let mut device = CapacitanceFilter::new(44100);
+        let native_output = step_response(&mut native, GB_SAMPLE_RATE / 100 + 1);
+        let device_output = step_response(&mut device, 44100 / 100 + 1);
+        assert!((native_output - device_output).abs() < 0.01, "{} != {}", native_output, device_output);
+    }
+}
@@ -5,6 +5,7 @@ use bitflags::bitflags;
 #[derive(Debug, Clone, Default, Eq, PartialEq, Decode, Encode)]
 pub struct FrameSequencer {
     value: u8,
+    double_speed: bool,
 }
 
 impl FrameSequencer {
@@ -17,10 +18,15 @@ impl FrameSequencer {
         self.value = 7;
     }
 
+    /// DIV runs twice as fast in CGB double speed mode, so the sequencer is clocked from the next bit up
+    pub fn set_double_speed(&mut self, double_speed: bool) {
+        self.double_speed = double_speed;
+    }
+
     pub fn update(&mut self, div_clocks: DividerClocks) -> FrameSequencerEvent {
         let mut events = FrameSequencerEvent::empty();
-        // TODO bit 4 in normal speed mode, bit 5 in CBG (double) speed mode
-        let delta = div_clocks.bit_fall_edge(4);
+        // bit 4 in normal speed mode, bit 5 in CGB (double) speed mode
+        let delta = div_clocks.bit_fall_edge(if self.double_speed { 5 } else { 4 });
         for _ in 0..delta {
             self.value += 1;
             self.value %= 8;
@@ -57,10 +57,10 @@ impl LengthTimer {
 
         if self.value > 0 {
             self.value -= 1;
-        }
-        if self.value == 0 {
-            // length overflowed, disable the channel
-            *channel_active = false;
+            if self.value == 0 {
+                // length overflowed, disable the channel
+                *channel_active = false;
+            }
         }
     }
 }
\ No newline at end of file
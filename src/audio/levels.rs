@@ -0,0 +1,56 @@
+use crate::cycles::MachineCycles;
+
+/// ~10ms at the native sample rate
+const WINDOW_CYCLES: f32 = 10_000.0;
+
+/// Short window RMS level of each channel's output before panning, for VU meters.
+/// The DAC outputs a DC offset for silence so the mean is removed, as the high pass filter would.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ChannelLevels {
+    means: [f32; 4],
+    mean_squares: [f32; 4],
+}
+
+impl ChannelLevels {
+    /// Accumulates channel outputs that were held for `delta` machine cycles into exponential moving averages
+    pub fn update(&mut self, delta: MachineCycles, outputs: [f32; 4]) {
+        let decay = (1.0 - 1.0 / WINDOW_CYCLES).powi(delta.m_cycles() as i32);
+        for (index, output) in outputs.into_iter().enumerate() {
+            self.means[index] = output + (self.means[index] - output) * decay;
+            let square = output * output;
+            self.mean_squares[index] = square + (self.mean_squares[index] - square) * decay;
+        }
+    }
+
+    pub fn rms(&self) -> [f32; 4] {
+        std::array::from_fn(|index| (self.mean_squares[index] - self.means[index].powi(2)).max(0.0).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_wave_level() {
+        let mut levels = ChannelLevels::default();
+        for _ in 0..1000 {
+            levels.update(MachineCycles::from_m(100), [1.0, 0.5, 0.0, 0.0]);
+            levels.update(MachineCycles::from_m(100), [-1.0, -0.5, 0.0, 0.0]);
+        }
+        let [ch1, ch2, ch3, ch4] = levels.rms();
+        assert!((ch1 - 1.0).abs() < 0.01, "{}", ch1);
+        assert!((ch2 - 0.5).abs() < 0.01, "{}", ch2);
+        assert_eq!(ch3, 0.0);
+        assert_eq!(ch4, 0.0);
+    }
+
+    #[test]
+    fn dc_offset_is_silent() {
+        let mut levels = ChannelLevels::default();
+        for _ in 0..1000 {
+            levels.update(MachineCycles::from_m(100), [1.0; 4]);
+        }
+        assert!(levels.rms().iter().all(|&level| level < 0.01));
+    }
+}
@@ -5,10 +5,12 @@ use bincode::enc::Encoder;
 use bincode::error::{DecodeError, EncodeError};
 use frame_sequencer::FrameSequencer;
 use filters::CapacitanceFilter;
+use levels::ChannelLevels;
 use master_volume::MasterVolume;
 use square_channel::SquareWaveChannel;
 use crate::audio::noise_channel::NoiseChannel;
 use crate::audio::panning::Panning;
+use crate::audio::resampler::Resampler;
 use crate::audio::sample::AudioSample;
 use crate::audio::wave_channel::WaveChannel;
 use crate::cycles::MachineCycles;
@@ -25,7 +27,9 @@ pub mod sample;
 pub mod dac;
 pub mod wave_channel;
 pub mod noise_channel;
+pub mod resampler;
 mod filters;
+mod levels;
 mod timer;
 
 pub const GB_SAMPLE_RATE: usize = 1048576; // Game Boy native audio frequency
@@ -41,15 +45,71 @@ pub struct Audio {
     channel3: WaveChannel,
     channel4: NoiseChannel,
     high_pass_filter: CapacitanceFilter,
+    levels: ChannelLevels,
+    sample_rate: usize,
+    /// Only set when the sample rate is not native
+    resampler: Option<Resampler>,
     buffer: VecDeque<f32>,
+    watermarks: BufferWatermarks,
+    buffer_level: BufferLevel,
+    watermark_crossed: Option<BufferLevel>,
 }
 
-fn default_buffer() -> VecDeque<f32> {
-    VecDeque::with_capacity(2 * GB_SAMPLE_RATE / 10) // buffer for 100ms of audio, 2 channels
+const fn buffer_capacity(sample_rate: usize) -> usize {
+    2 * sample_rate / 10 // buffer for 100ms of audio, 2 channels
+}
+
+const BUFFER_CAPACITY: usize = buffer_capacity(GB_SAMPLE_RATE);
+
+/// Buffer lengths in samples (2 per stereo frame) that the front-end should keep the buffer between
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferWatermarks {
+    pub low: usize,
+    pub high: usize,
+}
+
+impl Default for BufferWatermarks {
+    fn default() -> Self {
+        Self::for_capacity(BUFFER_CAPACITY)
+    }
+}
+
+impl BufferWatermarks {
+    pub fn for_capacity(capacity: usize) -> Self {
+        Self { low: capacity / 4, high: capacity * 3 / 4 }
+    }
+
+    pub fn level(&self, buffer_len: usize) -> BufferLevel {
+        if buffer_len < self.low {
+            BufferLevel::Low
+        } else if buffer_len > self.high {
+            BufferLevel::High
+        } else {
+            BufferLevel::Normal
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BufferLevel {
+    /// Below the low watermark, the front-end should speed up emulation to avoid an underrun
+    #[default]
+    Low,
+    Normal,
+    /// Above the high watermark, the front-end should slow down emulation to avoid an overrun
+    High,
 }
 
 impl Default for Audio {
     fn default() -> Self {
+        Self::with_sample_rate(GB_SAMPLE_RATE)
+    }
+}
+
+impl Audio {
+    /// Samples are emitted at `sample_rate`, downsampled from the native rate when lower
+    pub fn with_sample_rate(sample_rate: usize) -> Self {
+        let capacity = buffer_capacity(sample_rate);
         Self {
             enabled: false,
             panning: Panning::default(),
@@ -60,16 +120,82 @@ impl Default for Audio {
             channel3: WaveChannel::default(),
             channel4: NoiseChannel::default(),
             high_pass_filter: CapacitanceFilter::default(),
-            buffer: default_buffer()
+            levels: ChannelLevels::default(),
+            sample_rate,
+            resampler: (sample_rate != GB_SAMPLE_RATE).then(|| Resampler::new(GB_SAMPLE_RATE, sample_rate)),
+            buffer: VecDeque::with_capacity(capacity),
+            watermarks: BufferWatermarks::for_capacity(capacity),
+            buffer_level: BufferLevel::default(),
+            watermark_crossed: None,
         }
     }
-}
 
-impl Audio {
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// Change the output rate without touching the emulated state, samples buffered at the old rate are dropped
+    /// and the watermarks are resized for the new buffer capacity
+    pub fn set_sample_rate(&mut self, sample_rate: usize) {
+        let capacity = buffer_capacity(sample_rate);
+        self.sample_rate = sample_rate;
+        self.resampler = (sample_rate != GB_SAMPLE_RATE).then(|| Resampler::new(GB_SAMPLE_RATE, sample_rate));
+        self.buffer = VecDeque::with_capacity(capacity);
+        self.watermarks = BufferWatermarks::for_capacity(capacity);
+        self.buffer_level = BufferLevel::default();
+        self.watermark_crossed = None;
+    }
+
     pub fn buffer_mut(&mut self) -> &mut VecDeque<f32> {
         &mut self.buffer
     }
 
+    /// Samples waiting to be consumed by the front-end, 2 per stereo frame
+    pub fn buffer_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn watermarks(&self) -> BufferWatermarks {
+        self.watermarks
+    }
+
+    pub fn set_watermarks(&mut self, watermarks: BufferWatermarks) {
+        self.watermarks = watermarks;
+        self.update_buffer_level();
+    }
+
+    pub fn buffer_level(&self) -> BufferLevel {
+        self.watermarks.level(self.buffer.len())
+    }
+
+    /// The level the buffer moved into when it last crossed a watermark, cleared once read.
+    /// Crossings caused by the front-end draining the buffer are reported after the next update.
+    pub fn consume_watermark_crossing(&mut self) -> Option<BufferLevel> {
+        self.watermark_crossed.take()
+    }
+
+    fn update_buffer_level(&mut self) {
+        let level = self.buffer_level();
+        if level != self.buffer_level {
+            self.buffer_level = level;
+            self.watermark_crossed = Some(level);
+        }
+    }
+
+    /// Short window RMS of each channel's output before panning & master volume, 0.0 to 1.0
+    pub fn channel_levels(&self) -> [f32; 4] {
+        self.levels.rms()
+    }
+
+    pub fn set_double_speed(&mut self, double_speed: bool) {
+        self.frame_sequencer.set_double_speed(double_speed);
+    }
+
+    /// CGB carts run without the DMG only wave RAM quirks
+    pub fn set_cgb(&mut self, cgb: bool) {
+        self.channel3.set_cgb(cgb);
+    }
+
     fn reset(&mut self) {
         self.frame_sequencer.reset();
         self.panning = Panning::default();
@@ -83,6 +209,7 @@ impl Audio {
 
     pub fn update(&mut self, delta: MachineCycles, div_clocks: DividerClocks) {
         if !self.enabled {
+            self.levels.update(delta, [0.0; 4]);
             self.push_sample(delta, AudioSample::ZERO);
             return;
         }
@@ -92,6 +219,12 @@ impl Audio {
         self.channel2.update(delta, events);
         self.channel3.update(delta, events);
         self.channel4.update(delta, events);
+        self.levels.update(delta, [
+            self.channel1.output_f32(),
+            self.channel2.output_f32(),
+            self.channel3.output_f32(),
+            self.channel4.output_f32(),
+        ]);
 
         if !self.channel1.dac_enabled() && !self.channel2.dac_enabled() && !self.channel3.dac_enabled() && !self.channel4.dac_enabled() {
             // When all four channel DACs are off, the master volume units are disconnected from the sound output and the output level becomes 0
@@ -112,13 +245,14 @@ impl Audio {
     fn push_sample(&mut self, delta: MachineCycles, sample: AudioSample) {
         for _ in 0..delta.m_cycles() {
             let filtered_sample = self.high_pass_filter.process(sample);
-            self.buffer.push_back(filtered_sample.left);
-            self.buffer.push_back(filtered_sample.right);
-            if self.buffer.len() >= self.buffer.capacity() {
-                // audio buffer overflow :-(
-                self.buffer.drain(..2);
+            let frame = [filtered_sample.left, filtered_sample.right];
+            let buffer = &mut self.buffer;
+            match &mut self.resampler {
+                Some(resampler) => resampler.push_frame(frame, |frame| push_frame(buffer, frame)),
+                None => push_frame(buffer, frame),
             }
         }
+        self.update_buffer_level();
     }
 
     pub fn nr52_master_control(&self) -> u8 {
@@ -245,6 +379,16 @@ impl Audio {
         }
     }
 
+    /// PCM12, the digital outputs of channel 2 (high nibble) & channel 1 (low nibble)
+    pub fn pcm12(&self) -> u8 {
+        self.channel2.digital_output() << 4 | self.channel1.digital_output()
+    }
+
+    /// PCM34, the digital outputs of channel 4 (high nibble) & channel 3 (low nibble)
+    pub fn pcm34(&self) -> u8 {
+        self.channel4.digital_output() << 4 | self.channel3.digital_output()
+    }
+
     pub fn channel1(&self) -> &SquareWaveChannel {
         &self.channel1
     }
@@ -278,6 +422,15 @@ impl Audio {
     }
 }
 
+fn push_frame(buffer: &mut VecDeque<f32>, [left, right]: [f32; 2]) {
+    buffer.push_back(left);
+    buffer.push_back(right);
+    if buffer.len() >= buffer.capacity() {
+        // audio buffer overflow :-(
+        buffer.drain(..2);
+    }
+}
+
 impl PartialEq for Audio {
     fn eq(&self, other: &Self) -> bool {
         self.enabled == other.enabled &&
@@ -306,7 +459,13 @@ impl<__Context> Decode<__Context> for Audio {
             channel3: Decode::decode(decoder)?,
             channel4: Decode::decode(decoder)?,
             high_pass_filter: CapacitanceFilter::default(),
-            buffer: default_buffer(),
+            levels: ChannelLevels::default(),
+            sample_rate: GB_SAMPLE_RATE, // the output rate belongs to the front-end, it is restored by the loader
+            resampler: None,
+            buffer: VecDeque::with_capacity(BUFFER_CAPACITY),
+            watermarks: BufferWatermarks::default(),
+            buffer_level: BufferLevel::default(),
+            watermark_crossed: None,
         })
     }
 }
@@ -323,7 +482,13 @@ impl<'__de, __Context> BorrowDecode<'__de, __Context> for Audio {
             channel3: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             channel4: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             high_pass_filter: CapacitanceFilter::default(),
-            buffer: default_buffer(),
+            levels: ChannelLevels::default(),
+            sample_rate: GB_SAMPLE_RATE,
+            resampler: None,
+            buffer: VecDeque::with_capacity(BUFFER_CAPACITY),
+            watermarks: BufferWatermarks::default(),
+            buffer_level: BufferLevel::default(),
+            watermark_crossed: None,
         })
     }
 }
@@ -341,4 +506,273 @@ impl Encode for Audio
         Encode::encode(&self.channel4, encoder)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Registers used to drive the length counter of each channel
+    struct ChannelRegisters {
+        dac: (u16, u8),
+        length: u16,
+        control: u16,
+        status_bit: u8,
+        max_length: usize,
+    }
+
+    const CHANNELS: [ChannelRegisters; 4] = [
+        ChannelRegisters { dac: (0xFF12, 0xF0), length: 0xFF11, control: 0xFF14, status_bit: 0x01, max_length: 64 },
+        ChannelRegisters { dac: (0xFF17, 0xF0), length: 0xFF16, control: 0xFF19, status_bit: 0x02, max_length: 64 },
+        ChannelRegisters { dac: (0xFF1A, 0x80), length: 0xFF1B, control: 0xFF1E, status_bit: 0x04, max_length: 256 },
+        ChannelRegisters { dac: (0xFF21, 0xF0), length: 0xFF20, control: 0xFF23, status_bit: 0x08, max_length: 64 },
+    ];
+
+    const TRIGGER: u8 = 0x80;
+    const LENGTH_ENABLE: u8 = 0x40;
+
+    /// one frame sequencer step, 8192 Hz
+    const FRAME_SEQUENCER_STEP: DividerClocks = DividerClocks { initial_value: 0, count: 32 };
+
+    fn powered_on() -> Audio {
+        let mut audio = Audio::default();
+        audio.write(0xFF26, 0x80);
+        audio
+    }
+
+    /// advance the frame sequencer so that the next step does not clock the length counters
+    fn first_half_of_length_period(audio: &mut Audio) {
+        audio.frame_sequencer.reset();
+        assert!(audio.frame_sequencer.current_events().is_length_counter());
+    }
+
+    /// advance the frame sequencer so that the next step clocks the length counters
+    fn second_half_of_length_period(audio: &mut Audio) {
+        audio.frame_sequencer.reset();
+        audio.frame_sequencer.update(FRAME_SEQUENCER_STEP);
+        assert!(!audio.frame_sequencer.current_events().is_length_counter());
+    }
+
+    /// clock each length counter once
+    fn clock_length(audio: &mut Audio) {
+        second_half_of_length_period(audio);
+        audio.update(MachineCycles::ONE, FRAME_SEQUENCER_STEP);
+    }
+
+    fn is_active(audio: &Audio, channel: &ChannelRegisters) -> bool {
+        audio.read(0xFF26) & channel.status_bit != 0
+    }
+
+    /// number of length clocks until the channel is disabled by its length counter
+    fn remaining_length(audio: &mut Audio, channel: &ChannelRegisters) -> usize {
+        let mut clocks = 0;
+        while is_active(audio, channel) {
+            assert!(clocks <= channel.max_length, "length counter did not disable the channel");
+            clock_length(audio);
+            clocks += 1;
+        }
+        clocks
+    }
+
+    #[test]
+    fn length_counter_disables_channel() {
+        for channel in &CHANNELS {
+            let mut audio = powered_on();
+            audio.write(channel.dac.0, channel.dac.1);
+            audio.write(channel.length, (channel.max_length - 2) as u8);
+            second_half_of_length_period(&mut audio);
+            audio.write(channel.control, TRIGGER | LENGTH_ENABLE);
+            assert_eq!(remaining_length(&mut audio, channel), 2);
+        }
+    }
+
+    #[test]
+    fn trigger_reloads_expired_length_to_max() {
+        for channel in &CHANNELS {
+            let mut audio = powered_on();
+            audio.write(channel.dac.0, channel.dac.1);
+            audio.write(channel.length, (channel.max_length - 1) as u8);
+            second_half_of_length_period(&mut audio);
+            audio.write(channel.control, TRIGGER | LENGTH_ENABLE);
+            assert_eq!(remaining_length(&mut audio, channel), 1);
+
+            // length has expired, the next trigger reloads it to the maximum
+            second_half_of_length_period(&mut audio);
+            audio.write(channel.control, TRIGGER | LENGTH_ENABLE);
+            assert_eq!(remaining_length(&mut audio, channel), channel.max_length);
+        }
+    }
+
+    #[test]
+    fn trigger_reloads_expired_length_in_first_half_with_extra_clock() {
+        for channel in &CHANNELS {
+            let mut audio = powered_on();
+            audio.write(channel.dac.0, channel.dac.1);
+            audio.write(channel.length, (channel.max_length - 1) as u8);
+            second_half_of_length_period(&mut audio);
+            audio.write(channel.control, TRIGGER | LENGTH_ENABLE);
+            assert_eq!(remaining_length(&mut audio, channel), 1);
+
+            first_half_of_length_period(&mut audio);
+            audio.write(channel.control, TRIGGER | LENGTH_ENABLE);
+            assert_eq!(remaining_length(&mut audio, channel), channel.max_length - 1);
+        }
+    }
+
+    #[test]
+    fn enabling_length_in_first_half_clocks_length() {
+        for channel in &CHANNELS {
+            let mut audio = powered_on();
+            audio.write(channel.dac.0, channel.dac.1);
+            audio.write(channel.length, (channel.max_length - 4) as u8);
+            second_half_of_length_period(&mut audio);
+            audio.write(channel.control, TRIGGER);
+
+            first_half_of_length_period(&mut audio);
+            audio.write(channel.control, LENGTH_ENABLE);
+            assert_eq!(remaining_length(&mut audio, channel), 3);
+        }
+    }
+
+    #[test]
+    fn enabling_length_in_second_half_does_not_clock_length() {
+        for channel in &CHANNELS {
+            let mut audio = powered_on();
+            audio.write(channel.dac.0, channel.dac.1);
+            audio.write(channel.length, (channel.max_length - 4) as u8);
+            second_half_of_length_period(&mut audio);
+            audio.write(channel.control, TRIGGER);
+            audio.write(channel.control, LENGTH_ENABLE);
+            assert_eq!(remaining_length(&mut audio, channel), 4);
+        }
+    }
+
+    #[test]
+    fn extra_length_clock_to_zero_disables_channel() {
+        for channel in &CHANNELS {
+            let mut audio = powered_on();
+            audio.write(channel.dac.0, channel.dac.1);
+            audio.write(channel.length, (channel.max_length - 1) as u8);
+            second_half_of_length_period(&mut audio);
+            audio.write(channel.control, TRIGGER);
+            assert!(is_active(&audio, channel));
+
+            first_half_of_length_period(&mut audio);
+            audio.write(channel.control, LENGTH_ENABLE);
+            assert!(!is_active(&audio, channel));
+        }
+    }
+
+    #[test]
+    fn channel_levels() {
+        let mut audio = powered_on();
+        audio.write(0xFF17, 0xF0); // channel 2 max volume, no envelope
+        audio.write(0xFF16, 0x80); // 50% duty
+        audio.write(0xFF18, 0x00);
+        audio.write(0xFF19, 0x87); // trigger
+        for _ in 0..20_000 {
+            audio.update(MachineCycles::ONE, DividerClocks { initial_value: 0, count: 0 });
+        }
+
+        let [channel1, channel2, channel3, channel4] = audio.channel_levels();
+        assert!(channel2 > 0.5, "{}", channel2);
+        assert_eq!([channel1, channel3, channel4], [0.0; 3]);
+    }
+
+    #[test]
+    fn buffer_watermarks() {
+        let mut audio = Audio::default();
+        audio.set_watermarks(BufferWatermarks { low: 100, high: 200 });
+        let step = DividerClocks { initial_value: 0, count: 0 };
+
+        audio.update(MachineCycles::from_m(60), step); // 2 samples per machine cycle
+        assert_eq!(audio.buffer_len(), 120);
+        assert_eq!(audio.buffer_level(), BufferLevel::Normal);
+        assert_eq!(audio.consume_watermark_crossing(), Some(BufferLevel::Normal));
+
+        audio.update(MachineCycles::from_m(60), step);
+        assert_eq!(audio.buffer_level(), BufferLevel::High);
+        assert_eq!(audio.consume_watermark_crossing(), Some(BufferLevel::High));
+        assert_eq!(audio.consume_watermark_crossing(), None);
+
+        audio.update(MachineCycles::from_m(10), step);
+        assert_eq!(audio.consume_watermark_crossing(), None); // still high
+
+        audio.buffer_mut().clear();
+        audio.update(MachineCycles::ONE, step);
+        assert_eq!(audio.consume_watermark_crossing(), Some(BufferLevel::Low));
+    }
+
+    #[test]
+    fn with_sample_rate() {
+        let step = DividerClocks { initial_value: 0, count: 0 };
+        let mut audio = Audio::with_sample_rate(48000);
+        assert_eq!(audio.sample_rate(), 48000);
+        assert!(audio.buffer.capacity() >= 9600);
+        assert_eq!(audio.watermarks(), BufferWatermarks { low: 2400, high: 7200 });
+
+        // 50ms of emulated time
+        audio.update(MachineCycles::from_m(GB_SAMPLE_RATE / 20), step);
+        assert!((audio.buffer_len() as isize - 4800).abs() <= 2, "{}", audio.buffer_len());
+
+        assert_eq!(Audio::default().sample_rate(), GB_SAMPLE_RATE);
+    }
+
+    #[test]
+    fn set_sample_rate() {
+        let step = DividerClocks { initial_value: 0, count: 0 };
+        let mut audio = Audio::default();
+        audio.write(0xFF26, 0x80); // power on
+        audio.update(MachineCycles::from_m(100), step);
+
+        audio.set_sample_rate(48000);
+        assert!(audio.enabled);
+        assert_eq!(audio.buffer_len(), 0);
+        assert_eq!(audio.watermarks(), BufferWatermarks { low: 2400, high: 7200 });
+
+        audio.update(MachineCycles::from_m(GB_SAMPLE_RATE / 20), step);
+        assert!((audio.buffer_len() as isize - 4800).abs() <= 2, "{}", audio.buffer_len());
+
+        audio.set_sample_rate(GB_SAMPLE_RATE);
+        assert!(audio.resampler.is_none());
+    }
+
+    #[test]
+    fn noise_channel_is_mixed() {
+        let step = DividerClocks { initial_value: 0, count: 0 };
+        let noise = |panning: u8, volume_envelope: u8| {
+            let mut audio = powered_on();
+            audio.write(0xFF24, 0x77); // max master volume
+            audio.write(0xFF25, panning);
+            audio.write(0xFF21, volume_envelope);
+            audio.write(0xFF22, 0x00); // fastest LFSR clock
+            audio.write(0xFF23, TRIGGER);
+            audio.buffer_mut().clear();
+            for _ in 0..10_000 {
+                audio.update(MachineCycles::ONE, step);
+            }
+            audio.buffer_mut().iter().map(|sample| sample.abs()).fold(0.0f32, f32::max)
+        };
+
+        assert!(noise(0x88, 0xF0) > 0.01); // channel 4 panned left & right
+        assert_eq!(noise(0x00, 0xF0), 0.0); // not panned
+        assert_eq!(noise(0x88, 0x00), 0.0); // DAC off
+    }
+
+    #[test]
+    fn sweep_overflow_on_trigger() {
+        let trigger = |nr10: u8| {
+            let mut audio = powered_on();
+            audio.write(0xFF10, nr10);
+            audio.write(0xFF12, 0xF0); // DAC on
+            audio.write(0xFF13, 0xFF);
+            audio.write(0xFF14, TRIGGER | 0x07); // period 0x7FF
+            audio.channel1().is_active()
+        };
+
+        assert!(!trigger(0x01)); // 0x7FF + (0x7FF >> 1) overflows, checked immediately as the shift is non-zero
+        assert!(!trigger(0x71)); // the sweep pace does not matter
+        assert!(trigger(0x00)); // no shift, so no calculation on trigger
+        assert!(trigger(0x09)); // subtraction cannot overflow
+    }
+}
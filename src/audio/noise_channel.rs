@@ -102,6 +102,15 @@ impl NoiseChannel {
         self.output
     }
 
+    /// The 4-bit digital output, 0 while the channel or its DAC is off
+    pub fn digital_output(&self) -> u8 {
+        if !self.envelope_function.dac_enabled() || !self.active {
+            0
+        } else {
+            self.output
+        }
+    }
+
     pub fn output_f32(&self) -> f32 {
         if !self.envelope_function.dac_enabled() || !self.active {
             0.0
@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+
+/// Downsamples interleaved stereo audio by averaging every source frame that falls within each output
+/// frame's period, partially covered source frames are weighted by their overlap.
+/// This box filter is a cheap low-pass that removes most of the aliasing plain decimation would cause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resampler {
+    /// Source frames per output frame
+    step: f64,
+    /// Source frames still to be accumulated into the current output frame
+    remaining: f64,
+    sum: [f64; 2],
+    /// Reused between calls so draining the input does not allocate
+    scratch: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(source_hz: usize, target_hz: usize) -> Self {
+        let step = source_hz as f64 / target_hz as f64;
+        Self { step, remaining: step, sum: [0.0; 2], scratch: vec![] }
+    }
+
+    /// Consumes every complete stereo frame from the input, any trailing odd sample is left for the next call
+    pub fn process(&mut self, input: &mut VecDeque<f32>, out: &mut Vec<f32>) {
+        let frames = input.len() / 2;
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.extend(input.drain(..frames * 2));
+        for frame in scratch.chunks_exact(2) {
+            self.push_frame([frame[0], frame[1]], |output| out.extend(output));
+        }
+        scratch.clear(); // keeps its capacity
+        self.scratch = scratch;
+    }
+
+    /// Accumulates a single source frame, calling `emit` for each output frame it completes
+    pub fn push_frame(&mut self, frame: [f32; 2], mut emit: impl FnMut([f32; 2])) {
+        let mut frame_left = 1.0;
+        while frame_left > 0.0 {
+            let weight = self.remaining.min(frame_left);
+            self.sum[0] += frame[0] as f64 * weight;
+            self.sum[1] += frame[1] as f64 * weight;
+            self.remaining -= weight;
+            frame_left -= weight;
+
+            if self.remaining <= f64::EPSILON {
+                emit([(self.sum[0] / self.step) as f32, (self.sum[1] / self.step) as f32]);
+                self.sum = [0.0; 2];
+                self.remaining += self.step;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stereo(frames: impl IntoIterator<Item = (f32, f32)>) -> VecDeque<f32> {
+        frames.into_iter().flat_map(|(left, right)| [left, right]).collect()
+    }
+
+    #[test]
+    fn integer_ratio() {
+        let mut resampler = Resampler::new(4, 1);
+        let mut input = stereo([(1.0, -1.0), (1.0, -1.0), (0.0, 0.0), (0.0, 0.0), (0.5, 0.25)]);
+        let mut out = vec![];
+        resampler.process(&mut input, &mut out);
+        assert_eq!(out, vec![0.5, -0.5]);
+        assert!(input.is_empty());
+
+        // the partial frame is completed by the next call
+        let mut input = stereo([(0.5, 0.25), (0.5, 0.25), (0.5, 0.25)]);
+        resampler.process(&mut input, &mut out);
+        assert_eq!(out, vec![0.5, -0.5, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn fractional_ratio() {
+        let mut resampler = Resampler::new(3, 2);
+        let mut input = stereo([(1.0, 0.0), (0.0, 1.0), (1.0, 0.0)]);
+        let mut out = vec![];
+        resampler.process(&mut input, &mut out);
+
+        // each output frame averages 1.5 source frames
+        assert_eq!(out.len(), 4);
+        assert!((out[0] - 2.0 / 3.0).abs() < 1e-6);
+        assert!((out[1] - 1.0 / 3.0).abs() < 1e-6);
+        assert!((out[2] - 2.0 / 3.0).abs() < 1e-6);
+        assert!((out[3] - 1.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn odd_sample_is_kept() {
+        let mut resampler = Resampler::new(2, 1);
+        let mut input: VecDeque<f32> = [0.1, 0.2, 0.3].into_iter().collect();
+        let mut out = vec![];
+        resampler.process(&mut input, &mut out);
+        assert!(out.is_empty());
+        assert_eq!(input, [0.3]);
+    }
+
+    #[test]
+    fn game_boy_to_44100() {
+        let mut resampler = Resampler::new(crate::audio::GB_SAMPLE_RATE, 44100);
+        let mut input = stereo(std::iter::repeat_n((0.25, -0.25), crate::audio::GB_SAMPLE_RATE));
+        let mut out = vec![];
+        resampler.process(&mut input, &mut out);
+        assert!((out.len() as isize / 2 - 44100).abs() <= 1, "{}", out.len());
+        assert!(out.chunks_exact(2).all(|frame| (frame[0] - 0.25).abs() < 1e-4 && (frame[1] + 0.25).abs() < 1e-4));
+    }
+}
@@ -137,6 +137,15 @@ impl SquareWaveChannel {
         self.output
     }
 
+    /// The 4-bit digital output, 0 while the channel or its DAC is off
+    pub fn digital_output(&self) -> u8 {
+        if self.envelope_function.dac_enabled() && self.active {
+            self.output
+        } else {
+            0
+        }
+    }
+
     pub fn output_f32(&self) -> f32 {
         if self.envelope_function.dac_enabled() && self.active {
             dac_sample(self.output)
@@ -42,6 +42,16 @@ impl<const MAX_PHASE: u8, const SPEED_MULTIPLIER: usize> PhaseTimer<MAX_PHASE, S
         self.counter = self.period;
     }
 
+    /// Delay the next clock by the specified number of ticks
+    pub fn delay(&mut self, ticks: u16) {
+        self.counter += ticks;
+    }
+
+    /// Number of timer ticks until the counter next overflows
+    pub fn ticks_until_clock(self) -> u16 {
+        self.counter
+    }
+
     pub fn phase(&self) -> u8 {
         self.phase
     }
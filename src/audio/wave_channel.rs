@@ -37,6 +37,8 @@ pub struct WaveChannel {
     frequency_timer: WavetableTimer, // internal counter that overflows at current_period
 
     sample_buffer: u8, // current output sample (0-15)
+    wave_ram_accessible: bool, // the channel read from wave RAM on the last wavetable timer tick
+    cgb: bool, // the DMG wave RAM access & corruption quirks do not apply
 }
 
 // From https://gbdev.gg8.se/wiki/articles/Gameboy_sound_hardware#Power_Control
@@ -44,6 +46,9 @@ const DMG_INITIAL_RAM: [u8; 16] = [
     0x84, 0x40, 0x43, 0xAA, 0x2D, 0x78, 0x92, 0x3C, 0x60, 0x59, 0x59, 0xB0, 0x34, 0xB8, 0x2E, 0xDA,
 ];
 
+/// The first sample is read 3 wavetable timer ticks later than a full period after the channel is triggered
+const WAVE_TRIGGER_DELAY_TICKS: u16 = 3;
+
 impl Default for WaveChannel {
     fn default() -> Self {
         Self {
@@ -55,7 +60,9 @@ impl Default for WaveChannel {
             wave_ram: DMG_INITIAL_RAM,
             active: false,
             frequency_timer: WavetableTimer::default(),
-            sample_buffer: 0
+            sample_buffer: 0,
+            wave_ram_accessible: false,
+            cgb: false,
         }
     }
 }
@@ -63,7 +70,11 @@ impl Default for WaveChannel {
 impl WaveChannel {
     pub fn reset(&mut self) {
         // wave ram is not touched on reset
-        *self = Self { wave_ram: self.wave_ram, ..Self::default() };
+        *self = Self { wave_ram: self.wave_ram, cgb: self.cgb, ..Self::default() };
+    }
+
+    pub fn set_cgb(&mut self, cgb: bool) {
+        self.cgb = cgb;
     }
 
     pub fn nr30(&self) -> u8 {
@@ -111,6 +122,8 @@ impl WaveChannel {
 
     pub fn set_nr33_period_low(&mut self, value: u8) {
         self.period_register = (self.period_register & 0xFF00) | value as u16;
+        // the new period is used from the next time the wavetable timer is reloaded
+        self.frequency_timer.set_frequency(self.period_register);
     }
 
     pub fn nr34_period_high_and_control(&self) -> u8 {
@@ -120,6 +133,7 @@ impl WaveChannel {
 
     pub fn set_nr34_period_high_and_control(&mut self, value: u8, frame_sequencer: &FrameSequencer) {
         self.period_register = (self.period_register & 0x00FF) | (((value & 0b111) as u16) << 8);
+        self.frequency_timer.set_frequency(self.period_register);
         let length_enabled = value & 0b01000000 != 0;
         self.length_timer.set_enabled(length_enabled, frame_sequencer, &mut self.active);
         if value & 0b10000000 != 0 {
@@ -130,15 +144,44 @@ impl WaveChannel {
     pub fn wave_ram(&self, index: usize) -> u8 {
         // Reading from wavetable RAM while the channel is playing returns the contents of RAM at
         // the current wave position rather than the requested address.
-        if self.active {
+        // On the DMG this only works on the same clock as the channel reading wave RAM, otherwise 0xFF is returned.
+        if !self.active {
+            self.wave_ram[index]
+        } else if self.cgb || self.wave_ram_accessible {
             self.current_sample_byte()
         } else {
-            self.wave_ram[index]
+            0xFF
         }
     }
 
     pub fn set_wave_ram(&mut self, index: usize, value: u8) {
-        self.wave_ram[index] = value;
+        // Writes while the channel is playing are redirected to the current wave position,
+        // on the DMG they are ignored unless the channel is reading wave RAM at the same time.
+        if !self.active {
+            self.wave_ram[index] = value;
+        } else if self.cgb || self.wave_ram_accessible {
+            let position = self.current_sample_index();
+            self.wave_ram[position] = value;
+        }
+    }
+
+    /// Same as `wave_ram` but returns `None` rather than panicking when the index is out of range
+    pub fn wave_ram_checked(&self, index: usize) -> Option<u8> {
+        if index < self.wave_ram.len() {
+            Some(self.wave_ram(index))
+        } else {
+            None
+        }
+    }
+
+    /// Same as `set_wave_ram` but returns an error rather than panicking when the index is out of range
+    pub fn set_wave_ram_checked(&mut self, index: usize, value: u8) -> Result<(), String> {
+        if index < self.wave_ram.len() {
+            self.set_wave_ram(index, value);
+            Ok(())
+        } else {
+            Err(format!("Wave RAM index {} out of range", index))
+        }
     }
 
     pub fn is_active(&self) -> bool {
@@ -149,27 +192,50 @@ impl WaveChannel {
         self.dac_enabled
     }
 
-    pub fn output_f32(&self) -> f32 {
+    /// The 4-bit digital output after the output level is applied, 0 while the DAC is off or muted
+    pub fn digital_output(&self) -> u8 {
         if !self.dac_enabled || self.volume_register == 0 {
-            return 0.0;
+            return 0;
         }
 
         let sample_byte = self.sample_buffer >> (self.volume_register - 1);
-        let sample = if self.frequency_timer.phase() & 0x1 == 0 {
+        if self.frequency_timer.phase() & 0x1 == 0 {
             sample_byte >> 4
         } else {
             sample_byte & 0xF
-        };
+        }
+    }
 
-        dac_sample(sample)
+    pub fn output_f32(&self) -> f32 {
+        if !self.dac_enabled || self.volume_register == 0 {
+            return 0.0;
+        }
+        dac_sample(self.digital_output())
     }
 
     pub fn trigger(&mut self, frame_sequencer: &FrameSequencer) {
+        if !self.cgb && self.active && self.frequency_timer.ticks_until_clock() == 1 {
+            self.corrupt_wave_ram();
+        }
         self.active = self.dac_enabled;
         self.length_timer.trigger(frame_sequencer);
         self.frequency_timer.set_frequency(self.period_register);
         self.frequency_timer.trigger();
+        self.frequency_timer.delay(WAVE_TRIGGER_DELAY_TICKS);
+        self.wave_ram_accessible = false;
+    }
 
+    /// On the DMG, triggering the channel on the tick before it reads wave RAM overwrites the start of wave RAM
+    /// with the byte it was about to read, or the 4 aligned bytes containing it if it is outside the first 4 bytes.
+    /// https://gbdev.gg8.se/wiki/articles/Gameboy_sound_hardware#Obscure_Behavior
+    fn corrupt_wave_ram(&mut self) {
+        let position = (((self.frequency_timer.phase() + 1) >> 1) & 0x0F) as usize;
+        if position < 4 {
+            self.wave_ram[0] = self.wave_ram[position];
+        } else {
+            let start = position & !0x03;
+            self.wave_ram.copy_within(start..start + 4, 0);
+        }
     }
 
     pub fn update(&mut self, delta: MachineCycles, events: FrameSequencerEvent) {
@@ -179,6 +245,7 @@ impl WaveChannel {
 
         if !self.active {
             self.sample_buffer = 0;
+            self.wave_ram_accessible = false;
 
             // disabled channels still clock the length counter
             if events.is_length_counter() {
@@ -191,14 +258,22 @@ impl WaveChannel {
             self.clock_length_timer();
         }
 
-        if self.active && self.frequency_timer.update(delta) {
+        let clocked = self.active && self.frequency_timer.update(delta);
+        if clocked {
             // overflow, emit a sample
             self.sample_buffer = self.current_sample_byte();
         }
+        // the wavetable timer ticks twice per machine cycle, the CPU can only access wave RAM
+        // while the channel is active if the channel read from it on the very last tick
+        self.wave_ram_accessible = clocked && self.frequency_timer.just_reloaded();
+    }
+
+    fn current_sample_index(&self) -> usize {
+        (self.frequency_timer.phase() >> 1) as usize
     }
 
     fn current_sample_byte(&self) -> u8 {
-        self.wave_ram[(self.frequency_timer.phase() >> 1) as usize]
+        self.wave_ram[self.current_sample_index()]
     }
 
     fn clock_length_timer(&mut self) {
@@ -213,3 +288,180 @@ impl WaveChannel {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// period of 15 wavetable timer ticks, so samples are read alternately on the first & last tick of a machine cycle
+    const PERIOD_LOW: u8 = 0xF1;
+    const PERIOD_HIGH_AND_TRIGGER: u8 = 0x87;
+
+    fn playing_channel() -> WaveChannel {
+        let frame_sequencer = FrameSequencer::default();
+        let mut channel = WaveChannel::default();
+        for i in 0..16 {
+            channel.set_wave_ram(i, (i as u8) * 0x11);
+        }
+        channel.set_nr30(0x80);
+        channel.set_nr33_period_low(PERIOD_LOW);
+        channel.set_nr34_period_high_and_control(PERIOD_HIGH_AND_TRIGGER, &frame_sequencer);
+        assert!(channel.is_active());
+        channel
+    }
+
+    fn run_until_sample_read(channel: &mut WaveChannel) {
+        for _ in 0..16 {
+            let phase = channel.frequency_timer.phase();
+            channel.update(MachineCycles::ONE, FrameSequencerEvent::empty());
+            if channel.frequency_timer.phase() != phase {
+                return;
+            }
+        }
+        panic!("wave channel did not read a sample");
+    }
+
+    /// run until the channel will read wave RAM on the next wavetable timer tick
+    fn run_until_tick_before_read(channel: &mut WaveChannel) {
+        for _ in 0..16 {
+            channel.update(MachineCycles::ONE, FrameSequencerEvent::empty());
+            if channel.frequency_timer.ticks_until_clock() == 1 {
+                return;
+            }
+        }
+        panic!("wave channel is not about to read a sample");
+    }
+
+    #[test]
+    fn checked_access() {
+        let mut channel = WaveChannel::default();
+        assert_eq!(channel.set_wave_ram_checked(15, 0x12), Ok(()));
+        assert_eq!(channel.wave_ram_checked(15), Some(0x12));
+        assert_eq!(channel.wave_ram_checked(16), None);
+        assert!(channel.set_wave_ram_checked(16, 0x12).is_err());
+    }
+
+    #[test]
+    fn read_while_off() {
+        let channel = WaveChannel::default();
+        assert_eq!(channel.wave_ram(0), DMG_INITIAL_RAM[0]);
+        assert_eq!(channel.wave_ram(15), DMG_INITIAL_RAM[15]);
+    }
+
+    #[test]
+    fn read_while_on() {
+        let mut channel = playing_channel();
+
+        // the channel has not read wave RAM since it was triggered
+        assert_eq!(channel.wave_ram(5), 0xFF);
+
+        // first sample read is from phase 1 on the last tick of a machine cycle, which is in the first byte of wave RAM
+        run_until_sample_read(&mut channel);
+        assert_eq!(channel.wave_ram(5), 0x00);
+        assert_eq!(channel.wave_ram(15), 0x00);
+
+        // one machine cycle later the access window has passed
+        channel.update(MachineCycles::ONE, FrameSequencerEvent::empty());
+        assert_eq!(channel.wave_ram(5), 0xFF);
+
+        // phase 2 is read on the first tick of a machine cycle, so the access window has already passed
+        run_until_sample_read(&mut channel);
+        assert_eq!(channel.wave_ram(5), 0xFF);
+
+        // phase 3 reads from the second byte of wave RAM
+        run_until_sample_read(&mut channel);
+        assert_eq!(channel.wave_ram(5), 0x11);
+    }
+
+    #[test]
+    fn cgb_read_while_on() {
+        let mut channel = playing_channel();
+        channel.set_cgb(true);
+
+        // the current sample byte is always accessible
+        assert_eq!(channel.wave_ram(5), 0x00);
+        run_until_sample_read(&mut channel);
+        run_until_sample_read(&mut channel);
+        assert_eq!(channel.wave_ram(5), 0x11); // phase 2 is read on the first tick of a machine cycle
+    }
+
+    #[test]
+    fn write_while_on() {
+        let mut channel = playing_channel();
+
+        // writes outside of the access window are ignored
+        channel.set_wave_ram(5, 0xAB);
+        run_until_sample_read(&mut channel);
+        channel.update(MachineCycles::ONE, FrameSequencerEvent::empty());
+        channel.set_wave_ram(5, 0xAB);
+        assert_eq!(channel.wave_ram, [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+
+        // writes inside the access window land at the current wave position
+        run_until_sample_read(&mut channel);
+        run_until_sample_read(&mut channel);
+        channel.set_wave_ram(5, 0xAB);
+        assert_eq!(channel.wave_ram[1], 0xAB);
+        assert_eq!(channel.wave_ram[5], 0x55);
+    }
+
+    #[test]
+    fn trigger_corrupts_first_byte() {
+        let frame_sequencer = FrameSequencer::default();
+        let mut channel = playing_channel();
+
+        // the next sample is phase 2, in the second byte of wave RAM, which is copied to the first byte
+        run_until_sample_read(&mut channel);
+        run_until_tick_before_read(&mut channel);
+        channel.set_nr34_period_high_and_control(PERIOD_HIGH_AND_TRIGGER, &frame_sequencer);
+        assert_eq!(channel.wave_ram, [0x11, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+    }
+
+    #[test]
+    fn cgb_trigger_does_not_corrupt() {
+        let frame_sequencer = FrameSequencer::default();
+        let mut channel = playing_channel();
+        channel.set_cgb(true);
+
+        run_until_sample_read(&mut channel);
+        run_until_tick_before_read(&mut channel);
+        channel.set_nr34_period_high_and_control(PERIOD_HIGH_AND_TRIGGER, &frame_sequencer);
+        assert_eq!(channel.wave_ram, [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+    }
+
+    #[test]
+    fn trigger_corrupts_first_block() {
+        let frame_sequencer = FrameSequencer::default();
+        let mut channel = playing_channel();
+        while channel.frequency_timer.phase() < 9 {
+            channel.update(MachineCycles::ONE, FrameSequencerEvent::empty());
+        }
+
+        // the next sample is phase 10 in the sixth byte of wave RAM, so bytes 4-7 are copied to the start of wave RAM
+        run_until_tick_before_read(&mut channel);
+        channel.set_nr34_period_high_and_control(PERIOD_HIGH_AND_TRIGGER, &frame_sequencer);
+        assert_eq!(channel.wave_ram, [0x44, 0x55, 0x66, 0x77, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+    }
+
+    #[test]
+    fn trigger_without_corruption() {
+        let frame_sequencer = FrameSequencer::default();
+        let mut channel = playing_channel();
+
+        // the channel is not about to read wave RAM
+        run_until_sample_read(&mut channel);
+        channel.set_nr34_period_high_and_control(PERIOD_HIGH_AND_TRIGGER, &frame_sequencer);
+        assert_eq!(channel.wave_ram, [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+    }
+
+    #[test]
+    fn period_change_applies_on_reload() {
+        let mut channel = playing_channel();
+        run_until_sample_read(&mut channel);
+
+        // 2 wavetable timer ticks, so a sample is read every machine cycle after the current period
+        channel.set_nr33_period_low(0xFE);
+        run_until_sample_read(&mut channel);
+        let phase = channel.frequency_timer.phase();
+        channel.update(MachineCycles::ONE, FrameSequencerEvent::empty());
+        assert_eq!(channel.frequency_timer.phase(), phase + 1);
+    }
+}
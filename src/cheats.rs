@@ -0,0 +1,161 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cheat {
+    GameGenie(GameGenie),
+    GameShark(GameShark),
+}
+
+impl From<GameGenie> for Cheat {
+    fn from(value: GameGenie) -> Self {
+        Cheat::GameGenie(value)
+    }
+}
+
+impl From<GameShark> for Cheat {
+    fn from(value: GameShark) -> Self {
+        Cheat::GameShark(value)
+    }
+}
+
+/// Replaces a ROM byte as it is read, optionally only when the original byte matches so that the patch
+/// only applies to the intended ROM bank
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameGenie {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl GameGenie {
+    /// Parses `ABC-DEF` or `ABC-DEF-GHI` where AB is the new value, FCDE is the address XOR 0xF000
+    /// and GI is the compare value XOR'd with 0xBA, rotated left by 2 and inverted. H is unused.
+    pub fn parse(code: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid Game Genie code {}", code);
+        let digits: Vec<u8> = code.split('-')
+            .map(|group| if group.len() == 3 { Some(group) } else { None })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?
+            .concat()
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        if digits.len() != 6 && digits.len() != 9 {
+            return Err(invalid());
+        }
+
+        let value = (digits[0] << 4) | digits[1];
+        let address = ((digits[5] as u16 ^ 0xF) << 12) | ((digits[2] as u16) << 8) | ((digits[3] as u16) << 4) | digits[4] as u16;
+        if address >= 0x8000 {
+            return Err(format!("Game Genie code {} does not patch ROM", code));
+        }
+        let compare = if digits.len() == 9 {
+            Some((!((digits[6] << 4) | digits[8])).rotate_right(2) ^ 0xBA)
+        } else {
+            None
+        };
+        Ok(Self { address, value, compare })
+    }
+
+    pub fn patch(&self, address: u16, value: u8) -> Option<u8> {
+        if address == self.address && self.compare.is_none_or(|compare| compare == value) {
+            Some(self.value)
+        } else {
+            None
+        }
+    }
+}
+
+/// Writes a byte to RAM once per frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameShark {
+    /// External RAM bank, only used for addresses in 0xA000-0xBFFF
+    pub bank: u8,
+    pub address: u16,
+    pub value: u8,
+}
+
+impl GameShark {
+    /// Parses `ABCDEFGH` where AB is the RAM bank, CD is the new value and GHEF is the address
+    pub fn parse(code: &str) -> Result<Self, String> {
+        if code.len() != 8 {
+            return Err(format!("Invalid GameShark code {}", code));
+        }
+        let value = u32::from_str_radix(code, 16).map_err(|_| format!("Invalid GameShark code {}", code))?;
+        let [bank, value, address_low, address_high] = value.to_be_bytes();
+        let address = u16::from_le_bytes([address_low, address_high]);
+        if address < 0x8000 {
+            return Err(format!("GameShark code {} does not patch RAM", code));
+        }
+        Ok(Self { bank, address, value })
+    }
+}
+
+/// Active cheats, these are not included in save states
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Cheats {
+    game_genie: Vec<GameGenie>,
+    game_shark: Vec<GameShark>,
+}
+
+impl Cheats {
+    pub fn add(&mut self, cheat: Cheat) {
+        match cheat {
+            Cheat::GameGenie(game_genie) => self.game_genie.push(game_genie),
+            Cheat::GameShark(game_shark) => self.game_shark.push(game_shark),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.game_genie.clear();
+        self.game_shark.clear();
+    }
+
+    pub fn patch_rom(&self, address: u16, value: u8) -> u8 {
+        self.game_genie.iter()
+            .find_map(|game_genie| game_genie.patch(address, value))
+            .unwrap_or(value)
+    }
+
+    pub fn game_shark(&self) -> &[GameShark] {
+        &self.game_shark
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_game_genie() {
+        assert_eq!(
+            GameGenie::parse("00A-17B-C49").unwrap(),
+            GameGenie { address: 0x4A17, value: 0x00, compare: Some(0x37) }
+        );
+        assert_eq!(
+            GameGenie::parse("3eA-17b").unwrap(),
+            GameGenie { address: 0x4A17, value: 0x3E, compare: None }
+        );
+        assert_eq!(GameGenie::parse("00A-17"), Err("Invalid Game Genie code 00A-17".to_string()));
+        assert_eq!(GameGenie::parse("00A-17X"), Err("Invalid Game Genie code 00A-17X".to_string()));
+        assert_eq!(GameGenie::parse("00A-177"), Err("Game Genie code 00A-177 does not patch ROM".to_string()));
+    }
+
+    #[test]
+    fn game_genie_compare() {
+        let game_genie = GameGenie { address: 0x4A17, value: 0x00, compare: Some(0x37) };
+        assert_eq!(game_genie.patch(0x4A17, 0x37), Some(0x00));
+        assert_eq!(game_genie.patch(0x4A17, 0x38), None); // wrong bank
+        assert_eq!(game_genie.patch(0x4A18, 0x37), None);
+    }
+
+    #[test]
+    fn parse_game_shark() {
+        assert_eq!(
+            GameShark::parse("010F48D1").unwrap(),
+            GameShark { bank: 0x01, address: 0xD148, value: 0x0F }
+        );
+        assert_eq!(GameShark::parse("010F48"), Err("Invalid GameShark code 010F48".to_string()));
+        assert_eq!(GameShark::parse("010F48DZ"), Err("Invalid GameShark code 010F48DZ".to_string()));
+        assert_eq!(GameShark::parse("010F0040"), Err("GameShark code 010F0040 does not patch RAM".to_string()));
+    }
+}
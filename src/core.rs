@@ -1,9 +1,15 @@
-use bincode::{Decode, Encode};
+use std::collections::BTreeSet;
+use bincode::{BorrowDecode, Decode, Encode};
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::Encoder;
 use crate::activation::Activation;
 use crate::cycles::MachineCycles;
+use crate::header::CGBMode;
+use crate::interrupt::Interrupt;
 use crate::mmu::MMU;
 use crate::opcode::{JumpCondition, OpCode, Register, Register16, Register16Mem, Register16Stack};
-use crate::registers::RegisterSet;
+use crate::registers::{RegisterSet, RegisterSnapshot};
+use crate::trace::{TraceEntry, TraceFn, Tracer};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Decode, Encode)]
 pub enum CoreMode {
@@ -13,13 +19,38 @@ pub enum CoreMode {
     Crash,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Decode, Encode)]
+/// The reason the core entered `CoreMode::Crash`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Decode, Encode)]
+pub enum CrashReason {
+    /// an illegal opcode was executed at the specified address
+    IllegalOpCode { raw: u8, address: u16 },
+}
+
+/// What stopped a debugger step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugEvent {
+    /// the instruction was executed & the next one is not at a breakpoint
+    Step,
+    /// the next instruction is at the specified breakpoint
+    BreakpointHit(u16),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Core {
     registers: RegisterSet,
+    boot_registers: RegisterSet, // post-boot DMG or CGB registers or power on registers with a boot ROM, restored on reset
     mmu: MMU,
     interrupts_enabled: bool,
     interrupts_enabled_on_next_instruction: bool,
-    mode: CoreMode
+    mode: CoreMode,
+    last_error: Option<CrashReason>,
+    last_interrupt: Option<Interrupt>,
+    machine_cycles: u64,
+    breakpoints: BTreeSet<u16>,
+    breakpoint_hit: Option<u16>,
+    ticked_cycles: MachineCycles, // cycles of the current instruction already applied to the MMU
+    halt_bug: bool, // the next fetch does not increment PC
+    tracer: Tracer,
 }
 
 impl Core {
@@ -28,17 +59,121 @@ impl Core {
     }
 
     pub fn dmg(cart: &[u8]) -> Self {
+        Self::try_dmg(cart).expect("could not load ROM")
+    }
+
+    pub fn try_dmg(cart: &[u8]) -> Result<Self, String> {
+        Ok(Self::new(MMU::from_rom(cart)?, RegisterSet::dmg()))
+    }
+
+    /// Post-boot register state of a CGB, graphics still run in DMG mode
+    pub fn cgb(cart: &[u8]) -> Self {
+        Self::try_cgb(cart).expect("could not load ROM")
+    }
+
+    pub fn try_cgb(cart: &[u8]) -> Result<Self, String> {
+        Ok(Self::new(MMU::from_rom(cart)?, RegisterSet::cgb()))
+    }
+
+    /// Picks DMG or CGB register initialization from the CGB flag in the cart header
+    pub fn from_rom(cart: &[u8]) -> Result<Self, String> {
+        Ok(Self::from_mmu(MMU::from_rom(cart)?))
+    }
+
+    /// Like `from_rom` but fails if the Nintendo logo in the cart header is wrong, like real hardware
+    pub fn from_rom_strict(cart: &[u8]) -> Result<Self, String> {
+        Ok(Self::from_mmu(MMU::from_rom_strict(cart)?))
+    }
+
+    /// Runs the DMG boot ROM from 0x0000 rather than starting from the post-boot state
+    pub fn with_boot_rom(boot: &[u8], cart: &[u8]) -> Result<Self, String> {
+        let mut mmu = MMU::from_rom(cart)?;
+        mmu.load_boot_rom(boot)?;
+        Ok(Self::new(mmu, RegisterSet::power_on()))
+    }
+
+    fn from_mmu(mmu: MMU) -> Self {
+        let registers = match mmu.header().cgb_mode() {
+            CGBMode::None => RegisterSet::dmg(),
+            CGBMode::Enhanced | CGBMode::Exclusive => RegisterSet::cgb(),
+        };
+        Self::new(mmu, registers)
+    }
+
+    fn new(mmu: MMU, registers: RegisterSet) -> Self {
         Self {
-            registers: RegisterSet::dmg(),
-            mmu: MMU::from_rom(cart).expect("could not load ROM"),
+            registers,
+            boot_registers: registers,
+            mmu,
             interrupts_enabled: false,
             mode: CoreMode::Normal,
             interrupts_enabled_on_next_instruction: false,
+            last_error: None,
+            last_interrupt: None,
+            machine_cycles: 0,
+            breakpoints: BTreeSet::new(),
+            breakpoint_hit: None,
+            ticked_cycles: MachineCycles::ZERO,
+            halt_bug: false,
+            tracer: Tracer::default(),
         }
     }
 
+    /// Restores the post-boot CPU state & resets the MMU, the ROM, battery RAM & breakpoints are kept
     pub fn reset(&mut self) {
-        todo!()
+        self.registers = self.boot_registers;
+        self.mmu.reset();
+        self.interrupts_enabled = false;
+        self.interrupts_enabled_on_next_instruction = false;
+        self.mode = CoreMode::Normal;
+        self.last_error = None;
+        self.last_interrupt = None;
+        self.machine_cycles = 0;
+        self.breakpoint_hit = None;
+        self.halt_bug = false;
+    }
+
+    pub fn mode(&self) -> CoreMode {
+        self.mode
+    }
+
+    /// The reason for the last crash, if the core has crashed
+    pub fn last_error(&self) -> Option<CrashReason> {
+        self.last_error
+    }
+
+    /// Total machine cycles executed, including interrupt dispatch
+    pub fn machine_cycles(&self) -> u64 {
+        self.machine_cycles
+    }
+
+    /// CGB double speed mode, each machine cycle takes half as long
+    pub fn is_double_speed(&self) -> bool {
+        self.mmu.speed().is_double_speed()
+    }
+
+    /// The most recently serviced interrupt
+    pub fn last_interrupt(&self) -> Option<Interrupt> {
+        self.last_interrupt
+    }
+
+    /// Attempt to recover from a crash by resuming execution from the current program counter
+    pub fn recover(&mut self) -> Option<CrashReason> {
+        if self.mode != CoreMode::Crash {
+            return None;
+        }
+        self.mode = CoreMode::Normal;
+        self.mmu.restart();
+        self.last_error.take()
+    }
+
+    pub fn registers(&self) -> RegisterSnapshot {
+        self.registers.into()
+    }
+
+    /// Overwrite all registers, the low nibble of F is always zero on hardware so is discarded
+    pub fn set_registers(&mut self, snapshot: RegisterSnapshot) {
+        self.registers = snapshot.into();
     }
 
     pub fn mmu(&self) -> &MMU {
@@ -58,7 +193,7 @@ impl Core {
             E => self.registers.e,
             H => self.registers.h,
             L => self.registers.l,
-            mHL => self.mmu.read(self.registers.hl()),
+            mHL => self.mmu.cpu_read(self.registers.hl()),
             A => self.registers.a,
         }
     }
@@ -72,11 +207,45 @@ impl Core {
             E => self.registers.e = value,
             H => self.registers.h = value,
             L => self.registers.l = value,
-            mHL => self.mmu.write(self.registers.hl(), value),
+            mHL => self.mmu.cpu_write(self.registers.hl(), value),
             A => self.registers.a = value,
         }
     }
 
+    /// Advance the rest of the hardware by a machine cycle part way through an instruction
+    fn tick(&mut self) {
+        self.mmu.tick();
+        self.ticked_cycles += MachineCycles::ONE;
+    }
+
+    /// Each memory access takes a machine cycle, so the hardware has advanced by every earlier cycle of the instruction
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.mmu.cpu_read(address);
+        self.tick();
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.mmu.cpu_write(address, value);
+        self.tick();
+    }
+
+    /// Like `register` but (HL) is read on its own machine cycle
+    fn read_operand(&mut self, register: Register) -> u8 {
+        match register {
+            Register::mHL => self.read(self.registers.hl()),
+            _ => self.register(register),
+        }
+    }
+
+    /// Like `set_register` but (HL) is written on its own machine cycle
+    fn write_operand(&mut self, register: Register, value: u8) {
+        match register {
+            Register::mHL => self.write(self.registers.hl(), value),
+            _ => self.set_register(register, value),
+        }
+    }
+
     fn register16(&self, register: Register16) -> u16 {
         use Register16::*;
         match register {
@@ -105,7 +274,7 @@ impl Core {
             HLIncrement => self.registers.hl_increment(),
             HLDecrement => self.registers.hl_decrement(),
         };
-        self.mmu.read(address)
+        self.read(address)
     }
 
     fn write_register16_mem(&mut self, register: Register16Mem, value: u8) {
@@ -116,7 +285,7 @@ impl Core {
             HLIncrement => self.registers.hl_increment(),
             HLDecrement => self.registers.hl_decrement(),
         };
-        self.mmu.write(address, value);
+        self.write(address, value);
     }
 
     fn register16_stack(&self, register: Register16Stack) -> u16 {
@@ -139,8 +308,95 @@ impl Core {
         }
     }
 
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    pub(crate) fn take_breakpoints(&mut self) -> BTreeSet<u16> {
+        std::mem::take(&mut self.breakpoints)
+    }
+
+    pub(crate) fn set_breakpoints(&mut self, breakpoints: BTreeSet<u16>) {
+        self.breakpoints = breakpoints;
+    }
+
+    /// The breakpoint that execution is currently stopped at
+    pub fn breakpoint_hit(&self) -> Option<u16> {
+        self.breakpoint_hit
+    }
+
+    /// Should be checked before each fetch, returns true if the next instruction is at a breakpoint.
+    /// Checking again at the same breakpoint resumes execution so that a debugger can step past it.
+    pub fn check_breakpoint(&mut self) -> bool {
+        if self.breakpoints.is_empty() || self.mode != CoreMode::Normal {
+            return false;
+        }
+        let pc = self.registers.pc;
+        if self.breakpoint_hit.take() == Some(pc) || !self.breakpoints.contains(&pc) {
+            return false;
+        }
+        self.breakpoint_hit = Some(pc);
+        true
+    }
+
+    /// Execute exactly one instruction, reporting if execution is now stopped before a breakpoint.
+    /// Stepping from a breakpoint executes the instruction at it.
+    pub fn step(&mut self) -> (OpCode, DebugEvent) {
+        let opcode = self.fetch();
+        self.execute(opcode);
+        let event = if self.check_breakpoint() {
+            DebugEvent::BreakpointHit(self.registers.pc)
+        } else {
+            DebugEvent::Step
+        };
+        (opcode, event)
+    }
+
+    /// Call `callback` with the CPU state before each instruction is fetched, e.g. to diff against a known-good log
+    pub fn set_trace_fn(&mut self, callback: TraceFn) {
+        self.tracer = Tracer::new(callback);
+    }
+
+    pub fn clear_trace_fn(&mut self) {
+        self.tracer = Tracer::default();
+    }
+
+    pub(crate) fn take_tracer(&mut self) -> Tracer {
+        std::mem::take(&mut self.tracer)
+    }
+
+    pub(crate) fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = tracer;
+    }
+
+    fn trace_entry(&self) -> TraceEntry {
+        let registers = self.registers();
+        let pc = registers.pc;
+        TraceEntry {
+            a: registers.a,
+            f: registers.f,
+            b: registers.b,
+            c: registers.c,
+            d: registers.d,
+            e: registers.e,
+            h: registers.h,
+            l: registers.l,
+            sp: registers.sp,
+            pc,
+            pc_mem: std::array::from_fn(|offset| self.mmu.read(pc.wrapping_add(offset as u16))),
+        }
+    }
+
     pub fn fetch(&mut self) -> OpCode {
         if self.mode == CoreMode::Normal {
+            if self.tracer.is_enabled() {
+                let entry = self.trace_entry();
+                self.tracer.trace(&entry);
+            }
             OpCode::parse(self)
         } else {
             // execute a "virtual" nop if not in normal mode
@@ -158,10 +414,11 @@ impl Core {
         let mut condition_met = false;
         match opcode {
             OpCode::Load { source, destination } => {
-                self.set_register(destination, self.register(source));
+                let value = self.read_operand(source);
+                self.write_operand(destination, value);
             }
             OpCode::LoadImmediate { register, value } => {
-                self.set_register(register, value);
+                self.write_operand(register, value);
             }
             OpCode::LoadIndirectAccumulator { register } => {
                 self.write_register16_mem(register, self.registers.a);
@@ -170,32 +427,34 @@ impl Core {
                 self.registers.a = self.register16_mem(register);
             }
             OpCode::LoadAccumulatorDirect { address } => {
-                self.registers.a = self.mmu.read(address);
+                self.registers.a = self.read(address);
             }
             OpCode::LoadDirectAccumulator { address } => {
-                self.mmu.write(address, self.registers.a);
+                self.write(address, self.registers.a);
             }
             OpCode::LoadHighAccumulatorIndirect => {
                 let address = 0xFF00 | (self.registers.c as u16);
-                self.registers.a = self.mmu.read(address);
+                self.registers.a = self.read(address);
             }
             OpCode::LoadHighIndirectAccumulator => {
                 let address = 0xFF00 | (self.registers.c as u16);
-                self.mmu.write(address, self.registers.a);
+                self.write(address, self.registers.a);
             }
             OpCode::LoadHighDirectAccumulator { lsb } => {
                 let address = 0xFF00 | (lsb as u16);
-                self.mmu.write(address, self.registers.a);
+                self.write(address, self.registers.a);
             }
             OpCode::LoadHighAccumulatorDirect { lsb } => {
                 let address = 0xFF00 | (lsb as u16);
-                self.registers.a = self.mmu.read(address);
+                self.registers.a = self.read(address);
             }
             OpCode::Load16Immediate { register, value } => {
                 self.write_register16(register, value);
             }
             OpCode::LoadDirectStackPointer { address } => {
-                self.mmu.write_u16_le(address, self.registers.sp);
+                let [low, high] = self.registers.sp.to_le_bytes();
+                self.write(address, low);
+                self.write(address.wrapping_add(1), high);
             }
             OpCode::LoadStackPointerHL => {
                 self.registers.sp = self.registers.hl();
@@ -212,61 +471,66 @@ impl Core {
                 self.registers.set_hl(adjusted_sp);
             }
             OpCode::Add { register } => {
-                self.registers.a = self.alu_add(self.register(register), false);
+                let value = self.read_operand(register);
+                self.registers.a = self.alu_add(value, false);
             }
             OpCode::AddImmediate { value } => {
                 self.registers.a = self.alu_add(value, false);
             }
             OpCode::AddWithCarry { register } => {
-                self.registers.a = self.alu_add(self.register(register), true);
+                let value = self.read_operand(register);
+                self.registers.a = self.alu_add(value, true);
             }
             OpCode::AddWithCarryImmediate { value } => {
                 self.registers.a = self.alu_add(value, true);
             }
             OpCode::Subtract { register } => {
-                self.registers.a = self.alu_subtract(self.register(register), false);
+                let value = self.read_operand(register);
+                self.registers.a = self.alu_subtract(value, false);
             }
             OpCode::SubtractImmediate { value } => {
                 self.registers.a = self.alu_subtract(value, false);
             }
             OpCode::SubtractWithCarry { register } => {
-                self.registers.a = self.alu_subtract(self.register(register), true);
+                let value = self.read_operand(register);
+                self.registers.a = self.alu_subtract(value, true);
             }
             OpCode::SubtractWithCarryImmediate { value } => {
                 self.registers.a = self.alu_subtract(value, true);
             }
             OpCode::Compare { register } => {
-                self.alu_subtract(self.register(register), false);
+                let value = self.read_operand(register);
+                self.alu_subtract(value, false);
             }
             OpCode::CompareImmediate { value } => {
                 self.alu_subtract(value, false);
             }
             OpCode::Increment { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = self.alu_increment(value);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::Decrement { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = self.alu_decrement(value);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::And { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 self.registers.a = self.alu_and(value);
             }
             OpCode::AndImmediate { value } => {
                 self.registers.a = self.alu_and(value);
             }
             OpCode::Or { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 self.registers.a = self.alu_or(value);
             }
             OpCode::OrImmediate { value } => {
                 self.registers.a = self.alu_or(value);
             }
             OpCode::Xor { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 self.registers.a = self.alu_xor(value);
             }
             OpCode::XorImmediate { value } => {
@@ -310,12 +574,14 @@ impl Core {
                 let value = self.register16(register);
                 let result = value.wrapping_add(1);
                 self.write_register16(register, result);
+                self.mmu.ppu_mut().trigger_oam_bug(value);
                 // no flags are set
             }
             OpCode::Decrement16 { register } => {
                 let value = self.register16(register);
                 let result = value.wrapping_sub(1);
                 self.write_register16(register, result);
+                self.mmu.ppu_mut().trigger_oam_bug(value);
                 // no flags are set
             }
             OpCode::Add16 { register } => {
@@ -344,42 +610,42 @@ impl Core {
                 self.registers.a = self.alu_rotate_right(self.registers.a, false, false);
             }
             OpCode::RotateLeftCircular { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = self.alu_rotate_left(value, true, true);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::RotateRightCircular { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = self.alu_rotate_right(value, true, true);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::RotateLeft { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = self.alu_rotate_left(value, false, true);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::RotateRight { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = self.alu_rotate_right(value, false, true);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::ShiftLeftArithmetic { register } => {
                 self.registers.flags.c = false; // hack change rotate to shift
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = self.alu_rotate_left(value, false, true);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::ShiftRightArithmetic { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = self.alu_shift_right_arithmetic(value);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::Swap { register } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let high_nibble = (value & 0xF0) >> 4;
                 let low_nibble = (value & 0x0F) << 4;
                 let result = high_nibble | low_nibble;
-                self.set_register(register, result);
+                self.write_operand(register, result);
                 self.registers.flags.z = result == 0;
                 self.registers.flags.n = false;
                 self.registers.flags.h = false;
@@ -387,26 +653,26 @@ impl Core {
             }
             OpCode::ShiftRightLogical { register } => {
                 self.registers.flags.c = false;
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = self.alu_rotate_right(value, false, true);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::TestBit { register, bit } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let bit = (value >> bit) & 0x01;
                 self.registers.flags.z = bit == 0;
                 self.registers.flags.n = false;
                 self.registers.flags.h = true;
             }
             OpCode::ResetBit { register, bit } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = value & !(1 << bit);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::SetBit { register, bit } => {
-                let value = self.register(register);
+                let value = self.read_operand(register);
                 let result = value | (1 << bit);
-                self.set_register(register, result);
+                self.write_operand(register, result);
             }
             OpCode::Jump { address } => {
                 self.registers.pc = address;
@@ -442,6 +708,7 @@ impl Core {
                 self.registers.pc = self.pop_stack();
             }
             OpCode::ReturnConditional { condition } => {
+                self.tick(); // the condition is checked on its own machine cycle
                 if self.condition_met(condition) {
                     condition_met = true;
                     self.registers.pc = self.pop_stack();
@@ -449,17 +716,27 @@ impl Core {
             }
             OpCode::ReturnInterrupt => {
                 self.registers.pc = self.pop_stack();
-                self.interrupts_enabled_on_next_instruction = true;
+                // unlike EI, IME is enabled immediately so a pending interrupt is serviced before the next instruction
+                self.interrupts_enabled = true;
             }
             OpCode::Restart { lsb } => {
                 self.call(lsb as u16);
             }
             OpCode::Halt => {
-                self.mode = CoreMode::Halt;
+                if !self.interrupts_enabled && self.mmu.interrupt_pending().is_some() {
+                    // halt bug, the CPU does not halt & the byte after HALT is read twice
+                    // https://gbdev.io/pandocs/halt.html#halt-bug
+                    self.halt_bug = true;
+                } else {
+                    self.mode = CoreMode::Halt;
+                }
             }
             OpCode::Stop => {
-                self.mode = CoreMode::Stop;
-                self.mmu.stop();
+                // an armed CGB speed switch is performed instead of stopping
+                if !self.mmu.switch_speed() {
+                    self.mode = CoreMode::Stop;
+                    self.mmu.stop();
+                }
             }
             OpCode::Nop => {}
             OpCode::DisableInterrupts => {
@@ -468,25 +745,29 @@ impl Core {
             OpCode::EnableInterrupts => {
                 self.interrupts_enabled_on_next_instruction = true;
             }
-            OpCode::Illegal { .. } => {
+            OpCode::Illegal { raw } => {
                 println!("Illegal opcode encountered: {:?}", opcode);
                 self.mode = CoreMode::Crash;
+                // illegal opcodes are a single byte, so the program counter has already moved past it
+                self.last_error = Some(CrashReason::IllegalOpCode { raw, address: self.registers.pc.wrapping_sub(1) });
                 self.mmu.stop();
             }
         }
 
         let cycles = MachineCycles::from_m(opcode.machine_cycles(condition_met));
 
+        debug_assert!(self.ticked_cycles <= cycles, "{:?} ticked more than {:?}", opcode, cycles);
         let interrupt_cycles = match self.mode {
             CoreMode::Normal | CoreMode::Halt => {
-                self.mmu.update(cycles);
+                self.mmu.update(cycles - std::mem::take(&mut self.ticked_cycles));
                 self.interrupt()
             }
             CoreMode::Stop => {
                 // do not run timers in stop mode
                 if self.mmu.joypad().is_activation_pending() {
-                    // stop is interrupted by any joypad input
+                    // stop is interrupted by any joypad input, other interrupts are ignored
                     self.mode = CoreMode::Normal;
+                    self.mmu.restart();
                 }
                 MachineCycles::ZERO
             }
@@ -496,9 +777,11 @@ impl Core {
             }
         };
 
-        self.mmu.update(interrupt_cycles);
+        self.mmu.update(interrupt_cycles - std::mem::take(&mut self.ticked_cycles));
 
-        cycles + interrupt_cycles
+        let total_cycles = cycles + interrupt_cycles;
+        self.machine_cycles += total_cycles.m_cycles() as u64;
+        total_cycles
     }
 
     fn interrupt(&mut self) -> MachineCycles {
@@ -514,7 +797,9 @@ impl Core {
 
             debug_assert!(self.interrupts_enabled, "Interrupts are not enabled");
             self.mmu.clear_interrupt_request(interrupt);
+            self.last_interrupt = Some(interrupt);
             self.interrupts_enabled = false;
+            self.tick(); // 2 wait cycles, the second is part of the push
             self.call(interrupt.address());
             MachineCycles::from_m(5)
         } else {
@@ -527,17 +812,19 @@ impl Core {
         self.registers.pc = address;
     }
 
+    /// A push is an internal machine cycle followed by writing the high then low byte
     fn push_stack(&mut self, value: u16) {
+        self.tick();
         self.registers.sp = self.registers.sp.wrapping_sub(1);
-        self.mmu.write(self.registers.sp, (value >> 8) as u8);
+        self.write(self.registers.sp, (value >> 8) as u8);
         self.registers.sp = self.registers.sp.wrapping_sub(1);
-        self.mmu.write(self.registers.sp, (value & 0xFF) as u8);
+        self.write(self.registers.sp, (value & 0xFF) as u8);
     }
 
     fn pop_stack(&mut self) -> u16 {
-        let low = self.mmu.read(self.registers.sp);
+        let low = self.read(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(1);
-        let high = self.mmu.read(self.registers.sp);
+        let high = self.read(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(1);
         u16::from_le_bytes([low, high])
     }
@@ -677,13 +964,79 @@ pub trait Fetch {
 }
 
 impl Fetch for Core {
+    /// Each byte of an instruction is fetched on its own machine cycle, which is counted by the following `execute`
     fn fetch_u8(&mut self) -> u8 {
-        let opcode = self.mmu.read(self.registers.pc);
-        self.registers.pc = self.registers.pc.wrapping_add(1);
+        let opcode = self.mmu.cpu_read(self.registers.pc);
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            self.registers.pc = self.registers.pc.wrapping_add(1);
+        }
+        self.tick();
         opcode
     }
 }
 
+impl Encode for Core {
+    fn encode<__E: Encoder>(&self, encoder: &mut __E) -> Result<(), bincode::error::EncodeError> {
+        // the tracer & breakpoints are not encoded, they are debugging state set by the front end
+        Encode::encode(&self.registers, encoder)?;
+        Encode::encode(&self.boot_registers, encoder)?;
+        Encode::encode(&self.mmu, encoder)?;
+        Encode::encode(&self.interrupts_enabled, encoder)?;
+        Encode::encode(&self.interrupts_enabled_on_next_instruction, encoder)?;
+        Encode::encode(&self.mode, encoder)?;
+        Encode::encode(&self.last_error, encoder)?;
+        Encode::encode(&self.last_interrupt, encoder)?;
+        Encode::encode(&self.machine_cycles, encoder)?;
+        Encode::encode(&self.ticked_cycles, encoder)?;
+        Encode::encode(&self.halt_bug, encoder)?;
+        Ok(())
+    }
+}
+
+impl<__Context> Decode<__Context> for Core {
+    fn decode<__D: Decoder<Context=__Context>>(decoder: &mut __D) -> Result<Self, bincode::error::DecodeError> {
+        Ok(Self {
+            registers: Decode::decode(decoder)?,
+            boot_registers: Decode::decode(decoder)?,
+            mmu: Decode::decode(decoder)?,
+            interrupts_enabled: Decode::decode(decoder)?,
+            interrupts_enabled_on_next_instruction: Decode::decode(decoder)?,
+            mode: Decode::decode(decoder)?,
+            last_error: Decode::decode(decoder)?,
+            last_interrupt: Decode::decode(decoder)?,
+            machine_cycles: Decode::decode(decoder)?,
+            ticked_cycles: Decode::decode(decoder)?,
+            halt_bug: Decode::decode(decoder)?,
+            breakpoints: BTreeSet::new(),
+            breakpoint_hit: None,
+            tracer: Tracer::default(),
+        })
+    }
+}
+
+impl<'__de, __Context> BorrowDecode<'__de, __Context> for Core {
+    fn borrow_decode<__D: BorrowDecoder<'__de, Context=__Context>>(decoder: &mut __D) -> Result<Self, bincode::error::DecodeError> {
+        Ok(Self {
+            registers: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            boot_registers: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            mmu: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            interrupts_enabled: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            interrupts_enabled_on_next_instruction: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            mode: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            last_error: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            last_interrupt: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            machine_cycles: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            ticked_cycles: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            halt_bug: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            breakpoints: BTreeSet::new(),
+            breakpoint_hit: None,
+            tracer: Tracer::default(),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1341,6 +1694,64 @@ mod tests {
             assert!(core.registers.flags.h);
         }
 
+        #[test]
+        fn increment_hl_read_modify_write() {
+            let mut core = Core::dmg_hello_world();
+            core.mmu.ppu_mut().set_lcd_control(0x00);
+            core.mmu.ppu_mut().set_lcd_control(0x80); // restart the LCD in OAM scan, VRAM is accessible
+            core.mmu.vram_mut()[0] = 0x41;
+            core.registers.set_hl(0x8000);
+
+            // mid OAM scan, the read & write both see VRAM
+            core.mmu.ppu_mut().update(MachineCycles::from_m(5));
+            let cycles = core.execute(OpCode::Increment { register: mHL });
+            assert_eq!(cycles, MachineCycles::from_m(3));
+            assert_eq!(core.mmu.vram()[0], 0x42);
+
+            // one machine cycle before drawing, the PPU locks VRAM between the read & the write
+            core.mmu.ppu_mut().set_lcd_control(0x00);
+            core.mmu.ppu_mut().set_lcd_control(0x80);
+            core.mmu.ppu_mut().update(MachineCycles::from_m(19));
+            core.execute(OpCode::Increment { register: mHL });
+            assert_eq!(core.mmu.ppu().lcd_status().mode(), crate::lcd_status::LcdMode::Drawing);
+            assert_eq!(core.mmu.vram()[0], 0x42); // write was dropped
+        }
+
+        #[test]
+        fn memory_access_sees_hardware_at_its_machine_cycle() {
+            let mut core = Core::dmg_hello_world();
+            core.mmu.vram_mut()[0] = 0x42;
+            core.mmu.write(0xC000, 0x7E); // LD A,(HL)
+            core.registers.set_hl(0x8000);
+
+            // the read is on the second machine cycle, after the fetch
+            for (ppu_cycles, expected) in [(18, 0x42), (19, 0xFF)] {
+                core.mmu.ppu_mut().set_lcd_control(0x00);
+                core.mmu.ppu_mut().set_lcd_control(0x80); // restart the LCD in OAM scan, VRAM is accessible
+                core.mmu.ppu_mut().update(MachineCycles::from_m(ppu_cycles));
+                core.registers.pc = 0xC000;
+                core.registers.a = 0x00;
+                let (opcode, _) = core.step();
+                assert_eq!(opcode, OpCode::Load { source: mHL, destination: A });
+                assert_eq!(core.registers.a, expected);
+            }
+
+            // the rest of the hardware advances by exactly the instruction's machine cycles
+            for program in [[0x7E, 0x00, 0x00], [0xCD, 0x00, 0xC1], [0xCB, 0x46, 0x00], [0xC0, 0x00, 0x00]] {
+                for (offset, byte) in program.into_iter().enumerate() {
+                    core.mmu.write(0xC000 + offset as u16, byte);
+                }
+                core.registers.pc = 0xC000;
+                core.registers.sp = 0xDFFC;
+                core.mmu.write_u16_le(0xDFFC, 0xC000);
+                core.registers.flags.z = false;
+                let counter = core.mmu.divider().counter();
+                let (opcode, _) = core.step();
+                let expected = MachineCycles::from_m(opcode.machine_cycles(true));
+                assert_eq!(core.mmu.divider().counter().wrapping_sub(counter) as usize, expected.t_cycles(), "{}", opcode);
+            }
+        }
+
         #[test]
         fn and() {
             let mut core = Core::dmg_hello_world();
@@ -1487,6 +1898,39 @@ mod tests {
             assert_eq!(core.registers.sp, 0x0000); // wrap around
         }
 
+        #[test]
+        fn oam_bug() {
+            let mut core = Core::dmg_hello_world();
+            let mut pattern: Vec<u8> = (0..0xA0).collect();
+            pattern[0x08..0x0A].copy_from_slice(&0x00FFu16.to_le_bytes()); // b
+            pattern[0x0C..0x0E].copy_from_slice(&0x0F0Fu16.to_le_bytes()); // c
+            pattern[0x10..0x12].copy_from_slice(&0x1234u16.to_le_bytes()); // a
+            core.mmu.ppu_mut().set_lcd_control(0x00);
+            core.mmu.ppu_mut().set_lcd_control(0x80); // restart the LCD in OAM scan
+            core.mmu.ppu_mut().update(MachineCycles::from_m(2)); // scanning row 2
+            core.mmu.oam_mut().copy_from_slice(&pattern);
+
+            core.registers.sp = 0xFE10;
+            core.execute(OpCode::Increment16 { register: Register16::SP });
+            assert_eq!(core.registers.sp, 0xFE11);
+
+            // first word is ((a ^ c) & (b ^ c)) ^ c, the other three words are copied from the previous row
+            assert_eq!(&core.mmu.oam()[0x10..0x18], &[0x3F, 0x02, 0x0A, 0x0B, 0x0F, 0x0F, 0x0E, 0x0F]);
+            assert_eq!(&core.mmu.oam()[..0x10], &pattern[..0x10]);
+            assert_eq!(&core.mmu.oam()[0x18..], &pattern[0x18..]);
+
+            // not in range
+            core.mmu.oam_mut().copy_from_slice(&pattern);
+            core.registers.set_hl(0xFF00);
+            core.execute(OpCode::Decrement16 { register: Register16::HL });
+            assert_eq!(core.mmu.oam(), &pattern[..]);
+
+            // disabled
+            core.mmu.ppu_mut().set_oam_bug(false);
+            core.execute(OpCode::Decrement16 { register: Register16::SP });
+            assert_eq!(core.mmu.oam(), &pattern[..]);
+        }
+
         #[test]
         fn decrement16() {
             let mut core = Core::dmg_hello_world();
@@ -1519,6 +1963,52 @@ mod tests {
             assert!(core.registers.flags.c);
         }
 
+        /// HL + register, expected result, h, c
+        fn assert_add16(hl: u16, value: u16, expected: u16, h: bool, c: bool) {
+            let mut core = Core::dmg_hello_world();
+            core.registers.set_hl(hl);
+            core.registers.set_bc(value);
+            core.registers.flags.n = true;
+            core.execute(OpCode::Add16 { register: Register16::BC });
+            assert_eq!(core.registers.hl(), expected, "{:04X} + {:04X}", hl, value);
+            assert_eq!(core.registers.flags.h, h, "h flag for {:04X} + {:04X}", hl, value);
+            assert_eq!(core.registers.flags.c, c, "c flag for {:04X} + {:04X}", hl, value);
+            assert!(!core.registers.flags.n);
+        }
+
+        #[test]
+        fn add16_half_carry_from_bit_11() {
+            assert_add16(0x0FFF, 0x0001, 0x1000, true, false);
+            assert_add16(0x0800, 0x0800, 0x1000, true, false);
+            assert_add16(0x07FF, 0x0001, 0x0800, false, false); // carry out of bit 10 is not a half carry
+            assert_add16(0x00FF, 0x0001, 0x0100, false, false); // neither is a carry out of bit 7
+        }
+
+        #[test]
+        fn add16_carry_from_bit_15() {
+            assert_add16(0x8000, 0x8000, 0x0000, false, true);
+            assert_add16(0xFFFF, 0x0001, 0x0000, true, true);
+            assert_add16(0xF000, 0x1000, 0x0000, false, true);
+            assert_add16(0x7FFF, 0x7FFF, 0xFFFE, true, false);
+        }
+
+        #[test]
+        fn add16_preserves_zero_flag() {
+            let mut core = Core::dmg_hello_world();
+            core.registers.set_hl(0x8000);
+            core.registers.flags.z = false;
+            core.execute(OpCode::Add16 { register: Register16::HL });
+            assert_eq!(core.registers.hl(), 0x0000);
+            assert!(!core.registers.flags.z); // zero result does not set z
+
+            core.registers.set_hl(0x1234);
+            core.registers.sp = 0x0001;
+            core.registers.flags.z = true;
+            core.execute(OpCode::Add16 { register: Register16::SP });
+            assert_eq!(core.registers.hl(), 0x1235);
+            assert!(core.registers.flags.z);
+        }
+
         #[test]
         fn add_stack_pointer() {
             let mut core = Core::dmg_hello_world();
@@ -1826,6 +2316,23 @@ mod tests {
             assert_eq!(core.registers.pc, 0x0200);
         }
 
+        #[test]
+        fn conditional_cycles() {
+            let branches = [
+                (OpCode::JumpConditional { condition: JumpCondition::Zero, address: 0x0200 }, 4, 3),
+                (OpCode::JumpRelativeConditional { condition: JumpCondition::Zero, offset: 2 }, 3, 2),
+                (OpCode::CallConditional { condition: JumpCondition::Zero, address: 0x0200 }, 6, 3),
+                (OpCode::ReturnConditional { condition: JumpCondition::Zero }, 5, 2),
+            ];
+            for (opcode, taken, not_taken) in branches {
+                let mut core = Core::dmg_hello_world();
+                core.registers.flags.z = true;
+                assert_eq!(core.execute(opcode), MachineCycles::from_m(taken), "{} taken", opcode);
+                core.registers.flags.z = false;
+                assert_eq!(core.execute(opcode), MachineCycles::from_m(not_taken), "{} not taken", opcode);
+            }
+        }
+
         #[test]
         fn jump_hl() {
             let mut core = Core::dmg_hello_world();
@@ -2035,6 +2542,34 @@ mod tests {
             assert_eq!(core.registers.sp, 0xFFFC);
         }
 
+        #[test]
+        fn restart_vectors() {
+            for raw in [0xC7, 0xCF, 0xD7, 0xDF, 0xE7, 0xEF, 0xF7, 0xFF] {
+                let mut core = Core::dmg_hello_world();
+                core.mmu.write(0xC000, raw);
+                core.registers.pc = 0xC000;
+                let opcode = core.fetch();
+                core.execute(opcode);
+                assert_eq!(core.registers.pc, (raw & 0x38) as u16, "{:?}", opcode);
+                assert_eq!(core.registers.sp, 0xFFFC);
+                assert_eq!(core.mmu.read_u16_le(0xFFFC), 0xC001); // return address pushed before the jump
+            }
+        }
+
+        #[test]
+        fn return_interrupt_enables_immediately() {
+            let mut core = Core::dmg_hello_world();
+            core.execute(OpCode::Call { address: 0x0300 });
+            core.execute(OpCode::ReturnInterrupt);
+            assert!(core.interrupts_enabled);
+            assert!(!core.interrupts_enabled_on_next_instruction);
+
+            // compared with EI, which takes effect after the next instruction
+            core.execute(OpCode::DisableInterrupts);
+            core.execute(OpCode::EnableInterrupts);
+            assert!(!core.interrupts_enabled);
+        }
+
         #[test]
         fn nop() {
             let mut core = Core::dmg_hello_world();
@@ -2056,17 +2591,244 @@ mod tests {
             assert_eq!(core.mode, CoreMode::Normal);
         }
 
+        #[test]
+        fn halt_wakes_without_interrupt_master_enable() {
+            let mut core = Core::dmg_hello_world();
+            core.execute(OpCode::Halt);
+            assert_eq!(core.mode, CoreMode::Halt);
+
+            // pending interrupts wake it up even when IME is disabled
+            assert!(!core.interrupts_enabled);
+            core.mmu.write(0xFFFF, 0xFF); // enable all interrupts
+            core.mmu.write(0xFF0F, 0xFF); // request all interrupts
+            core.execute(OpCode::Nop); // update core state
+            assert_eq!(core.mode, CoreMode::Normal);
+
+            // but the interrupt handler is not called
+            assert_eq!(core.registers.pc, 0x0100);
+            assert_eq!(core.registers.sp, 0xFFFE);
+            assert_eq!(core.mmu.read(0xFF0F) & 0x1F, 0x1F); // interrupts are still requested
+        }
+
+        #[test]
+        fn halt_bug() {
+            let mut core = Core::dmg_hello_world();
+            core.mmu.wram_mut()[..3].copy_from_slice(&[0x76, 0x3C, 0x00]); // HALT, INC A, NOP
+            core.registers.pc = 0xC000;
+            core.registers.a = 0;
+            core.mmu.write(0xFFFF, 0x01);
+            core.mmu.write(0xFF0F, 0x01); // vblank already pending with IME disabled
+
+            let opcode = core.fetch();
+            core.execute(opcode);
+            assert_eq!(core.mode, CoreMode::Normal); // does not halt
+
+            // INC A is fetched twice as PC fails to increment
+            for pc in [0xC001, 0xC002] {
+                let opcode = core.fetch();
+                assert_eq!(opcode, OpCode::Increment { register: Register::A });
+                assert_eq!(core.registers.pc, pc);
+                core.execute(opcode);
+            }
+            assert_eq!(core.registers.a, 2);
+            assert_eq!(core.fetch(), OpCode::Nop);
+        }
+
         #[test]
         fn stop() {
             let mut core = Core::dmg_hello_world();
             assert_eq!(core.mode, CoreMode::Normal);
+            for _ in 0..100 {
+                core.execute(OpCode::Nop);
+            }
+            assert_eq!(core.mmu.read(0xFF04), 1);
+
             core.execute(OpCode::Stop);
             assert_eq!(core.mode, CoreMode::Stop);
+            assert_eq!(core.mmu.read(0xFF04), 0); // DIV is reset on entry
+
+            // other interrupts do not wake it and the divider does not run
+            core.mmu.write(0xFFFF, 0xFF); // enable all interrupts
+            core.mmu.write(0xFF0F, 0x0F); // request all but joypad
+            for _ in 0..100 {
+                core.execute(OpCode::Nop);
+            }
+            assert_eq!(core.mode, CoreMode::Stop);
+            assert_eq!(core.mmu.read(0xFF04), 0);
 
             // joypad input wakes it up
+            core.mmu.write(0xFF00, 0x10); // select buttons
             core.mmu.joypad_mut().press_button(JoypadButton::A);
             core.execute(OpCode::Nop); // update core state
             assert_eq!(core.mode, CoreMode::Normal);
+
+            // and the divider restarts from zero
+            for _ in 0..64 {
+                core.execute(OpCode::Nop);
+            }
+            assert_eq!(core.mmu.read(0xFF04), 1);
+        }
+
+        #[test]
+        fn stop_switches_speed() {
+            let mut rom = crate::roms::acid::ROM.to_vec();
+            rom[0x0143] = 0x80; // CGB enhanced
+            let mut core = Core::cgb(&rom);
+            core.mmu.write(0xFF4D, 0x01); // arm the speed switch
+            assert_eq!(core.mmu.read(0xFF4D), 0x7F);
+
+            core.execute(OpCode::Stop);
+            assert_eq!(core.mode, CoreMode::Normal); // switched rather than stopped
+            assert!(core.is_double_speed());
+            assert_eq!(core.mmu.read(0xFF4D), 0xFE);
+
+            // not armed, so stops as normal
+            core.execute(OpCode::Stop);
+            assert_eq!(core.mode, CoreMode::Stop);
+            assert!(core.is_double_speed());
+        }
+    }
+
+    mod trace {
+        use super::*;
+
+        #[test]
+        fn trace() {
+            let entries = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let mut core = Core::dmg_hello_world();
+            let traced = entries.clone();
+            core.set_trace_fn(Box::new(move |entry| traced.borrow_mut().push(entry.to_string())));
+
+            // entry point is NOP; JP $0150
+            core.step();
+            core.step();
+            let entries = entries.borrow().clone();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0], "A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01");
+            assert!(entries[1].ends_with("PC:0101 PCMEM:C3,50,01,CE"), "{}", entries[1]);
+
+            // the tracer is not carried over to a clone
+            assert!(!core.clone().tracer.is_enabled());
+            core.clear_trace_fn();
+            assert!(!core.tracer.is_enabled());
+        }
+    }
+
+    mod breakpoints {
+        use super::*;
+
+        #[test]
+        fn breakpoint() {
+            let mut core = Core::dmg_hello_world();
+            assert!(!core.check_breakpoint()); // no breakpoints
+
+            core.add_breakpoint(0x0100);
+            assert!(core.check_breakpoint());
+            assert_eq!(core.breakpoint_hit(), Some(0x0100));
+
+            // checking again resumes
+            assert!(!core.check_breakpoint());
+            assert_eq!(core.breakpoint_hit(), None);
+
+            core.remove_breakpoint(0x0100);
+            core.registers.pc = 0x0100;
+            assert!(!core.check_breakpoint());
+        }
+
+        #[test]
+        fn step() {
+            let mut core = Core::dmg_hello_world();
+            core.add_breakpoint(0x0150);
+
+            // entry point is NOP; JP $0150
+            assert_eq!(core.step(), (OpCode::Nop, DebugEvent::Step));
+            assert_eq!(core.registers.pc, 0x0101);
+            assert_eq!(core.step(), (OpCode::Jump { address: 0x0150 }, DebugEvent::BreakpointHit(0x0150)));
+            assert_eq!(core.breakpoint_hit(), Some(0x0150));
+            assert_eq!(core.registers.pc, 0x0150);
+
+            // stepping from the breakpoint executes it
+            let expected = crate::disassembler::disassemble(&core.mmu, 0x0150, 1)[0].1;
+            let (opcode, event) = core.step();
+            assert_ne!(core.registers.pc, 0x0150);
+            assert_eq!(event, DebugEvent::Step);
+            assert_eq!(core.breakpoint_hit(), None);
+            assert_eq!(opcode, expected);
+        }
+    }
+
+    mod crash {
+        use super::*;
+
+        #[test]
+        fn illegal_opcode() {
+            let mut core = Core::dmg_hello_world();
+            assert_eq!(core.mode(), CoreMode::Normal);
+            assert_eq!(core.last_error(), None);
+
+            core.registers.pc = 0x0151; // as if the opcode was fetched from 0x0150
+            core.execute(OpCode::Illegal { raw: 0xD3 });
+            assert_eq!(core.mode(), CoreMode::Crash);
+            assert_eq!(core.last_error(), Some(CrashReason::IllegalOpCode { raw: 0xD3, address: 0x0150 }));
+
+            // the core stays crashed
+            let opcode = core.fetch();
+            core.execute(opcode);
+            assert_eq!(core.mode(), CoreMode::Crash);
+            assert_eq!(core.registers.pc, 0x0151);
+        }
+
+        #[test]
+        fn recover() {
+            let mut core = Core::dmg_hello_world();
+            assert_eq!(core.recover(), None);
+
+            core.execute(OpCode::Illegal { raw: 0xFC });
+            assert_eq!(core.recover(), Some(CrashReason::IllegalOpCode { raw: 0xFC, address: 0x00FF }));
+            assert_eq!(core.mode(), CoreMode::Normal);
+            assert_eq!(core.last_error(), None);
+        }
+    }
+
+    mod registers {
+        use super::*;
+
+        #[test]
+        fn cgb_flag_selects_register_init() {
+            // cpu_instrs is flagged as CGB enhanced, dmg-acid2 is DMG only
+            assert_eq!(Core::from_rom(crate::roms::blargg_cpu::ROM).unwrap().registers().a, 0x11);
+            assert_eq!(Core::from_rom(crate::roms::acid::ROM).unwrap().registers().a, 0x01);
+            assert_eq!(Core::cgb(crate::roms::acid::ROM).registers().a, 0x11);
+            assert_eq!(Core::dmg(crate::roms::blargg_cpu::ROM).registers().a, 0x01);
+        }
+
+        #[test]
+        fn snapshot_round_trip() {
+            let mut core = Core::dmg_hello_world();
+            let snapshot = core.registers();
+            assert_eq!(snapshot.f, 0x80);
+            assert_eq!(snapshot.pc, 0x0100);
+
+            core.set_registers(RegisterSnapshot {
+                a: 0x12,
+                f: 0xFF,
+                b: 0x34,
+                c: 0x56,
+                d: 0x78,
+                e: 0x9A,
+                h: 0xBC,
+                l: 0xDE,
+                sp: 0xC000,
+                pc: 0x0150,
+            });
+            let snapshot = core.registers();
+            assert_eq!(snapshot.f, 0xF0);
+            assert_eq!(snapshot.flags(), crate::registers::FlagsRegister { z: true, n: true, h: true, c: true });
+            assert_eq!(snapshot.a, 0x12);
+            assert_eq!(core.registers.hl(), 0xBCDE);
+            assert_eq!(core.registers.af(), 0x12F0);
+            assert_eq!(snapshot.sp, 0xC000);
+            assert_eq!(snapshot.pc, 0x0150);
         }
     }
 
@@ -2089,6 +2851,34 @@ mod tests {
             assert!(!core.interrupts_enabled);
         }
 
+        #[test]
+        fn enable_interrupts_is_delayed() {
+            let mut core = Core::dmg_hello_world();
+            core.mmu.write(0xFFFF, 0xFF); // enable all interrupts
+            core.mmu.request_interrupt(Interrupt::VBlank);
+
+            // the pending interrupt is not serviced straight after EI
+            core.execute(OpCode::EnableInterrupts);
+            assert_eq!(core.registers.pc, 0x0100);
+            assert_eq!(core.mmu.interrupt_pending(), Some(Interrupt::VBlank));
+
+            // but after the next instruction, so the return address is after it
+            core.execute(OpCode::LoadImmediate { register: Register::A, value: 0x42 });
+            assert_eq!(core.registers.a, 0x42);
+            assert_eq!(core.registers.pc, Interrupt::VBlank.address());
+            assert_eq!(core.mmu.read_u16_le(core.registers.sp), 0x0100);
+
+            // EI immediately followed by DI never enables interrupts
+            let mut core = Core::dmg_hello_world();
+            core.mmu.write(0xFFFF, 0xFF);
+            core.mmu.request_interrupt(Interrupt::VBlank);
+            core.execute(OpCode::EnableInterrupts);
+            core.execute(OpCode::DisableInterrupts);
+            core.execute(OpCode::Nop);
+            assert_eq!(core.registers.pc, 0x0100);
+            assert!(!core.interrupts_enabled);
+        }
+
         #[test]
         fn handle_interrupts_does_nothing_when_interrupt_master_disabled() {
             let mut core = Core::dmg_hello_world();
@@ -2106,27 +2896,65 @@ mod tests {
             core.mmu.write(0xFFFF, 0xFF); // enable all interrupts
             core.mmu.write(0xFF0F, 0xFF); // request all interrupts
 
-            // run all interrupts in sequence
+            // run all interrupts in sequence, RETI re-enables IME immediately so each
+            // remaining interrupt is serviced straight after returning from the last
+            core.execute(OpCode::Nop);
             let expected_interrupts = [0x0040, 0x0048, 0x0050, 0x0058, 0x0060];
             for expected_address in expected_interrupts {
-                core.execute(OpCode::Nop);
-
                 assert_eq!(core.registers.pc, expected_address);
                 assert!(!core.interrupts_enabled);
                 assert_eq!(core.registers.sp, 0xFFFC); // stack pointer decremented twice
                 assert_eq!(core.mmu.read_u16_le(0xFFFC), 0x0100); // PC pushed onto stack
 
                 core.execute(OpCode::ReturnInterrupt);
-                assert_eq!(core.registers.pc, 0x0100); // PC restored from stack
-                assert_eq!(core.registers.sp, 0xFFFE); // stack pointer incremented twice
 
                 println!("Handled interrupt at address: {:#04X}", expected_address);
             }
+            assert_eq!(core.registers.pc, 0x0100); // PC restored from stack
+            assert_eq!(core.registers.sp, 0xFFFE); // stack pointer incremented twice
+            assert!(core.interrupts_enabled);
 
             // after that there should be no more interrupts
             core.execute(OpCode::Nop);
             assert_eq!(core.registers.pc, 0x0100); // PC should not change
         }
+
+        #[test]
+        fn machine_cycles() {
+            let mut core = Core::dmg_hello_world();
+            let opcodes = [
+                OpCode::Nop,
+                OpCode::EnableInterrupts,
+                OpCode::Call { address: 0x0200 },
+                OpCode::Return,
+                OpCode::JumpConditional { condition: JumpCondition::Carry, address: 0x0300 },
+            ];
+            let mut expected = 0;
+            for opcode in opcodes {
+                expected += core.execute(opcode).m_cycles() as u64;
+            }
+            // z is set & carry clear in the DMG boot state
+            assert_eq!(expected, 1 + 1 + 6 + 4 + 3);
+            assert_eq!(core.machine_cycles(), expected);
+
+            core.mmu.write(0xFFFF, 0xFF); // enable all interrupts
+            core.mmu.request_interrupt(Interrupt::VBlank);
+            core.execute(OpCode::Nop);
+            assert_eq!(core.machine_cycles(), expected + 1 + 5); // NOP + interrupt dispatch
+        }
+
+        #[test]
+        fn last_interrupt() {
+            let mut core = Core::dmg_hello_world();
+            core.execute(OpCode::EnableInterrupts);
+            assert_eq!(core.last_interrupt(), None);
+
+            core.mmu.write(0xFFFF, 0xFF); // enable all interrupts
+            core.mmu.request_interrupt(Interrupt::Serial);
+            core.execute(OpCode::Nop);
+            assert_eq!(core.last_interrupt(), Some(Interrupt::Serial));
+            assert_eq!(core.registers.pc, Interrupt::Serial.address());
+        }
     }
 
     #[test]
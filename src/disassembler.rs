@@ -0,0 +1,72 @@
+use crate::core::Fetch;
+use crate::mmu::MMU;
+use crate::opcode::OpCode;
+
+/// Reads instruction bytes straight from memory, leaving the CPU untouched
+struct MemoryFetch<'a> {
+    mmu: &'a MMU,
+    address: u32, // wider than the address space so that running off the end can be detected
+}
+
+impl Fetch for MemoryFetch<'_> {
+    fn fetch_u8(&mut self) -> u8 {
+        let value = u16::try_from(self.address).map_or(0x00, |address| self.mmu.read(address));
+        self.address += 1;
+        value
+    }
+}
+
+/// Decodes up to `count` instructions from `start` into their address, opcode & length in bytes.
+/// Stops early rather than wrapping around if an instruction would run past 0xFFFF.
+pub fn disassemble(mmu: &MMU, start: u16, count: usize) -> Vec<(u16, OpCode, u8)> {
+    let mut fetch = MemoryFetch { mmu, address: start as u32 };
+    let mut instructions = Vec::with_capacity(count);
+    while instructions.len() < count && fetch.address <= u16::MAX as u32 {
+        let address = fetch.address;
+        let opcode = OpCode::parse(&mut fetch);
+        if fetch.address > u16::MAX as u32 + 1 {
+            break; // truncated by the end of the address space
+        }
+        instructions.push((address as u16, opcode, (fetch.address - address) as u8));
+    }
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcode::{Register, Register16};
+
+    #[test]
+    fn disassemble_work_ram() {
+        let mut mmu = MMU::from_rom(crate::roms::blargg_cpu::ROM).unwrap();
+        // LD HL,$1234; CB SET 7,A; LDH ($40),A; NOP
+        mmu.wram_mut()[..8].copy_from_slice(&[0x21, 0x34, 0x12, 0xCB, 0xFF, 0xE0, 0x40, 0x00]);
+
+        let instructions = disassemble(&mmu, 0xC000, 3);
+        assert_eq!(instructions, vec![
+            (0xC000, OpCode::Load16Immediate { register: Register16::HL, value: 0x1234 }, 3),
+            (0xC003, OpCode::SetBit { bit: 7, register: Register::A }, 2),
+            (0xC005, OpCode::LoadHighDirectAccumulator { lsb: 0x40 }, 2),
+        ]);
+        assert_eq!(instructions[0].1.to_string(), "LD HL, 0x1234");
+    }
+
+    #[test]
+    fn stops_at_end_of_address_space() {
+        let mut mmu = MMU::from_rom(crate::roms::blargg_cpu::ROM).unwrap();
+        mmu.hram_mut()[0x7C..].copy_from_slice(&[0x00, 0x01, 0x00]); // 0xFFFC NOP, 0xFFFD LD BC,nn
+        mmu.write(0xFFFF, 0x00);
+
+        // an instruction ending exactly at 0xFFFF is kept
+        let instructions = disassemble(&mmu, 0xFFFC, 10);
+        assert_eq!(instructions, vec![
+            (0xFFFC, OpCode::Nop, 1),
+            (0xFFFD, OpCode::Load16Immediate { register: Register16::BC, value: 0x0000 }, 3),
+        ]);
+
+        // LD BC,nn from 0xFFFE would need a byte past the end of the address space
+        mmu.write(0xFFFE, 0x01);
+        assert_eq!(disassemble(&mmu, 0xFFFE, 10), vec![]);
+    }
+}
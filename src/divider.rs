@@ -23,8 +23,10 @@ impl Divider {
         self.enabled = true;
     }
 
+    /// Stops the divider, the whole internal counter is reset so it restarts from zero when enabled
     pub fn disable(&mut self) {
         self.value = 0;
+        self.cycles_since_tick = MachineCycles::ZERO;
         self.enabled = false;
     }
 
@@ -32,14 +34,21 @@ impl Divider {
         self.enabled
     }
 
-    pub fn reset(&mut self) {
-        self.value = 0;
-    }
-
     pub fn value(&self) -> u8 {
         self.value
     }
 
+    /// The full 16-bit internal counter in t-cycles, DIV is the high byte.
+    /// The bottom 2 bits are always zero as the divider is only clocked in machine cycles.
+    pub fn counter(&self) -> u16 {
+        ((self.value as u16) << 8) | self.cycles_since_tick.t_cycles() as u16
+    }
+
+    pub fn set_counter(&mut self, counter: u16) {
+        self.value = (counter >> 8) as u8;
+        self.cycles_since_tick = MachineCycles::from_t((counter & 0xFF) as usize);
+    }
+
     pub fn update(&mut self, cycles: MachineCycles) -> DividerClocks {
         let mut result = DividerClocks { initial_value: self.value, count: 0 };
         if !self.enabled {
@@ -132,6 +141,24 @@ mod tests {
         assert_eq!(divider.value(), 0);
     }
 
+    #[test]
+    fn counter() {
+        let mut divider = Divider::default();
+        divider.update(MachineCycles::from_m(3));
+        assert_eq!(divider.counter(), 12);
+        divider.update(MachineCycles::PER_DIVIDER_TICK);
+        assert_eq!(divider.counter(), 0x010C);
+
+        divider.set_counter(0xABFC);
+        assert_eq!(divider.value(), 0xAB);
+        let clocks = divider.update(MachineCycles::ONE);
+        assert_eq!(clocks, DividerClocks { initial_value: 0xAB, count: 1 });
+        assert_eq!(divider.counter(), 0xAC00);
+
+        // sub machine cycle bits are dropped
+        divider.set_counter(0x1203);
+        assert_eq!(divider.counter(), 0x1200);
+    }
 
     #[test]
     fn bit_fall_edge() {
@@ -32,6 +32,27 @@ pub enum CartType {
     HuC1RamBattery = 0xFF,
 }
 
+impl CartType {
+    /// MBC3 cartridges with a real-time clock
+    pub fn has_timer(&self) -> bool {
+        matches!(self, CartType::NBC3TimerBattery | CartType::MBC3TimerRamBattery)
+    }
+
+    pub fn is_mbc1(&self) -> bool {
+        matches!(self, CartType::MBC1 | CartType::MBC1Ram | CartType::MBC1RamBattery)
+    }
+
+    pub fn is_mbc5(&self) -> bool {
+        matches!(self, CartType::MBC5 | CartType::MBC5Ram | CartType::MBC5RamBattery
+            | CartType::MBC5Rumble | CartType::MBC5RumbleRam | CartType::MBC5RumbleRamBattery)
+    }
+
+    /// MBC5 carts with a rumble motor, which is switched by bit 3 of the RAM bank register
+    pub fn has_rumble(&self) -> bool {
+        matches!(self, CartType::MBC5Rumble | CartType::MBC5RumbleRam | CartType::MBC5RumbleRamBattery)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Decode, Encode)]
 pub enum CGBMode {
     None,
@@ -39,15 +60,41 @@ pub enum CGBMode {
     Exclusive
 }
 
+/// https://gbdev.io/pandocs/The_Cartridge_Header.html#014a--destination-code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Decode, Encode)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+/// https://gbdev.io/pandocs/The_Cartridge_Header.html
 #[derive(Debug, Clone, PartialEq, Eq, Decode, Encode)]
 pub struct CartHeader {
     title: String,
+    manufacturer_code: Option<String>,
     cgb_mode: CGBMode,
+    sgb_support: bool,
     cart_type: CartType,
     rom_banks: usize,
     ram_banks: usize,
+    destination: Destination,
+    old_licensee_code: u8,
+    new_licensee_code: Option<String>,
+    mask_rom_version: u8,
+    header_checksum: u8,
+    global_checksum: u16,
+    header_checksum_valid: bool,
+    global_checksum_valid: bool,
+    logo_valid: bool,
 }
 
+/// https://gbdev.io/pandocs/The_Cartridge_Header.html#0104-0133--nintendo-logo
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
 impl CartHeader {
     pub fn parse(data: &[u8]) -> Result<Self, String> {
         let title_bytes = data.get(0x0134..0x0143).ok_or("Invalid title length")?;
@@ -91,7 +138,65 @@ impl CartHeader {
             })
             .ok_or("Invalid RAM size")?;
 
-        Ok(Self { title, cgb_mode, cart_type, rom_banks, ram_banks })
+        let header = data.get(0x0100..0x0150).ok_or("Invalid header length")?;
+        let header_byte = |address: usize| header[address - 0x0100];
+
+        // only CGB cartridges have a manufacturer code in the last 4 bytes of the title, older titles use all 16 bytes
+        let manufacturer_code = (cgb_mode != CGBMode::None)
+            .then(|| std::str::from_utf8(&header[0x3F..0x43]).ok())
+            .flatten()
+            .filter(|code| code.bytes().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()))
+            .map(|code| code.to_string());
+
+        let destination = if header_byte(0x014A) == 0x00 { Destination::Japan } else { Destination::Overseas };
+
+        // the new licensee code is only used when the old licensee code is 0x33
+        let old_licensee_code = header_byte(0x014B);
+        let new_licensee_code = (old_licensee_code == 0x33)
+            .then(|| String::from_utf8_lossy(&header[0x44..0x46]).to_string());
+
+        let header_checksum = header_byte(0x014D);
+        let global_checksum = u16::from_be_bytes([header_byte(0x014E), header_byte(0x014F)]);
+
+        Ok(Self {
+            title,
+            manufacturer_code,
+            cgb_mode,
+            sgb_support: header_byte(0x0146) == 0x03,
+            cart_type,
+            rom_banks,
+            ram_banks,
+            destination,
+            old_licensee_code,
+            new_licensee_code,
+            mask_rom_version: header_byte(0x014C),
+            header_checksum,
+            global_checksum,
+            header_checksum_valid: Self::compute_header_checksum(data) == Some(header_checksum),
+            global_checksum_valid: Self::compute_global_checksum(data) == Some(global_checksum),
+            logo_valid: header[0x04..0x34] == NINTENDO_LOGO,
+        })
+    }
+
+    /// The boot ROM refuses to start a cartridge if this does not match the header checksum, `None` if the header is truncated
+    /// https://gbdev.io/pandocs/The_Cartridge_Header.html#014d--header-checksum
+    pub fn compute_header_checksum(data: &[u8]) -> Option<u8> {
+        let checksum = data.get(0x0134..=0x014C)?.iter()
+            .fold(0u8, |checksum, &byte| checksum.wrapping_sub(byte).wrapping_sub(1));
+        Some(checksum)
+    }
+
+    /// Sum of every ROM byte except the global checksum itself, this is not checked by the boot ROM.
+    /// `None` if the header is truncated.
+    /// https://gbdev.io/pandocs/The_Cartridge_Header.html#014e-014f--global-checksum
+    pub fn compute_global_checksum(data: &[u8]) -> Option<u16> {
+        if data.len() < 0x0150 {
+            return None;
+        }
+        let checksum = data.iter().enumerate()
+            .filter(|&(address, _)| address != 0x014E && address != 0x014F)
+            .fold(0u16, |checksum, (_, &byte)| checksum.wrapping_add(byte as u16));
+        Some(checksum)
     }
 
     pub fn title(&self) -> &str {
@@ -113,6 +218,51 @@ impl CartHeader {
     pub fn ram_banks(&self) -> usize {
         self.ram_banks
     }
+
+    pub fn manufacturer_code(&self) -> Option<&str> {
+        self.manufacturer_code.as_deref()
+    }
+
+    pub fn sgb_support(&self) -> bool {
+        self.sgb_support
+    }
+
+    pub fn destination(&self) -> Destination {
+        self.destination
+    }
+
+    pub fn old_licensee_code(&self) -> u8 {
+        self.old_licensee_code
+    }
+
+    pub fn new_licensee_code(&self) -> Option<&str> {
+        self.new_licensee_code.as_deref()
+    }
+
+    pub fn mask_rom_version(&self) -> u8 {
+        self.mask_rom_version
+    }
+
+    pub fn header_checksum(&self) -> u8 {
+        self.header_checksum
+    }
+
+    pub fn global_checksum(&self) -> u16 {
+        self.global_checksum
+    }
+
+    pub fn header_checksum_valid(&self) -> bool {
+        self.header_checksum_valid
+    }
+
+    pub fn global_checksum_valid(&self) -> bool {
+        self.global_checksum_valid
+    }
+
+    /// Real hardware refuses to boot a cartridge if the logo at 0x0104-0x0133 doesn't match Nintendo's
+    pub fn logo_valid(&self) -> bool {
+        self.logo_valid
+    }
 }
 
 #[cfg(test)]
@@ -128,6 +278,75 @@ mod tests {
         assert_eq!(header.cart_type(), CartType::MBC1);
         assert_eq!(header.rom_banks(), 4); // 64KB ROM
         assert_eq!(header.ram_banks(), 0); // No RAM
+        assert_eq!(header.manufacturer_code(), None);
+        assert!(!header.sgb_support());
+        assert_eq!(header.destination(), Destination::Japan);
+        assert!(header.header_checksum_valid());
+        assert!(header.logo_valid());
+    }
+
+    #[test]
+    fn parse_checksums() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0134..0x0138].copy_from_slice(b"TEST");
+        rom[0x013F..0x0143].copy_from_slice(b"ATEJ");
+        rom[0x0143] = 0x80; // CGB enhanced, so there is a manufacturer code
+        rom[0x0146] = 0x03; // SGB
+        rom[0x014A] = 0x01; // overseas
+        rom[0x014B] = 0x33; // use the new licensee code
+        rom[0x0144..0x0146].copy_from_slice(b"01");
+        rom[0x014C] = 0x02;
+        rom[0x014D] = CartHeader::compute_header_checksum(&rom).unwrap();
+        let global_checksum = CartHeader::compute_global_checksum(&rom).unwrap();
+        rom[0x014E..0x0150].copy_from_slice(&global_checksum.to_be_bytes());
+
+        let header = CartHeader::parse(&rom).unwrap();
+        assert_eq!(header.title(), "TEST");
+        assert_eq!(header.manufacturer_code(), Some("ATEJ"));
+        assert!(header.sgb_support());
+        assert_eq!(header.destination(), Destination::Overseas);
+        assert_eq!(header.old_licensee_code(), 0x33);
+        assert_eq!(header.new_licensee_code(), Some("01"));
+        assert_eq!(header.mask_rom_version(), 0x02);
+        assert_eq!(header.global_checksum(), global_checksum);
+        assert!(header.header_checksum_valid());
+        assert!(header.global_checksum_valid());
+
+        rom[0x0200] = 0xFF; // corrupt the ROM, but not the header
+        let header = CartHeader::parse(&rom).unwrap();
+        assert!(header.header_checksum_valid());
+        assert!(!header.global_checksum_valid());
+
+        rom[0x0147] = 0x01; // corrupt the header
+        assert!(!CartHeader::parse(&rom).unwrap().header_checksum_valid());
+
+        assert_eq!(CartHeader::compute_header_checksum(&rom[..0x014C]), None);
+        assert_eq!(CartHeader::compute_global_checksum(&rom[..0x014F]), None);
+    }
+
+    #[test]
+    fn parse_manufacturer_code() {
+        // older cartridges use the whole title area, so the end of the title is not a manufacturer code
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0134..0x0143].copy_from_slice(b"POKEMON RED2000");
+        let header = CartHeader::parse(&rom).unwrap();
+        assert_eq!(header.title(), "POKEMON RED2000");
+        assert_eq!(header.manufacturer_code(), None);
+
+        rom[0x0143] = 0xC0; // CGB only
+        assert_eq!(CartHeader::parse(&rom).unwrap().manufacturer_code(), Some("2000"));
+    }
+
+    #[test]
+    fn parse_logo() {
+        let mut rom = vec![0u8; 0x8000];
+        assert!(!CartHeader::parse(&rom).unwrap().logo_valid());
+
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+        assert!(CartHeader::parse(&rom).unwrap().logo_valid());
+
+        rom[0x0133] ^= 0x01;
+        assert!(!CartHeader::parse(&rom).unwrap().logo_valid());
     }
 
     #[test]
@@ -0,0 +1,170 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::joypad::JoypadButton;
+
+/// A button press or release applied before the emulated frame `frame` is run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub button: JoypadButton,
+    pub pressed: bool,
+}
+
+/// Joypad events by frame, for deterministic replay from a fixed initial state.
+/// As text there is one `<frame> <button> press|release` event per line, blank lines & `#` comments are ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InputLog {
+    events: Vec<InputEvent>,
+}
+
+impl InputLog {
+    /// Events are kept in frame order, events on the same frame stay in the order they were recorded
+    pub fn record(&mut self, frame: u64, button: JoypadButton, pressed: bool) {
+        let index = self.events.partition_point(|event| event.frame <= frame);
+        self.events.insert(index, InputEvent { frame, button, pressed });
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// The frame after the last event
+    pub fn frames(&self) -> u64 {
+        self.events.last().map_or(0, |event| event.frame + 1)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut log = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || format!("Invalid input log line {}: {}", index + 1, line);
+            let [frame, button, action] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(invalid());
+            };
+            let frame = frame.parse().map_err(|_| invalid())?;
+            let button = JoypadButton::from_str(button).map_err(|_| invalid())?;
+            let pressed = match action {
+                "press" => true,
+                "release" => false,
+                _ => return Err(invalid()),
+            };
+            log.record(frame, button, pressed);
+        }
+        Ok(log)
+    }
+}
+
+impl Display for InputLog {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{} {} {}", event.frame, event.button, if event.pressed { "press" } else { "release" })?;
+        }
+        Ok(())
+    }
+}
+
+/// Recording & playback state of a `GameBoy`, frames are counted from when recording or playback started
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InputReplay {
+    frame: u64,
+    recording: Option<InputLog>,
+    playback: Option<(InputLog, usize)>, // the log & the index of the next event to apply
+}
+
+impl InputReplay {
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn start_recording(&mut self) {
+        self.frame = 0;
+        self.recording = Some(InputLog::default());
+    }
+
+    pub fn stop_recording(&mut self) -> Option<InputLog> {
+        self.recording.take()
+    }
+
+    pub fn record(&mut self, button: JoypadButton, pressed: bool) {
+        if let Some(log) = self.recording.as_mut() {
+            log.record(self.frame, button, pressed);
+        }
+    }
+
+    pub fn play(&mut self, log: &InputLog) {
+        self.frame = 0;
+        self.playback = Some((log.clone(), 0));
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playback.as_ref().is_some_and(|(log, next)| *next < log.events.len())
+    }
+
+    /// The events to apply before running the current frame
+    pub fn take_frame_events(&mut self) -> Vec<InputEvent> {
+        let Some((log, next)) = self.playback.as_mut() else {
+            return vec![];
+        };
+        let start = *next;
+        while *next < log.events.len() && log.events[*next].frame <= self.frame {
+            *next += 1;
+        }
+        log.events[start..*next].to_vec()
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joypad::JoypadButton::*;
+
+    #[test]
+    fn text_round_trip() {
+        let mut log = InputLog::default();
+        log.record(10, A, true);
+        log.record(12, A, false);
+        log.record(10, Start, true);
+        assert_eq!(log.to_string(), "10 A press\n10 Start press\n12 A release\n");
+        assert_eq!(log.frames(), 13);
+        assert_eq!(InputLog::parse(&log.to_string()), Ok(log));
+
+        let parsed = InputLog::parse("# comment\n\n  5 Up press # hold up\n").unwrap();
+        assert_eq!(parsed.events(), &[InputEvent { frame: 5, button: Up, pressed: true }]);
+
+        assert_eq!(InputLog::parse("5 Up hold").unwrap_err(), "Invalid input log line 1: 5 Up hold");
+        assert!(InputLog::parse("x Up press").is_err());
+        assert!(InputLog::parse("5 Turbo press").is_err());
+        assert!(InputLog::parse("5 Up").is_err());
+    }
+
+    #[test]
+    fn playback() {
+        let mut log = InputLog::default();
+        log.record(0, B, true);
+        log.record(2, B, false);
+        let mut replay = InputReplay::default();
+        replay.play(&log);
+
+        let mut frames = vec![];
+        while replay.is_playing() {
+            frames.push(replay.take_frame_events());
+            replay.end_frame();
+        }
+        assert_eq!(frames, vec![
+            vec![InputEvent { frame: 0, button: B, pressed: true }],
+            vec![],
+            vec![InputEvent { frame: 2, button: B, pressed: false }],
+        ]);
+    }
+}
@@ -25,75 +25,91 @@ impl Default for InterruptFlags {
 
 impl InterruptFlags {
     pub fn set(&mut self, value: u8) {
-        self.joypad = (value & 0x10) != 0;
-        self.serial = (value & 0x08) != 0;
-        self.timer = (value & 0x04) != 0;
-        self.lcd_stat = (value & 0x02) != 0;
-        self.v_blank = (value & 0x01) != 0;
+        for interrupt in Interrupt::all() {
+            if value & interrupt.bit() != 0 {
+                self.set_interrupt(interrupt);
+            } else {
+                self.clear_interrupt(interrupt);
+            }
+        }
     }
 
     pub fn get(&self) -> u8 {
-        let mut value = 0;
-        if self.joypad { value |= 0x10; }
-        if self.serial { value |= 0x08; }
-        if self.timer { value |= 0x04; }
-        if self.lcd_stat { value |= 0x02; }
-        if self.v_blank { value |= 0x01; }
-        value
+        Interrupt::all()
+            .filter(|&interrupt| self.is_set(interrupt))
+            .fold(0, |value, interrupt| value | interrupt.bit())
     }
 
-    pub fn is_set(&self, interrupt: InterruptType) -> bool {
+    pub fn is_set(&self, interrupt: Interrupt) -> bool {
         match interrupt {
-            InterruptType::VBlank => self.v_blank,
-            InterruptType::LcdStatus => self.lcd_stat,
-            InterruptType::Timer => self.timer,
-            InterruptType::Serial => self.serial,
-            InterruptType::Joypad => self.joypad,
+            Interrupt::VBlank => self.v_blank,
+            Interrupt::LcdStat => self.lcd_stat,
+            Interrupt::Timer => self.timer,
+            Interrupt::Serial => self.serial,
+            Interrupt::Joypad => self.joypad,
         }
     }
 
-    pub fn clear_interrupt(&mut self, interrupt: InterruptType) {
+    pub fn clear_interrupt(&mut self, interrupt: Interrupt) {
         match interrupt {
-            InterruptType::VBlank => self.v_blank = false,
-            InterruptType::LcdStatus => self.lcd_stat = false,
-            InterruptType::Timer => self.timer = false,
-            InterruptType::Serial => self.serial = false,
-            InterruptType::Joypad => self.joypad = false,
+            Interrupt::VBlank => self.v_blank = false,
+            Interrupt::LcdStat => self.lcd_stat = false,
+            Interrupt::Timer => self.timer = false,
+            Interrupt::Serial => self.serial = false,
+            Interrupt::Joypad => self.joypad = false,
         }
     }
 
-    pub fn set_interrupt(&mut self, interrupt: InterruptType) {
+    pub fn set_interrupt(&mut self, interrupt: Interrupt) {
         match interrupt {
-            InterruptType::VBlank => self.v_blank = true,
-            InterruptType::LcdStatus => self.lcd_stat = true,
-            InterruptType::Timer => self.timer = true,
-            InterruptType::Serial => self.serial = true,
-            InterruptType::Joypad => self.joypad = true,
+            Interrupt::VBlank => self.v_blank = true,
+            Interrupt::LcdStat => self.lcd_stat = true,
+            Interrupt::Timer => self.timer = true,
+            Interrupt::Serial => self.serial = true,
+            Interrupt::Joypad => self.joypad = true,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::EnumIter)]
-pub enum InterruptType {
+/// Interrupt sources in priority order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::EnumIter, strum_macros::Display, Decode, Encode)]
+pub enum Interrupt {
     VBlank,
-    LcdStatus,
+    LcdStat,
     Timer,
     Serial,
     Joypad,
 }
 
-impl InterruptType {
-    pub fn all() -> InterruptTypeIter {
+impl Interrupt {
+    pub fn all() -> InterruptIter {
         Self::iter()
     }
 
     pub fn address(self) -> u16 {
         match self {
-            InterruptType::VBlank => 0x0040,
-            InterruptType::LcdStatus => 0x0048,
-            InterruptType::Timer => 0x0050,
-            InterruptType::Serial => 0x0058,
-            InterruptType::Joypad => 0x0060,
+            Interrupt::VBlank => 0x0040,
+            Interrupt::LcdStat => 0x0048,
+            Interrupt::Timer => 0x0050,
+            Interrupt::Serial => 0x0058,
+            Interrupt::Joypad => 0x0060,
+        }
+    }
+
+    /// The highest priority interrupt in an IE & IF style bitmask, the lowest set bit wins
+    /// https://gbdev.io/pandocs/Interrupts.html#interrupt-priorities
+    pub fn highest_priority(bits: u8) -> Option<Self> {
+        Self::all().nth((bits & 0x1F).trailing_zeros() as usize)
+    }
+
+    /// The bit of this interrupt in the IE & IF registers
+    pub fn bit(self) -> u8 {
+        match self {
+            Interrupt::VBlank => 0x01,
+            Interrupt::LcdStat => 0x02,
+            Interrupt::Timer => 0x04,
+            Interrupt::Serial => 0x08,
+            Interrupt::Joypad => 0x10,
         }
     }
 }
@@ -119,4 +135,15 @@ mod tests {
         flags.set(0x1F);
         assert_eq!(flags.get(), 0x1F); // All flags set
     }
+
+    #[test]
+    fn highest_priority() {
+        assert_eq!(Interrupt::highest_priority(0x00), None);
+        assert_eq!(Interrupt::highest_priority(0x1F), Some(Interrupt::VBlank));
+        assert_eq!(Interrupt::highest_priority(0x1E), Some(Interrupt::LcdStat));
+        assert_eq!(Interrupt::highest_priority(0x1C), Some(Interrupt::Timer));
+        assert_eq!(Interrupt::highest_priority(0x18), Some(Interrupt::Serial));
+        assert_eq!(Interrupt::highest_priority(0x10), Some(Interrupt::Joypad));
+        assert_eq!(Interrupt::highest_priority(0xE0), None); // unused bits
+    }
 }
\ No newline at end of file
@@ -1,7 +1,8 @@
 use bincode::{Decode, Encode};
 use crate::activation::Activation;
+use crate::sgb::{SgbPacket, SgbReceiver};
 /// https://gbdev.io/pandocs/Joypad_Input.html#ff00--p1joyp-joypad
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Decode, Encode)]
+#[derive(Debug, Clone, PartialEq, Eq, Decode, Encode)]
 pub struct JoypadRegister {
     up: bool,
     down: bool,
@@ -14,6 +15,7 @@ pub struct JoypadRegister {
     select_buttons: bool,
     select_directions: bool,
     interrupt_pending: bool,
+    sgb: SgbReceiver,
 }
 
 impl Default for JoypadRegister {
@@ -30,14 +32,32 @@ impl Default for JoypadRegister {
             select_buttons: false,
             select_directions: false,
             interrupt_pending: false,
+            sgb: SgbReceiver::default(),
         }
     }
 }
 
 impl JoypadRegister {
     pub fn set(&mut self, value: u8) {
-        self.select_buttons = (value & 0x20) == 0;
-        self.select_directions = (value & 0x10) == 0;
+        // selecting a group with a button already held is also a falling edge
+        self.update_lines(|joypad| {
+            joypad.select_buttons = (value & 0x20) == 0;
+            joypad.select_directions = (value & 0x10) == 0;
+        });
+        self.sgb.write(value);
+    }
+
+    /// The interrupt is requested when any of P10-P13 go from high to low, so only presses in a selected group count
+    fn update_lines(&mut self, f: impl FnOnce(&mut Self)) {
+        let before = self.get() & 0xF;
+        f(self);
+        let after = self.get() & 0xF;
+        self.interrupt_pending = self.interrupt_pending || (before & !after) != 0;
+    }
+
+    /// SGB command packets captured from writes to this register since the last call
+    pub fn take_sgb_packets(&mut self) -> Vec<SgbPacket> {
+        self.sgb.take_packets()
     }
 
     pub fn get(&self) -> u8 {
@@ -69,17 +89,16 @@ impl JoypadRegister {
     }
 
     pub fn update_button(&mut self, button: JoypadButton, pressed: bool) {
-        self.interrupt_pending = self.interrupt_pending || (pressed && !self.is_button_pressed(button));
-        match button {
-            JoypadButton::Up => self.up = pressed,
-            JoypadButton::Down => self.down = pressed,
-            JoypadButton::Left => self.left = pressed,
-            JoypadButton::Right => self.right = pressed,
-            JoypadButton::A => self.a = pressed,
-            JoypadButton::B => self.b = pressed,
-            JoypadButton::Select => self.select = pressed,
-            JoypadButton::Start => self.start = pressed,
-        }
+        self.update_lines(|joypad| match button {
+            JoypadButton::Up => joypad.up = pressed,
+            JoypadButton::Down => joypad.down = pressed,
+            JoypadButton::Left => joypad.left = pressed,
+            JoypadButton::Right => joypad.right = pressed,
+            JoypadButton::A => joypad.a = pressed,
+            JoypadButton::B => joypad.b = pressed,
+            JoypadButton::Select => joypad.select = pressed,
+            JoypadButton::Start => joypad.start = pressed,
+        });
     }
 
     pub fn press_button(&mut self, button: JoypadButton) {
@@ -101,7 +120,7 @@ impl Activation for JoypadRegister {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::EnumIter, strum_macros::Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::EnumIter, strum_macros::Display, strum_macros::EnumString)]
 pub enum JoypadButton {
     Up,
     Down,
@@ -142,6 +161,7 @@ mod tests {
     #[test]
     fn interrupts() {
         let mut joypad = JoypadRegister::default();
+        joypad.set(0x10); // select buttons
         assert!(!joypad.is_activation_pending()); // disabled by default
         joypad.release_button(A);
         assert!(!joypad.is_activation_pending()); // no interrupt on release
@@ -150,4 +170,45 @@ mod tests {
         joypad.release_button(A);
         assert!(joypad.is_activation_pending()); // still interrupt required until read
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn interrupts_only_for_selected_group() {
+        let mut joypad = JoypadRegister::default();
+        joypad.set(0x20); // select directions
+        joypad.press_button(A);
+        assert!(!joypad.is_activation_pending());
+        joypad.press_button(Up);
+        assert!(joypad.consume_pending_activation());
+
+        // selecting buttons while A is held pulls P10 low
+        joypad.set(0x10);
+        assert!(joypad.consume_pending_activation());
+        joypad.set(0x10);
+        assert!(!joypad.is_activation_pending());
+    }
+
+    #[test]
+    fn sgb_packet() {
+        let mut joypad = JoypadRegister::default();
+        let mut packet = [0u8; 16];
+        packet[0] = 1; // PAL01 (command 0x00), 1 packet
+        packet[1] = 0xFF;
+        packet[2] = 0x7F;
+
+        joypad.set(0x00); // reset pulse
+        joypad.set(0x30);
+        for byte in packet {
+            for bit in 0..8 {
+                joypad.set(if byte & (1 << bit) != 0 { 0x10 } else { 0x20 });
+                joypad.set(0x30);
+            }
+        }
+        joypad.set(0x20); // stop bit
+        joypad.set(0x30);
+
+        let packets = joypad.take_sgb_packets();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].command(), 0x00);
+        assert_eq!(packets[0].data()[1..3], [0xFF, 0x7F]);
+    }
+}
@@ -1,6 +1,9 @@
+use std::ops::Range;
 use bincode::{Decode, Encode};
 use crate::cycles::MachineCycles;
 
+/// OAM DMA copies 160 bytes from XX00-XX9F to OAM, one byte per machine cycle
+/// https://gbdev.io/pandocs/OAM_DMA_Transfer.html
 #[derive(Debug, Clone, PartialEq, Eq, Default, Decode, Encode)]
 pub struct LcdDma {
     state: Option<LcdDmaState>,
@@ -11,23 +14,18 @@ impl LcdDma {
         self.state = Some(LcdDmaState { address: ((value & 0xDF) as u16) << 8, cycles: MachineCycles::ZERO });
     }
 
+    /// Advance the transfer, returns the bytes to copy for the elapsed machine cycles
     pub fn update(&mut self, delta_machine_cycles: MachineCycles) -> Option<DmaTransfer> {
-        if let Some(state) = &mut self.state {
-            state.cycles += delta_machine_cycles;
-            if state.cycles >= DMA_TRANSFER_CYCLES {
-                // Transfer complete, reset state
-                let transfer = DmaTransfer { address: state.address };
-                self.state = None;
-                Some(transfer)
-            } else {
-                // still in transfer
-                // TODO implement partial transfer logic
-                None
-            }
-        } else {
-            // no transfer in progress
-            None
+        let state = self.state.as_mut()?;
+        let start = state.cycles.m_cycles();
+        state.cycles += delta_machine_cycles;
+        let end = state.cycles.min(DMA_TRANSFER_CYCLES).m_cycles();
+        let transfer = DmaTransfer { address: state.address, offsets: start as u16..end as u16 };
+        if state.cycles >= DMA_TRANSFER_CYCLES {
+            // transfer complete, reset state
+            self.state = None;
         }
+        Some(transfer)
     }
 
     pub fn is_active(&self) -> bool {
@@ -35,9 +33,10 @@ impl LcdDma {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DmaTransfer {
-    pub address: u16
+    pub address: u16, // source address of the first byte
+    pub offsets: Range<u16>, // offsets into the source & OAM to copy
 }
 
 const DMA_TRANSFER_CYCLES: MachineCycles = MachineCycles::from_m(160);
@@ -46,4 +45,4 @@ const DMA_TRANSFER_CYCLES: MachineCycles = MachineCycles::from_m(160);
 pub struct LcdDmaState {
     address: u16,
     cycles: MachineCycles
-}
\ No newline at end of file
+}
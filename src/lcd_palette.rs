@@ -1,5 +1,7 @@
 use std::ops::{Deref, DerefMut};
-use bincode::{Decode, Encode};
+use bincode::{BorrowDecode, Decode, Encode};
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::Encoder;
 use image::Rgb;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::FromRepr, Default, Decode, Encode)]
@@ -15,12 +17,38 @@ pub enum DMGColor {
 impl DMGColor {
 
     pub fn to_rgb(self) -> Rgb<u8> {
-        match self {
-            DMGColor::White => Rgb([0xFF, 0xFF, 0xFF]),      // Pure white
-            DMGColor::LightGray => Rgb([0xAA, 0xAA, 0xAA]),  // Light gray
-            DMGColor::DarkGray => Rgb([0x55, 0x55, 0x55]),      // Dark gray
-            DMGColor::Black => Rgb([0x00, 0x00, 0x00]),            // Pure black
-        }
+        DMGPalette::GRAYSCALE.rgb(self)
+    }
+}
+
+/// Presentation palette mapping each DMG shade to an RGB color, this is not visible to the emulated game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DMGPalette(pub [Rgb<u8>; 4]);
+
+impl DMGPalette {
+    pub const GRAYSCALE: Self = Self([
+        Rgb([0xFF, 0xFF, 0xFF]), // Pure white
+        Rgb([0xAA, 0xAA, 0xAA]), // Light gray
+        Rgb([0x55, 0x55, 0x55]), // Dark gray
+        Rgb([0x00, 0x00, 0x00]), // Pure black
+    ]);
+
+    /// The original DMG's green tinted LCD
+    pub const GREEN: Self = Self([
+        Rgb([0x9B, 0xBC, 0x0F]),
+        Rgb([0x8B, 0xAC, 0x0F]),
+        Rgb([0x30, 0x62, 0x30]),
+        Rgb([0x0F, 0x38, 0x0F]),
+    ]);
+
+    pub fn rgb(&self, color: DMGColor) -> Rgb<u8> {
+        self.0[color as usize]
+    }
+}
+
+impl Default for DMGPalette {
+    fn default() -> Self {
+        Self::GRAYSCALE
     }
 }
 
@@ -59,11 +87,79 @@ impl DMGPaletteRegister {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Decode, Encode)]
+pub const CGB_PALETTE_COUNT: usize = 8;
+const CGB_PALETTE_BYTES: usize = CGB_PALETTE_COUNT * 4 * 2; // 8 palettes of 4 little endian RGB555 colors
+
+/// CGB palette RAM, accessed through a specification register (BCPS/OCPS) & a data register (BCPD/OCPD)
+/// https://gbdev.io/pandocs/Palettes.html#lcd-color-palettes-cgb-only
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Decode, Encode)]
+pub struct CgbPalette {
+    data: [u8; CGB_PALETTE_BYTES],
+    index: u8, // bits 0-5 of the specification register
+    auto_increment: bool, // bit 7 of the specification register, the index is incremented after each data write
+}
+
+impl Default for CgbPalette {
+    fn default() -> Self {
+        Self {
+            data: [0xFF; CGB_PALETTE_BYTES], // every color white
+            index: 0,
+            auto_increment: false,
+        }
+    }
+}
+
+impl CgbPalette {
+    pub fn spec(&self) -> u8 {
+        0x40 | ((self.auto_increment as u8) << 7) | self.index // bit 6 is unused
+    }
+
+    pub fn set_spec(&mut self, value: u8) {
+        self.index = value & 0x3F;
+        self.auto_increment = value & 0x80 != 0;
+    }
+
+    pub fn data(&self) -> u8 {
+        self.data[self.index as usize]
+    }
+
+    pub fn set_data(&mut self, value: u8) {
+        self.data[self.index as usize] = value;
+        self.increment();
+    }
+
+    /// Writes during PPU mode 3 are dropped but still auto-increment the index
+    pub fn increment(&mut self) {
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3F;
+        }
+    }
+
+    /// The raw RGB555 color, red in bits 0-4, green in bits 5-9 & blue in bits 10-14
+    pub fn color(&self, palette: usize, color_index: usize) -> u16 {
+        let offset = (palette * 4 + color_index) * 2;
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]]) & 0x7FFF
+    }
+
+    pub fn rgb(&self, palette: usize, color_index: usize) -> Rgb<u8> {
+        let color = self.color(palette, color_index);
+        // scale each 5-bit channel to 8 bits
+        let channel = |shift: u16| {
+            let value = ((color >> shift) & 0x1F) as u8;
+            (value << 3) | (value >> 2)
+        };
+        Rgb([channel(0), channel(5), channel(10)])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct LcdPalette {
     background: DMGPaletteRegister,
     object0: DMGPaletteRegister,
     object1: DMGPaletteRegister,
+    color_map: DMGPalette, // output stage only, BGP/OBP shades are mapped through this into RGB
+    cgb_background: CgbPalette, // BCPS/BCPD (CGB only)
+    cgb_object: CgbPalette, // OCPS/OCPD (CGB only)
 }
 
 impl LcdPalette {
@@ -90,4 +186,67 @@ impl LcdPalette {
     pub fn object1_mut(&mut self) -> &mut DMGPaletteRegister {
         &mut self.object1
     }
-}
\ No newline at end of file
+
+    /// RGB colors of the 4 shades in the final framebuffer, grayscale by default
+    pub fn color_map(&self) -> DMGPalette {
+        self.color_map
+    }
+
+    pub fn set_color_map(&mut self, color_map: DMGPalette) {
+        self.color_map = color_map;
+    }
+
+    pub fn cgb_background(&self) -> &CgbPalette {
+        &self.cgb_background
+    }
+
+    pub fn cgb_background_mut(&mut self) -> &mut CgbPalette {
+        &mut self.cgb_background
+    }
+
+    pub fn cgb_object(&self) -> &CgbPalette {
+        &self.cgb_object
+    }
+
+    pub fn cgb_object_mut(&mut self) -> &mut CgbPalette {
+        &mut self.cgb_object
+    }
+}
+
+impl Encode for LcdPalette {
+    fn encode<__E: Encoder>(&self, encoder: &mut __E) -> Result<(), bincode::error::EncodeError> {
+        // the color map is not encoded, it is chosen by the front end & is not visible to the emulated game
+        Encode::encode(&self.background, encoder)?;
+        Encode::encode(&self.object0, encoder)?;
+        Encode::encode(&self.object1, encoder)?;
+        Encode::encode(&self.cgb_background, encoder)?;
+        Encode::encode(&self.cgb_object, encoder)?;
+        Ok(())
+    }
+}
+
+impl<__Context> Decode<__Context> for LcdPalette {
+    fn decode<__D: Decoder<Context=__Context>>(decoder: &mut __D) -> Result<Self, bincode::error::DecodeError> {
+        Ok(Self {
+            background: Decode::decode(decoder)?,
+            object0: Decode::decode(decoder)?,
+            object1: Decode::decode(decoder)?,
+            cgb_background: Decode::decode(decoder)?,
+            cgb_object: Decode::decode(decoder)?,
+            color_map: DMGPalette::default(),
+        })
+    }
+}
+
+impl<'__de, __Context> BorrowDecode<'__de, __Context> for LcdPalette {
+    fn borrow_decode<__D: BorrowDecoder<'__de, Context=__Context>>(decoder: &mut __D) -> Result<Self, bincode::error::DecodeError> {
+        Ok(Self {
+            background: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            object0: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            object1: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            cgb_background: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            cgb_object: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            color_map: DMGPalette::default(),
+        })
+    }
+}
@@ -10,6 +10,7 @@ pub struct LcdStatus {
     vblank_interrupt: bool, // bit 4: Mode 1 interrupt (VBlank)
     oam_interrupt: bool, // bit 5: Mode 2 interrupt (OAM)
     lyc_interrupt: bool, // bit 6: LYC=LY interrupt
+    stat_line: bool, // internal STAT interrupt line, all enabled sources OR'd together
     interrupt_pending: bool, // Indicates if any interrupt is pending
 }
 
@@ -24,17 +25,24 @@ impl LcdStatus {
         if self.ly > 153 {
             self.ly = 0; // wrap around after VBlank
         }
-        self.check_lyc_interrupt();
+        self.update_stat_line();
         self.ly
     }
 
+    /// Sets LY to 0 & changes mode without requesting any STAT interrupts, used when the LCD is switched on or off
+    pub fn reset(&mut self, mode: LcdMode) {
+        self.ly = 0;
+        self.mode = mode;
+        self.stat_line = self.stat_sources();
+    }
+
     pub fn lyc(&self) -> u8 {
         self.lyc
     }
 
     pub fn set_lyc(&mut self, value: u8) {
         self.lyc = value;
-        self.check_lyc_interrupt();
+        self.update_stat_line();
     }
 
     pub fn mode(&self) -> LcdMode {
@@ -46,15 +54,7 @@ impl LcdStatus {
             return; // no change
         }
         self.mode = mode;
-
-        // check interrupt
-        // TODO emulate STAT blocking
-        self.interrupt_pending |= match mode {
-            LcdMode::HBlank => self.hblank_interrupt,
-            LcdMode::VBlank => self.vblank_interrupt,
-            LcdMode::OAM => self.oam_interrupt,
-            LcdMode::Drawing => false
-        };
+        self.update_stat_line();
     }
 
     pub fn stat(&self) -> u8 {
@@ -72,10 +72,25 @@ impl LcdStatus {
         self.vblank_interrupt = (value & 0x10) != 0;
         self.oam_interrupt = (value & 0x20) != 0;
         self.lyc_interrupt = (value & 0x40) != 0;
+        self.update_stat_line();
+    }
+
+    fn stat_sources(&self) -> bool {
+        let mode_source = match self.mode {
+            LcdMode::HBlank => self.hblank_interrupt,
+            LcdMode::VBlank => self.vblank_interrupt,
+            LcdMode::OAM => self.oam_interrupt,
+            LcdMode::Drawing => false
+        };
+        mode_source || (self.lyc_interrupt && self.lyc == self.ly)
     }
 
-    fn check_lyc_interrupt(&mut self) {
-        self.interrupt_pending |= self.lyc_interrupt && self.lyc == self.ly;
+    /// The interrupt is only requested on a rising edge of the STAT line, so a source becoming active while
+    /// another is still holding the line high is blocked https://gbdev.io/pandocs/Interrupt_Sources.html#int-48--stat-interrupt
+    fn update_stat_line(&mut self) {
+        let stat_line = self.stat_sources();
+        self.interrupt_pending |= stat_line && !self.stat_line;
+        self.stat_line = stat_line;
     }
 }
 
@@ -107,4 +122,55 @@ impl LcdMode {
     pub fn oam_accessible(self) -> bool {
         self == LcdMode::HBlank || self == LcdMode::VBlank
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lyc_interrupt() {
+        let mut status = LcdStatus::default();
+        status.set_lyc(2);
+        status.set_stat(0x40);
+        status.increment_ly();
+        assert!(!status.consume_pending_activation());
+        status.increment_ly();
+        assert!(status.consume_pending_activation());
+        assert_eq!(status.stat() & 0x04, 0x04);
+    }
+
+    #[test]
+    fn stat_blocking() {
+        let mut status = LcdStatus::default();
+        status.set_mode(LcdMode::Drawing);
+        status.set_stat(0x48); // HBlank & LYC=LY
+        status.set_lyc(1);
+
+        status.increment_ly(); // LYC=LY raises the line
+        assert!(status.consume_pending_activation());
+
+        // HBlank while LYC=LY is still holding the line high is blocked
+        status.set_mode(LcdMode::HBlank);
+        assert!(!status.consume_pending_activation());
+
+        // the line stays high until both sources are inactive
+        status.set_mode(LcdMode::OAM);
+        status.increment_ly();
+        status.set_mode(LcdMode::Drawing);
+        assert!(!status.consume_pending_activation());
+        status.set_mode(LcdMode::HBlank);
+        assert!(status.consume_pending_activation());
+    }
+
+    #[test]
+    fn enabling_active_source_interrupts() {
+        let mut status = LcdStatus::default();
+        status.set_mode(LcdMode::OAM);
+        assert!(!status.consume_pending_activation());
+        status.set_stat(0x20);
+        assert!(status.consume_pending_activation());
+        status.set_stat(0x20);
+        assert!(!status.consume_pending_activation()); // line is already high
+    }
+}
@@ -5,6 +5,7 @@ mod core;
 mod mmu;
 mod roms;
 mod joypad;
+mod sgb;
 mod interrupt;
 mod header;
 mod ppu;
@@ -21,7 +22,17 @@ mod timer;
 mod audio;
 mod activation;
 mod pokemon;
+mod cheats;
+mod memory_scan;
+mod render;
+mod rtc;
+mod speed;
+mod turbo;
+mod rewind;
+mod input_log;
+mod disassembler;
+mod trace;
 
 pub fn main() -> Result<(), String> {
-    sdl::render::render()
+    sdl::render::render(sdl::viewport::RenderConfig::default())
 }
\ No newline at end of file
@@ -0,0 +1,72 @@
+/// Mapped addresses of work RAM and high RAM, echo RAM is skipped as it would report every match twice
+pub const WRAM_START: u16 = 0xC000;
+pub const HRAM_START: u16 = 0xFF80;
+
+/// How a value must have changed since the previous snapshot to be kept by a diff scan
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ScanChange {
+    Increased,
+    Decreased,
+    Changed,
+    Unchanged,
+}
+
+impl ScanChange {
+    pub fn matches(&self, previous: u8, current: u8) -> bool {
+        match self {
+            ScanChange::Increased => current > previous,
+            ScanChange::Decreased => current < previous,
+            ScanChange::Changed => current != previous,
+            ScanChange::Unchanged => current == previous,
+        }
+    }
+}
+
+/// Copy of work RAM & high RAM used as the baseline for a diff scan
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RamSnapshot {
+    pub wram: Vec<u8>,
+    pub hram: Vec<u8>,
+}
+
+impl RamSnapshot {
+    pub fn new(wram: &[u8], hram: &[u8]) -> Self {
+        Self { wram: wram.to_vec(), hram: hram.to_vec() }
+    }
+
+    /// Each RAM region with its first mapped address
+    pub fn regions(&self) -> [(u16, &[u8]); 2] {
+        [(WRAM_START, &self.wram), (HRAM_START, &self.hram)]
+    }
+}
+
+pub fn scan_u8(regions: [(u16, &[u8]); 2], value: u8) -> Vec<u16> {
+    regions.into_iter()
+        .flat_map(|(start, ram)| ram.iter()
+            .enumerate()
+            .filter(move |&(_, &byte)| byte == value)
+            .map(move |(offset, _)| start + offset as u16))
+        .collect()
+}
+
+/// Values are never matched across the gap between work RAM and high RAM
+pub fn scan_u16_le(regions: [(u16, &[u8]); 2], value: u16) -> Vec<u16> {
+    let bytes = value.to_le_bytes();
+    regions.into_iter()
+        .flat_map(|(start, ram)| ram.windows(2)
+            .enumerate()
+            .filter(move |&(_, window)| window == bytes)
+            .map(move |(offset, _)| start + offset as u16))
+        .collect()
+}
+
+pub fn diff_scan(previous: &RamSnapshot, current: &RamSnapshot, change: ScanChange) -> Vec<u16> {
+    previous.regions().into_iter()
+        .zip(current.regions())
+        .flat_map(|((start, previous), (_, current))| previous.iter()
+            .zip(current.iter())
+            .enumerate()
+            .filter(move |&(_, (&previous, &current))| change.matches(previous, current))
+            .map(move |(offset, _)| start + offset as u16))
+        .collect()
+}
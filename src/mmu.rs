@@ -3,27 +3,37 @@ use bincode::de::{BorrowDecoder, Decoder};
 use bincode::enc::Encoder;
 use crate::activation::Activation;
 use crate::audio::Audio;
+use crate::cheats::{Cheat, Cheats};
 use crate::core::CoreMode;
 use crate::cycles::MachineCycles;
 use crate::divider::Divider;
-use crate::header::CartHeader;
-use crate::interrupt::{InterruptFlags, InterruptType};
+use crate::header::{CGBMode, CartHeader};
+use crate::interrupt::{InterruptFlags, Interrupt};
 use crate::joypad::JoypadRegister;
+use crate::memory_scan::{self, RamSnapshot, ScanChange};
 use crate::ppu::PPU;
+use crate::rtc::{RealTimeClock, RtcRegister, RtcState};
 use crate::serial::Serial;
+use crate::speed::SpeedSwitch;
 use crate::timer::Timer;
 
 const RAM_BANK_SIZE: usize = 0x2000; // 8KB
 const ROM_BANK_SIZE: usize = 0x4000; // 16KB
+pub const BOOT_ROM_SIZE: usize = 0x100; // 256 bytes, DMG only
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct MMU {
     data: Vec<u8>,
     header: CartHeader,
+    boot_rom: Option<Vec<u8>>, // kept after it is unmapped so that a reset boots again
+    boot_rom_mapped: bool, // mapped over 0x0000-0x00FF until 0xFF50 is written
     ram_banks: Vec<[u8; RAM_BANK_SIZE]>,
     ram_enabled: bool,
     rom_bank_register: usize,
-    ram_bank_register: usize,
+    ram_bank_register: usize, // on MBC1 also the upper 2 bits of the ROM bank
+    banking_mode: bool, // MBC1 only, 1 = the upper bits also apply to 0x0000-0x3FFF & select the RAM bank
+    rtc: Option<RealTimeClock>, // MBC3 timer carts only
+    rtc_register: Option<RtcRegister>, // mapped to 0xA000-0xBFFF instead of a RAM bank
     work_ram: [u8; 0x2000], // 8KB of work RAM (DMG mode only)
     high_ram: [u8; 0x7F], // 128 bytes of high RAM
     ppu: PPU,
@@ -34,6 +44,8 @@ pub struct MMU {
     interrupt_request: InterruptFlags,
     joypad_register: JoypadRegister,
     audio: Audio,
+    speed: SpeedSwitch,
+    cheats: Cheats,
 }
 
 impl MMU {
@@ -43,13 +55,34 @@ impl MMU {
         println!("{:?}", header);
 
         let ram_banks = Vec::from_iter((0..header.ram_banks()).map(|_| [0; RAM_BANK_SIZE]));
-        Ok(Self {
-            data: data.to_vec(),
+        Ok(Self::new(data.to_vec(), header, ram_banks))
+    }
+
+    /// Fails if the Nintendo logo in the header is wrong, as the boot ROM would refuse to start the cartridge
+    pub fn from_rom_strict(data: &[u8]) -> Result<Self, String> {
+        let mmu = Self::from_rom(data)?;
+        if !mmu.header.logo_valid() {
+            return Err("Invalid Nintendo logo in cartridge header".to_string());
+        }
+        Ok(mmu)
+    }
+
+    fn new(data: Vec<u8>, header: CartHeader, ram_banks: Vec<[u8; RAM_BANK_SIZE]>) -> Self {
+        let rtc = header.cart_type().has_timer().then(RealTimeClock::default);
+        let mut audio = Audio::default();
+        audio.set_cgb(header.cgb_mode() != CGBMode::None);
+        Self {
+            data,
             header,
+            boot_rom: None,
+            boot_rom_mapped: false,
             ram_banks,
             ram_enabled: false,
             rom_bank_register: 1,
             ram_bank_register: 0,
+            banking_mode: false,
+            rtc,
+            rtc_register: None,
             work_ram: [0; 0x2000],
             high_ram: [0; 0x7F],
             ppu: PPU::default(),
@@ -59,8 +92,28 @@ impl MMU {
             serial: Serial::default(),
             divider: Divider::default(),
             timer: Timer::default(),
-            audio: Audio::default(),
-        })
+            audio,
+            speed: SpeedSwitch::default(),
+            cheats: Cheats::default(),
+        }
+    }
+
+    /// Power cycle all memory & IO back to their initial state, the ROM, battery RAM & cheats are kept
+    pub fn reset(&mut self) {
+        let mut reset = Self::new(
+            std::mem::take(&mut self.data),
+            self.header.clone(),
+            std::mem::take(&mut self.ram_banks),
+        );
+        reset.cheats = std::mem::take(&mut self.cheats);
+        reset.serial.set_link(self.serial.take_link()); // the link cable stays connected
+        reset.rtc = self.rtc; // battery backed
+        reset.ppu.palette_mut().set_color_map(self.ppu.palette().color_map());
+        reset.audio.set_sample_rate(self.audio.sample_rate());
+        reset.audio.set_watermarks(self.audio.watermarks());
+        reset.boot_rom_mapped = self.boot_rom.is_some();
+        reset.boot_rom = self.boot_rom.take();
+        *self = reset;
     }
 
     pub fn header(&self) -> &CartHeader {
@@ -71,24 +124,104 @@ impl MMU {
         &self.data
     }
 
-    pub fn dump_sram(&self) -> Vec<u8> {
-        let mut data = Vec::with_capacity(self.ram_banks.len() * RAM_BANK_SIZE);
-        for bank in &self.ram_banks {
-            data.extend_from_slice(bank);
+    /// Map a DMG boot ROM over 0x0000-0x00FF, it unmaps itself by writing to 0xFF50 https://gbdev.io/pandocs/Power_Up_Sequence.html
+    pub fn load_boot_rom(&mut self, boot: &[u8]) -> Result<(), String> {
+        if boot.len() != BOOT_ROM_SIZE {
+            return Err(format!("Invalid boot ROM, expected {} bytes, got {}", BOOT_ROM_SIZE, boot.len()));
         }
-        data
+        self.boot_rom = Some(boot.to_vec());
+        self.boot_rom_mapped = true;
+        Ok(())
+    }
+
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom_mapped
+    }
+
+    /// 8KB of VRAM mapped to 0x8000-0x9FFF, bypassing PPU mode access restrictions
+    pub fn vram(&self) -> &[u8] {
+        self.ppu.vram()
+    }
+
+    pub fn vram_mut(&mut self) -> &mut [u8] {
+        self.ppu.vram_mut()
+    }
+
+    /// 160 bytes of OAM mapped to 0xFE00-0xFE9F, bypassing PPU mode access restrictions
+    pub fn oam(&self) -> &[u8] {
+        self.ppu.oam()
+    }
+
+    pub fn oam_mut(&mut self) -> &mut [u8] {
+        self.ppu.oam_mut()
+    }
+
+    /// 8KB of work RAM mapped to 0xC000-0xDFFF (and echoed at 0xE000-0xFDFF)
+    pub fn wram(&self) -> &[u8] {
+        &self.work_ram
+    }
+
+    pub fn wram_mut(&mut self) -> &mut [u8] {
+        &mut self.work_ram
+    }
+
+    /// 127 bytes of high RAM mapped to 0xFF80-0xFFFE
+    pub fn hram(&self) -> &[u8] {
+        &self.high_ram
+    }
+
+    pub fn hram_mut(&mut self) -> &mut [u8] {
+        &mut self.high_ram
+    }
+
+    /// Addresses in work RAM & high RAM holding the value
+    pub fn scan_u8(&self, value: u8) -> Vec<u16> {
+        memory_scan::scan_u8(self.ram_regions(), value)
+    }
+
+    /// Addresses in work RAM & high RAM holding the little endian value
+    pub fn scan_u16_le(&self, value: u16) -> Vec<u16> {
+        memory_scan::scan_u16_le(self.ram_regions(), value)
+    }
+
+    pub fn ram_snapshot(&self) -> RamSnapshot {
+        RamSnapshot::new(&self.work_ram, &self.high_ram)
+    }
+
+    /// Addresses in work RAM & high RAM whose value has changed since the previous snapshot,
+    /// repeat with the latest snapshot to narrow down the candidates
+    pub fn diff_scan(&self, previous: &RamSnapshot, change: ScanChange) -> Vec<u16> {
+        memory_scan::diff_scan(previous, &self.ram_snapshot(), change)
+    }
+
+    fn ram_regions(&self) -> [(u16, &[u8]); 2] {
+        [(memory_scan::WRAM_START, &self.work_ram), (memory_scan::HRAM_START, &self.high_ram)]
+    }
+
+    /// Battery backed cartridge RAM, every bank in order, sized by the header
+    pub fn save_ram(&self) -> &[u8] {
+        self.ram_banks.as_flattened()
     }
 
-    pub fn restore_sram(&mut self, data: &[u8]) -> Result<(), String> {
+    pub fn load_ram(&mut self, data: &[u8]) -> Result<(), String> {
         if data.len() != self.ram_banks.len() * RAM_BANK_SIZE {
             Err(format!("Cannot restore SRAM, expected {} bytes, got {}", self.ram_banks.len() * RAM_BANK_SIZE, data.len()))
         } else {
-            for (bank, chunk) in self.ram_banks.iter_mut().zip(data.chunks_exact(RAM_BANK_SIZE)) {
-                bank.copy_from_slice(chunk);
-            }
+            self.ram_banks.as_flattened_mut().copy_from_slice(data);
             Ok(())
         }
+    }
 
+    /// The running MBC3 clock, None if the cartridge has no RTC
+    pub fn rtc_state(&self) -> Option<RtcState> {
+        self.rtc.map(|rtc| rtc.state())
+    }
+
+    pub fn set_rtc_state(&mut self, state: RtcState) -> Result<(), String> {
+        let rtc = self.rtc.as_mut()
+            .ok_or_else(|| format!("Cartridge type {:?} has no RTC", self.header.cart_type()))?;
+        rtc.set_state(state);
+        Ok(())
     }
 
     /// replace rom data, only intended for reloading save states without rom data
@@ -108,6 +241,10 @@ impl MMU {
         &self.ppu
     }
 
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        &mut self.ppu
+    }
+
     pub fn audio(&self) -> &Audio {
         &self.audio
     }
@@ -116,6 +253,14 @@ impl MMU {
         &mut self.audio
     }
 
+    pub fn divider(&self) -> &Divider {
+        &self.divider
+    }
+
+    pub fn divider_mut(&mut self) -> &mut Divider {
+        &mut self.divider
+    }
+
     pub fn serial(&self) -> &Serial {
         &self.serial
     }
@@ -124,6 +269,48 @@ impl MMU {
         &mut self.serial
     }
 
+    /// Game Genie codes patch ROM reads, GameShark codes are written to RAM at the start of every VBlank
+    pub fn apply_cheat(&mut self, cheat: impl Into<Cheat>) {
+        self.cheats.add(cheat.into());
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+    }
+
+    fn apply_game_shark(&mut self) {
+        for index in 0..self.cheats.game_shark().len() {
+            let game_shark = self.cheats.game_shark()[index];
+            match game_shark.address {
+                // bypasses ram enable & the current bank register
+                0xA000..=0xBFFF => if let Some(ram_bank) = self.ram_banks.get_mut(game_shark.bank as usize) {
+                    ram_bank[(game_shark.address - 0xA000) as usize] = game_shark.value;
+                }
+                address => self.write(address, game_shark.value),
+            }
+        }
+    }
+
+    pub fn speed(&self) -> SpeedSwitch {
+        self.speed
+    }
+
+    /// Performs an armed CGB speed switch, returns false if no switch was armed so STOP should stop the CPU
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.speed.switch() {
+            return false;
+        }
+        self.reset_divider(); // DIV is reset by STOP regardless
+        self.audio.set_double_speed(self.speed.is_double_speed());
+        true
+    }
+
+    /// Resets the internal divider counter, which can clock the timer
+    fn reset_divider(&mut self) {
+        self.timer.reset_counter(self.divider.counter());
+        self.divider.set_counter(0);
+    }
+
     pub fn stop(&mut self) {
         self.divider.disable();
         self.timer.disable();
@@ -134,94 +321,136 @@ impl MMU {
         self.timer.enable();
     }
 
+    /// Advance the hardware by a single machine cycle part way through an instruction
+    pub fn tick(&mut self) {
+        self.update(MachineCycles::ONE);
+    }
+
     /// update internal state of the MMU, should be called every CPU cycle
+    /// the DMA, divider, timer & serial are advanced by exactly the specified number of machine cycles,
+    /// in CGB double speed mode the ppu, rtc & apu are advanced by half as many as they stay at normal speed
     pub fn update(&mut self, delta_machine_cycles: MachineCycles) {
         if delta_machine_cycles == MachineCycles::ZERO {
             return; // no cycles to update
         }
+        let normal_speed_cycles = self.speed.normal_speed_cycles(delta_machine_cycles);
 
         if let Some(transfer) = self.ppu.dma_mut().update(delta_machine_cycles) {
-            // DMA transfer is in progress, we need to copy data from ROM to OAM
-            for i in 0 .. 0xA0 {
-                let value = self.read(transfer.address + i);
-                self.ppu.write_oam(i, value);
+            // DMA transfer is in progress, copy one byte to OAM per machine cycle
+            for offset in transfer.offsets {
+                let value = self.read(transfer.address + offset);
+                self.ppu.oam_mut()[offset as usize] = value;
             }
         }
 
-
         self.serial.update(delta_machine_cycles);
+        let counter = self.divider.counter();
         let div_clocks = self.divider.update(delta_machine_cycles);
-        self.timer.update(delta_machine_cycles);
-        self.ppu.update(delta_machine_cycles);
-        self.audio.update(delta_machine_cycles, div_clocks);
+        self.timer.update(counter, delta_machine_cycles);
+        self.ppu.update(normal_speed_cycles);
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.update(normal_speed_cycles);
+        }
+        self.audio.update(normal_speed_cycles, div_clocks);
 
         // consume pending, an interrupt is triggered on a rising edge
-        for interrupt in InterruptType::all() {
+        for interrupt in Interrupt::all() {
             let interrupt_pending = match interrupt {
-                InterruptType::Joypad => self.joypad_register.consume_pending_activation(),
-                InterruptType::LcdStatus => self.ppu.lcd_status_mut().consume_pending_activation(),
-                InterruptType::VBlank => self.ppu.consume_pending_activation(),
-                InterruptType::Serial => self.serial.consume_pending_activation(),
-                InterruptType::Timer => self.timer.consume_pending_activation(),
+                Interrupt::Joypad => self.joypad_register.consume_pending_activation(),
+                Interrupt::LcdStat => self.ppu.lcd_status_mut().consume_pending_activation(),
+                Interrupt::VBlank => self.ppu.consume_pending_activation(),
+                Interrupt::Serial => self.serial.consume_pending_activation(),
+                Interrupt::Timer => self.timer.consume_pending_activation(),
             };
             if interrupt_pending {
                 self.interrupt_request.set_interrupt(interrupt);
+                if interrupt == Interrupt::VBlank {
+                    self.apply_game_shark();
+                }
             }
         }
     }
 
-    pub fn interrupt_pending(&self) -> Option<InterruptType> {
-        for interrupt in InterruptType::all() {
-            if self.interrupt_enable.is_set(interrupt) && self.interrupt_request.is_set(interrupt) {
-                return Some(interrupt);
-            }
-        }
-        None
+    pub fn interrupt_pending(&self) -> Option<Interrupt> {
+        Interrupt::highest_priority(self.interrupt_enable.get() & self.interrupt_request.get())
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_request.set_interrupt(interrupt);
     }
 
-    pub fn clear_interrupt_request(&mut self, interrupt: InterruptType) {
+    pub fn clear_interrupt_request(&mut self, interrupt: Interrupt) {
         self.interrupt_request.clear_interrupt(interrupt);
     }
 
-    pub fn check_interrupts(&mut self, interrupt_master_enable: bool, core_mode: CoreMode) -> Option<InterruptType> {
+    pub fn check_interrupts(&mut self, interrupt_master_enable: bool, core_mode: CoreMode) -> Option<Interrupt> {
         if !interrupt_master_enable || core_mode == CoreMode::Crash {
             return None;
         }
 
-        // check if enabled interrupts in order of priority
-        for interrupt in InterruptType::all() {
-            if core_mode == CoreMode::Stop && interrupt != InterruptType::Joypad {
-                continue; // In STOP mode, only JOYPAD interrupts are checked
-            }
+        let mut pending = self.interrupt_enable.get() & self.interrupt_request.get();
+        if core_mode == CoreMode::Stop {
+            pending &= Interrupt::Joypad.bit(); // In STOP mode, only JOYPAD interrupts are checked
+        }
 
-            if self.interrupt_enable.is_set(interrupt) && self.interrupt_request.is_set(interrupt) {
-                self.interrupt_request.clear_interrupt(interrupt);
-                return Some(interrupt);
-            }
+        // service the highest priority interrupt, lower priority requests stay pending
+        let interrupt = Interrupt::highest_priority(pending)?;
+        self.interrupt_request.clear_interrupt(interrupt);
+        Some(interrupt)
+    }
+
+    /// Read the memory map as the CPU sees it, only HRAM & IO registers are accessible during OAM DMA
+    /// https://gbdev.io/pandocs/OAM_DMA_Transfer.html
+    pub fn cpu_read(&self, address: u16) -> u8 {
+        if self.cpu_bus_blocked(address) {
+            return 0xFF;
         }
-        None
+        self.read(address)
+    }
+
+    /// Write the memory map as the CPU sees it, writes outside of HRAM & IO registers are ignored during OAM DMA
+    pub fn cpu_write(&mut self, address: u16, value: u8) {
+        if !self.cpu_bus_blocked(address) {
+            self.write(address, value);
+        }
+    }
+
+    fn cpu_bus_blocked(&self, address: u16) -> bool {
+        address < 0xFF00 && self.ppu.dma_blocks_bus()
     }
 
+    /// Read the memory map, ignoring any OAM DMA in progress
     pub fn read(&self, address: u16) -> u8 {
         // https://gbdev.io/pandocs/Memory_Map.html
         match address {
+            0x0000..=0x00FF if self.boot_rom_mapped => match self.boot_rom.as_ref() {
+                Some(boot_rom) => boot_rom[address as usize],
+                None => 0xFF,
+            },
             // rom bank 0
             0x0000..=0x3FFF => {
                 // https://gbdev.io/pandocs/MBC1.html#00003fff--rom-bank-x0-read-only
-                self.data[address as usize]
+                let bank_offset = self.lower_rom_bank() * ROM_BANK_SIZE;
+                self.cheats.patch_rom(address, self.data[bank_offset + address as usize])
             }
             // rom bank 1-n
             0x4000..=0x7FFF => {
                 // https://gbdev.io/pandocs/MBC1.html#40007fff--rom-bank-01-7f-read-only
-                let bank_offset = self.rom_bank_register * ROM_BANK_SIZE;
-                self.data[bank_offset + (address - 0x4000) as usize]
+                let bank_offset = self.upper_rom_bank() * ROM_BANK_SIZE;
+                self.cheats.patch_rom(address, self.data[bank_offset + (address - 0x4000) as usize])
             }
             // vram
             0x8000..=0x9FFF => self.ppu.read_vram(address - 0x8000),
             // external ram
+            0xA000..=0xBFFF if self.ram_enabled && self.rtc_register.is_some() => {
+                match (self.rtc.as_ref(), self.rtc_register) {
+                    (Some(rtc), Some(register)) => rtc.read(register),
+                    _ => 0xFF,
+                }
+            }
             0xA000..=0xBFFF if self.ram_enabled && self.header.ram_banks() > 0 => {
                 // https://gbdev.io/pandocs/MBC1.html#a000bfff--ram-bank-0003-if-any
-                let ram_bank = &self.ram_banks[self.ram_bank_register];
+                let ram_bank = &self.ram_banks[self.ram_bank()];
                 ram_bank[(address - 0xA000) as usize]
             }
             0xC000..=0xDFFF => self.work_ram[(address - 0xC000) as usize], // work ram
@@ -240,7 +469,7 @@ impl MMU {
             0xFF41 => self.ppu.lcd_status().stat(), // LCD status register
             0xFF42 => self.ppu.scroll().y, // SCY register
             0xFF43 => self.ppu.scroll().x, // SCX register
-            0xFF44 => self.ppu.lcd_status().ly(), // LY register (read-only)
+            0xFF44 => self.ppu.ly(), // LY register (read-only)
             0xFF45 => self.ppu.lcd_status().lyc(), // LYC register
             0xFF46 => 0, // DMA register (write-only, returns 0 when read)
             0xFF47 => self.ppu.palette().background().to_byte(), // BGP register
@@ -248,6 +477,14 @@ impl MMU {
             0xFF49 => self.ppu.palette().object1().to_byte(), // OBP1 register
             0xFF4A => self.ppu.window_position().y, // WY register
             0xFF4B => self.ppu.window_position().x, // WX register
+            0xFF4D if self.header.cgb_mode() != CGBMode::None => self.speed.key1(), // KEY1 register (CGB only)
+            0xFF68 if self.header.cgb_mode() != CGBMode::None => self.ppu.palette().cgb_background().spec(), // BCPS register (CGB only)
+            0xFF69 if self.header.cgb_mode() != CGBMode::None => self.ppu.read_cgb_palette(false), // BCPD register (CGB only)
+            0xFF6A if self.header.cgb_mode() != CGBMode::None => self.ppu.palette().cgb_object().spec(), // OCPS register (CGB only)
+            0xFF6B if self.header.cgb_mode() != CGBMode::None => self.ppu.read_cgb_palette(true), // OCPD register (CGB only)
+            0xFF76 if self.header.cgb_mode() != CGBMode::None => self.audio.pcm12(), // PCM12 register (CGB only)
+            0xFF77 if self.header.cgb_mode() != CGBMode::None => self.audio.pcm34(), // PCM34 register (CGB only)
+            0xFF76 | 0xFF77 => 0x00, // PCM registers read as 0 in DMG mode
             0xFF80..=0xFFFE => self.high_ram[(address - 0xFF80) as usize], // high ram
             0xFFFF => self.interrupt_enable.get(),
             _ => {
@@ -280,21 +517,63 @@ impl MMU {
                 // https://gbdev.io/pandocs/MBC1.html#00001fff--ram-enable-write-only
                 self.ram_enabled = value & 0xF == 0xA;
             }
-            0x2000..=0x3FFF if self.header.rom_banks() > 2 => {
+            0x2000..=0x2FFF if self.header.cart_type().is_mbc5() => {
+                // https://gbdev.io/pandocs/MBC5.html#2000-2fff---8-least-significant-bits-of-rom-bank-number-write-only
+                // unlike MBC1, bank 0 can be mapped to 0x4000-0x7FFF
+                self.rom_bank_register = (self.rom_bank_register & 0x100) | value as usize;
+            }
+            0x3000..=0x3FFF if self.header.cart_type().is_mbc5() => {
+                // https://gbdev.io/pandocs/MBC5.html#3000-3fff---9th-bit-of-rom-bank-number-write-only
+                self.rom_bank_register = (self.rom_bank_register & 0xFF) | ((value as usize & 0x01) << 8);
+            }
+            0x4000..=0x5FFF if self.header.cart_type().is_mbc5() => {
+                // https://gbdev.io/pandocs/MBC5.html#4000-5fff---ram-bank-number-write-only
+                // bit 3 switches the motor on rumble carts, the bank wraps around the RAM size
+                let mask = if self.header.cart_type().has_rumble() { 0x07 } else { 0x0F };
+                self.ram_bank_register = (value & mask) as usize % self.header.ram_banks().max(1);
+            }
+            0x2000..=0x3FFF if self.header.cart_type().is_mbc1() => {
                 // https://gbdev.io/pandocs/MBC1.html#20003fff--rom-bank-number-write-only
-                // TODO MBC1 should mask to 0x1F
+                // 0 is treated as 1 before the upper bits are applied, so banks 0x20, 0x40 & 0x60 cannot be mapped to 0x4000-0x7FFF
+                self.rom_bank_register = ((value & 0x1F) as usize).max(1);
+            }
+            0x4000..=0x5FFF if self.header.cart_type().is_mbc1() => {
+                // https://gbdev.io/pandocs/MBC1.html#40005fff--ram-bank-number--or--upper-bits-of-rom-bank-number-write-only
+                self.ram_bank_register = (value & 0x03) as usize;
+            }
+            0x6000..=0x7FFF if self.header.cart_type().is_mbc1() => {
+                // https://gbdev.io/pandocs/MBC1.html#60007fff--banking-mode-select-write-only
+                self.banking_mode = value & 0x01 != 0;
+            }
+            0x2000..=0x3FFF if self.header.rom_banks() > 2 => {
+                // https://gbdev.io/pandocs/MBC3.html#2000-3fff---rom-bank-number-write-only
                 self.rom_bank_register = ((value & 0x7F) as usize)
                     .min(self.header.rom_banks() - 1)
                     .max(1);
             }
+            0x4000..=0x5FFF if self.rtc.is_some() && RtcRegister::from_repr(value).is_some() => {
+                // https://gbdev.io/pandocs/MBC3.html#4000-5fff---ram-bank-number---or---rtc-register-select-write-only
+                self.rtc_register = RtcRegister::from_repr(value);
+            }
             0x4000..=0x5FFF if self.header.ram_banks() > 0 => {
                 // https://gbdev.io/pandocs/MBC1.html#40005fff--ram-bank-number--or--upper-bits-of-rom-bank-number-write-only
                 self.ram_bank_register = ((value & 0x03) as usize).min(self.header.ram_banks() - 1);
+                self.rtc_register = None;
+            }
+            0x6000..=0x7FFF if self.rtc.is_some() => {
+                // https://gbdev.io/pandocs/MBC3.html#6000-7fff---latch-clock-data-write-only
+                self.rtc.as_mut().unwrap().write_latch(value);
             }
             // vram
             0x8000..=0x9FFF => self.ppu.write_vram(address - 0x8000, value),
+            0xA000..=0xBFFF if self.ram_enabled && self.rtc_register.is_some() => {
+                if let (Some(rtc), Some(register)) = (self.rtc.as_mut(), self.rtc_register) {
+                    rtc.write(register, value);
+                }
+            }
             0xA000..=0xBFFF if self.ram_enabled && self.header.ram_banks() > 0 => {
-                let ram_bank = &mut self.ram_banks[self.ram_bank_register];
+                let ram_bank_index = self.ram_bank();
+                let ram_bank = &mut self.ram_banks[ram_bank_index];
                 ram_bank[(address - 0xA000) as usize] = value;
             }
             0xC000..=0xDFFF => self.work_ram[(address - 0xC000) as usize] = value, // work ram
@@ -303,17 +582,19 @@ impl MMU {
             0xFF00 => self.joypad_register.set(value),
             0xFF01 => self.serial.set_data(value), // serial data register
             0xFF02 => self.serial.set_control(value), // serial control register
-            0xFF04 => self.divider.reset(), // DIV register (reset on write)
+            0xFF04 => self.reset_divider(), // DIV register (reset on write)
             0xFF05 => self.timer.set_value(value), // TIMA register
             0xFF06 => self.timer.set_modulo(value), // TMA register
-            0xFF07 => self.timer.set_control(value), // TAC register
+            0xFF07 => self.timer.set_control(value, self.divider.counter()), // TAC register
             0xFF0F => self.interrupt_request.set(value), // IF register (interrupt request flags)
             0xFF10..=0xFF3F => self.audio.write(address, value),
-            0xFF40 => self.ppu.lcd_control_mut().set(value), // LCD control register
+            0xFF40 => self.ppu.set_lcd_control(value), // LCD control register
             0xFF41 => self.ppu.lcd_status_mut().set_stat(value), // LCD status register
             0xFF42 => self.ppu.scroll_mut().y = value, // SCY register
             0xFF43 => self.ppu.scroll_mut().x = value, // SCX register
-            0xFF44 => {} // LY register is read-only, writing to it has no effect
+            // LY register is read-only, writes are ignored on hardware. Some early docs claim a write resets LY,
+            // but that only happens by turning the LCD off & on.
+            0xFF44 => {}
             0xFF45 => self.ppu.lcd_status_mut().set_lyc(value), // LYC register
             0xFF46 => self.ppu.dma_mut().set(value), // DMA register (write-only)
             0xFF47 => self.ppu.palette_mut().background_mut().set_from_byte(value), // BGP register
@@ -321,6 +602,12 @@ impl MMU {
             0xFF49 => self.ppu.palette_mut().object1_mut().set_from_byte(value), // OBP1 register
             0xFF4A => self.ppu.window_position_mut().y = value, // WY register
             0xFF4B => self.ppu.window_position_mut().x = value, // WX register
+            0xFF4D if self.header.cgb_mode() != CGBMode::None => self.speed.set_key1(value), // KEY1 register (CGB only)
+            0xFF68 if self.header.cgb_mode() != CGBMode::None => self.ppu.palette_mut().cgb_background_mut().set_spec(value), // BCPS register (CGB only)
+            0xFF69 if self.header.cgb_mode() != CGBMode::None => self.ppu.write_cgb_palette(false, value), // BCPD register (CGB only)
+            0xFF6A if self.header.cgb_mode() != CGBMode::None => self.ppu.palette_mut().cgb_object_mut().set_spec(value), // OCPS register (CGB only)
+            0xFF6B if self.header.cgb_mode() != CGBMode::None => self.ppu.write_cgb_palette(true, value), // OCPD register (CGB only)
+            0xFF50 if value != 0 => self.boot_rom_mapped = false, // boot ROM disable, cannot be re-enabled
             0xFF80..=0xFFFE => self.high_ram[(address - 0xFF80) as usize] = value, // high ram
             0xFFFF => self.interrupt_enable.set(value),
             _ => {
@@ -329,6 +616,36 @@ impl MMU {
         }
     }
 
+    /// The ROM bank mapped to 0x0000-0x3FFF, only MBC1 in banking mode 1 maps anything other than bank 0
+    fn lower_rom_bank(&self) -> usize {
+        if self.header.cart_type().is_mbc1() && self.banking_mode {
+            (self.ram_bank_register << 5) % self.header.rom_banks().max(1)
+        } else {
+            0
+        }
+    }
+
+    /// The ROM bank mapped to 0x4000-0x7FFF, bank numbers wrap around the ROM size
+    fn upper_rom_bank(&self) -> usize {
+        let bank = if self.header.cart_type().is_mbc1() {
+            self.ram_bank_register << 5 | self.rom_bank_register
+        } else {
+            self.rom_bank_register
+        };
+        bank % self.header.rom_banks().max(1)
+    }
+
+    /// The RAM bank mapped to 0xA000-0xBFFF, MBC1 in banking mode 0 always maps bank 0
+    fn ram_bank(&self) -> usize {
+        if !self.header.cart_type().is_mbc1() {
+            self.ram_bank_register
+        } else if self.banking_mode {
+            self.ram_bank_register % self.header.ram_banks().max(1)
+        } else {
+            0
+        }
+    }
+
     pub fn write_u16_le(&mut self, address: u16, value: u16) {
         let [low, high] = value.to_le_bytes();
         self.write(address, low);
@@ -353,10 +670,15 @@ impl Encode for MMU {
     fn encode<__E: Encoder>(&self, encoder: &mut __E) -> Result<(), bincode::error::EncodeError> {
         // Encode::encode(&self.data, encoder)?; Do not encode the ROM data
         Encode::encode(&self.header, encoder)?;
+        Encode::encode(&self.boot_rom, encoder)?;
+        Encode::encode(&self.boot_rom_mapped, encoder)?;
         Encode::encode(&self.ram_banks, encoder)?;
         Encode::encode(&self.ram_enabled, encoder)?;
         Encode::encode(&self.rom_bank_register, encoder)?;
         Encode::encode(&self.ram_bank_register, encoder)?;
+        Encode::encode(&self.banking_mode, encoder)?;
+        Encode::encode(&self.rtc, encoder)?;
+        Encode::encode(&self.rtc_register, encoder)?;
         Encode::encode(&self.work_ram, encoder)?;
         Encode::encode(&self.high_ram, encoder)?;
         Encode::encode(&self.ppu, encoder)?;
@@ -367,6 +689,7 @@ impl Encode for MMU {
         Encode::encode(&self.interrupt_request, encoder)?;
         Encode::encode(&self.joypad_register, encoder)?;
         Encode::encode(&self.audio, encoder)?;
+        Encode::encode(&self.speed, encoder)?;
         core::result::Result::Ok(())
     }
 }
@@ -376,10 +699,15 @@ impl<__Context> Decode<__Context> for MMU {
         Ok(Self {
             data: vec![], // temporary empty data, will be filled in from the ROM
             header: Decode::decode(decoder)?,
+            boot_rom: Decode::decode(decoder)?,
+            boot_rom_mapped: Decode::decode(decoder)?,
             ram_banks: Decode::decode(decoder)?,
             ram_enabled: Decode::decode(decoder)?,
             rom_bank_register: Decode::decode(decoder)?,
             ram_bank_register: Decode::decode(decoder)?,
+            banking_mode: Decode::decode(decoder)?,
+            rtc: Decode::decode(decoder)?,
+            rtc_register: Decode::decode(decoder)?,
             work_ram: Decode::decode(decoder)?,
             high_ram: Decode::decode(decoder)?,
             ppu: Decode::decode(decoder)?,
@@ -389,7 +717,9 @@ impl<__Context> Decode<__Context> for MMU {
             interrupt_enable: Decode::decode(decoder)?,
             interrupt_request: Decode::decode(decoder)?,
             joypad_register: Decode::decode(decoder)?,
-            audio: Decode::decode(decoder)?
+            audio: Decode::decode(decoder)?,
+            speed: Decode::decode(decoder)?,
+            cheats: Cheats::default(),
         })
     }
 }
@@ -398,10 +728,15 @@ impl<'__de, __Context> BorrowDecode<'__de, __Context> for MMU {
         Ok(Self {
             data: vec![],
             header: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            boot_rom: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            boot_rom_mapped: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             ram_banks: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             ram_enabled: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             rom_bank_register: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             ram_bank_register: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            banking_mode: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            rtc: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            rtc_register: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             work_ram: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             high_ram: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             ppu: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
@@ -412,6 +747,8 @@ impl<'__de, __Context> BorrowDecode<'__de, __Context> for MMU {
             interrupt_request: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             joypad_register: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
             audio: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            speed: BorrowDecode::<'_, __Context>::borrow_decode(decoder)?,
+            cheats: Cheats::default(),
         })
     }
 }
@@ -420,6 +757,11 @@ impl<'__de, __Context> BorrowDecode<'__de, __Context> for MMU {
 mod tests {
     use crate::roms::blargg_cpu::ROM;
     use super::*;
+    use crate::lcd_status::LcdMode;
+    use crate::ppu::OamEntry;
+    use crate::joypad::JoypadButton;
+    use crate::cheats::{GameGenie, GameShark};
+    use std::collections::BTreeSet;
 
     #[test]
     fn mmu_enable_ram() {
@@ -428,6 +770,102 @@ mod tests {
         assert!(mmu.ram_enabled);
     }
 
+    #[test]
+    fn from_rom_strict() {
+        assert!(MMU::from_rom_strict(ROM).is_ok());
+
+        let rom = vec![0u8; 0x8000]; // no logo
+        assert!(MMU::from_rom(&rom).is_ok());
+        assert_eq!(MMU::from_rom_strict(&rom).unwrap_err(), "Invalid Nintendo logo in cartridge header");
+    }
+
+    #[test]
+    fn boot_rom() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        assert_eq!(mmu.load_boot_rom(&[0; 10]).unwrap_err(), "Invalid boot ROM, expected 256 bytes, got 10");
+
+        let boot = [0xAA; BOOT_ROM_SIZE];
+        mmu.load_boot_rom(&boot).unwrap();
+        assert!(mmu.boot_rom_mapped());
+        assert_eq!(mmu.read(0x0000), 0xAA);
+        assert_eq!(mmu.read(0x00FF), 0xAA);
+        assert_eq!(mmu.read(0x0100), ROM[0x0100]); // the cart header is always visible
+
+        mmu.write(0xFF50, 0x00); // ignored
+        assert!(mmu.boot_rom_mapped());
+        mmu.write(0xFF50, 0x01);
+        assert!(!mmu.boot_rom_mapped());
+        assert_eq!(mmu.read(0x0000), ROM[0x0000]);
+
+        mmu.reset();
+        assert!(mmu.boot_rom_mapped());
+        assert_eq!(mmu.read(0x0000), 0xAA);
+    }
+
+    #[test]
+    fn save_ram_round_trip() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0147] = 0x03; // MBC1 + RAM + battery
+        rom[0x0149] = 0x03; // 32KB RAM, 4 banks
+        let mut mmu = MMU::from_rom(&rom).unwrap();
+        assert_eq!(mmu.save_ram().len(), 4 * RAM_BANK_SIZE);
+
+        mmu.write(0x0000, 0x0A);
+        mmu.write(0x6000, 0x01); // RAM banking mode
+        for bank in 0..4 {
+            mmu.write(0x4000, bank);
+            mmu.write(0xA000, 0x10 + bank);
+            mmu.write(0xBFFF, 0x20 + bank);
+        }
+        let save = mmu.save_ram().to_vec();
+        assert_eq!(save[3 * RAM_BANK_SIZE], 0x13);
+        assert_eq!(save[4 * RAM_BANK_SIZE - 1], 0x23);
+
+        let mut reloaded = MMU::from_rom(&rom).unwrap();
+        reloaded.load_ram(&save).unwrap();
+        assert_eq!(reloaded.save_ram(), &save[..]);
+        reloaded.write(0x0000, 0x0A);
+        reloaded.write(0x6000, 0x01);
+        reloaded.write(0x4000, 2);
+        assert_eq!(reloaded.read(0xA000), 0x12);
+
+        assert_eq!(reloaded.load_ram(&save[..RAM_BANK_SIZE]), Err("Cannot restore SRAM, expected 32768 bytes, got 8192".to_string()));
+        assert!(MMU::from_rom(ROM).unwrap().save_ram().is_empty());
+    }
+
+    #[test]
+    fn mbc3_rtc() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0147] = 0x10; // MBC3 + timer + RAM + battery
+        rom[0x0149] = 0x03; // 32KB RAM
+        let mut mmu = MMU::from_rom(&rom).unwrap();
+        assert_eq!(mmu.rtc_state(), Some(RtcState::default()));
+        mmu.set_rtc_state(RtcState { seconds: 30, minutes: 59, hours: 23, days: 0x1FF, halt: false, day_carry: false }).unwrap();
+
+        mmu.write(0x0000, 0x0A);
+        mmu.write(0x4000, 0x01);
+        mmu.write(0xA000, 0x42); // RAM bank 1
+        mmu.update(MachineCycles::from_hz(1) * 30); // midnight on the last day
+
+        mmu.write(0x4000, 0x0C); // select day high
+        assert_eq!(mmu.read(0xA000), 0x00); // nothing latched yet
+        mmu.write(0x6000, 0x00);
+        mmu.write(0x6000, 0x01);
+        assert_eq!(mmu.read(0xA000), 0x80); // day carry, day bit 8 cleared
+        mmu.write(0x4000, 0x08);
+        assert_eq!(mmu.read(0xA000), 0);
+
+        // halt & carry survive a day high write, RAM banks are still reachable
+        mmu.write(0x4000, 0x0C);
+        mmu.write(0xA000, 0xC1);
+        assert_eq!(mmu.rtc_state(), Some(RtcState { days: 0x100, halt: true, day_carry: true, ..RtcState::default() }));
+        mmu.write(0x4000, 0x01);
+        assert_eq!(mmu.read(0xA000), 0x42);
+
+        assert!(MMU::from_rom(ROM).unwrap().rtc_state().is_none());
+        assert_eq!(MMU::from_rom(ROM).unwrap().set_rtc_state(RtcState::default()), Err("Cartridge type MBC1 has no RTC".to_string()));
+    }
+
     #[test]
     fn mmu_rom_banks() {
         let mut mmu = MMU::from_rom(ROM).unwrap();
@@ -442,6 +880,120 @@ mod tests {
         assert_eq!(mmu.read(0x4244), 0xBE); // read from ROM bank 2, different to rom bank 1
     }
 
+    #[test]
+    fn mbc1_banking_modes() {
+        // 2MB MBC1, each ROM bank starts with its bank number
+        let mut rom = vec![0u8; 128 * ROM_BANK_SIZE];
+        for bank in 1..128 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom[0x0147] = 0x01; // MBC1
+        rom[0x0148] = 0x06; // 2MB
+        let mut mmu = MMU::from_rom(&rom).unwrap();
+        assert_eq!(mmu.read(0x4000), 1);
+
+        mmu.write(0x2000, 0x00);
+        assert_eq!(mmu.read(0x4000), 1); // bank 0 is mapped as bank 1
+        mmu.write(0x2000, 0x20);
+        assert_eq!(mmu.read(0x4000), 1); // only 5 bits are used
+        mmu.write(0x2000, 0x1F);
+        assert_eq!(mmu.read(0x4000), 0x1F);
+
+        // mode 0, the upper bits only apply to 0x4000-0x7FFF
+        mmu.write(0x4000, 0x01);
+        assert_eq!(mmu.read(0x4000), 0x3F);
+        assert_eq!(mmu.read(0x0000), 0);
+        mmu.write(0x2000, 0x00);
+        assert_eq!(mmu.read(0x4000), 0x21); // 0x20 cannot be mapped
+
+        // mode 1, the upper bits also apply to 0x0000-0x3FFF
+        mmu.write(0x6000, 0x01);
+        assert_eq!(mmu.read(0x0000), 0x20);
+        assert_eq!(mmu.read(0x4000), 0x21);
+        mmu.write(0x4000, 0x03);
+        assert_eq!(mmu.read(0x0000), 0x60);
+        assert_eq!(mmu.read(0x4000), 0x61);
+
+        mmu.write(0x6000, 0x00);
+        assert_eq!(mmu.read(0x0000), 0);
+        assert_eq!(mmu.read(0x4000), 0x61);
+    }
+
+    #[test]
+    fn mbc1_ram_banking_mode() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0147] = 0x03; // MBC1 + RAM + battery
+        rom[0x0149] = 0x03; // 32KB RAM, 4 banks
+        let mut mmu = MMU::from_rom(&rom).unwrap();
+        mmu.write(0x0000, 0x0A);
+
+        // mode 0 always maps RAM bank 0
+        mmu.write(0x4000, 0x02);
+        mmu.write(0xA000, 0x42);
+        assert_eq!(mmu.save_ram()[0], 0x42);
+        assert_eq!(mmu.read(0x4000), 0x00); // the small ROM ignores the upper bits
+
+        mmu.write(0x6000, 0x01);
+        assert_eq!(mmu.read(0xA000), 0x00);
+        mmu.write(0xA000, 0x24);
+        assert_eq!(mmu.save_ram()[2 * RAM_BANK_SIZE], 0x24);
+        assert_eq!(mmu.read(0x0000), 0x00); // bank 0x40 wraps to bank 0
+    }
+
+    #[test]
+    fn mbc5_banks() {
+        // 8MB MBC5 with 128KB RAM, each ROM bank starts with its bank number
+        let mut rom = vec![0u8; 512 * ROM_BANK_SIZE];
+        for bank in 0..512 {
+            rom[bank * ROM_BANK_SIZE..bank * ROM_BANK_SIZE + 2].copy_from_slice(&(bank as u16).to_le_bytes());
+        }
+        rom[0x0147] = 0x1B; // MBC5 + RAM + battery
+        rom[0x0148] = 0x08; // 8MB
+        rom[0x0149] = 0x04; // 128KB, 16 banks
+        let mut mmu = MMU::from_rom(&rom).unwrap();
+        assert_eq!(mmu.read_u16_le(0x4000), 1);
+
+        mmu.write(0x2000, 0x00); // bank 0 can be mapped
+        assert_eq!(mmu.read_u16_le(0x4000), 0);
+        mmu.write(0x2FFF, 0x42);
+        assert_eq!(mmu.read_u16_le(0x4000), 0x42);
+        mmu.write(0x3000, 0x01); // 9th bit
+        assert_eq!(mmu.read_u16_le(0x4000), 0x142);
+        mmu.write(0x2000, 0xFF);
+        assert_eq!(mmu.read_u16_le(0x4000), 0x1FF);
+        mmu.write(0x3FFF, 0x00);
+        assert_eq!(mmu.read_u16_le(0x4000), 0xFF);
+        assert_eq!(mmu.read_u16_le(0x0000), 0); // bank 0 is always at 0x0000
+
+        mmu.write(0x0000, 0x0A);
+        for bank in 0..16 {
+            mmu.write(0x4000, bank);
+            mmu.write(0xA000, bank);
+        }
+        for bank in 0..16 {
+            mmu.write(0x4000, bank);
+            assert_eq!(mmu.read(0xA000), bank);
+        }
+        mmu.write(0x4000, 0x13); // only 4 bits are used
+        assert_eq!(mmu.read(0xA000), 3);
+    }
+
+    #[test]
+    fn mbc5_wraps_rom_size() {
+        // 256KB MBC5, 16 banks
+        let mut rom = vec![0u8; 16 * ROM_BANK_SIZE];
+        for bank in 0..16 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom[0x0147] = 0x19;
+        rom[0x0148] = 0x03;
+        let mut mmu = MMU::from_rom(&rom).unwrap();
+        mmu.write(0x2000, 0x13);
+        assert_eq!(mmu.read(0x4000), 3);
+        mmu.write(0x3000, 0x01);
+        assert_eq!(mmu.read(0x4000), 3);
+    }
+
     #[test]
     fn mmu_work_ram() {
         let mut mmu = MMU::from_rom(ROM).unwrap();
@@ -452,6 +1004,52 @@ mod tests {
         assert_eq!(mmu.read(0xC000), 0x24); // Echo RAM mirrors work RAM
     }
 
+    #[test]
+    fn mmu_echo_ram() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xC005, 0x42);
+        assert_eq!(mmu.read(0xE005), 0x42);
+        mmu.write(0xE010, 0x99);
+        assert_eq!(mmu.read(0xC010), 0x99);
+        mmu.write(0xFDFF, 0x77); // last echo address mirrors 0xDDFF
+        assert_eq!(mmu.read(0xDDFF), 0x77);
+    }
+
+    #[test]
+    fn scan_u8() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xD123, 0xA5);
+        assert_eq!(mmu.scan_u8(0xA5), vec![0xD123]);
+        mmu.write(0xFF90, 0xA5);
+        assert_eq!(mmu.scan_u8(0xA5), vec![0xD123, 0xFF90]);
+    }
+
+    #[test]
+    fn scan_u16_le() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write_u16_le(0xC100, 0x1234);
+        mmu.write_u16_le(0xFFFD, 0x1234);
+        assert_eq!(mmu.scan_u16_le(0x1234), vec![0xC100, 0xFFFD]);
+        mmu.write(0xDFFF, 0x34);
+        mmu.write(0xFF80, 0x12);
+        assert_eq!(mmu.scan_u16_le(0x1234), vec![0xC100, 0xFFFD]); // not matched across regions
+    }
+
+    #[test]
+    fn diff_scan() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xC010, 10);
+        mmu.write(0xC020, 10);
+        let snapshot = mmu.ram_snapshot();
+        mmu.write(0xC010, 11);
+        mmu.write(0xC020, 9);
+        mmu.write(0xFF85, 1);
+        assert_eq!(mmu.diff_scan(&snapshot, ScanChange::Increased), vec![0xC010, 0xFF85]);
+        assert_eq!(mmu.diff_scan(&snapshot, ScanChange::Decreased), vec![0xC020]);
+        assert_eq!(mmu.diff_scan(&snapshot, ScanChange::Changed), vec![0xC010, 0xC020, 0xFF85]);
+        assert!(!mmu.diff_scan(&snapshot, ScanChange::Unchanged).contains(&0xC010));
+    }
+
     #[test]
     fn mmu_high_ram() {
         let mut mmu = MMU::from_rom(ROM).unwrap();
@@ -461,6 +1059,148 @@ mod tests {
         assert_eq!(mmu.read(0xFFFE), 0xCD);
     }
 
+    #[test]
+    fn mmu_memory_regions() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        assert_eq!(mmu.vram().len(), 0x2000);
+        assert_eq!(mmu.oam().len(), 0xA0);
+        assert_eq!(mmu.wram().len(), 0x2000);
+        assert_eq!(mmu.hram().len(), 0x7F);
+
+        let tile = [0x3C, 0x7E, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x7E, 0x5E, 0x7E, 0x0A, 0x7C, 0x56, 0x38, 0x7C];
+        for (index, &byte) in tile.iter().enumerate() {
+            mmu.write(0x8010 + index as u16, byte);
+        }
+        assert_eq!(&mmu.vram()[0x10..0x20], &tile);
+
+        mmu.oam_mut()[4] = 0x42;
+        assert_eq!(mmu.read(0xFE04), 0x42);
+        mmu.write(0xE123, 0x24); // echo RAM
+        assert_eq!(mmu.wram()[0x123], 0x24);
+        mmu.hram_mut()[0x7E] = 0xCD;
+        assert_eq!(mmu.read(0xFFFE), 0xCD);
+    }
+
+    #[test]
+    fn mmu_oam_entries() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        // second entry: y = 20, x = 10 on screen, tile 0x42, priority, x flip & OBP1
+        for (index, byte) in [36, 18, 0x42, 0xB0].into_iter().enumerate() {
+            mmu.write(0xFE04 + index as u16, byte);
+        }
+
+        let entries = mmu.ppu().oam_entries();
+        assert_eq!(entries.len(), 40);
+        assert_eq!(entries[0], OamEntry { y: -16, x: -8, ..OamEntry::default() }); // zeroed OAM is off screen
+        assert_eq!(entries[1], OamEntry {
+            y: 20,
+            x: 10,
+            tile: 0x42,
+            priority: true,
+            y_flip: false,
+            x_flip: true,
+            palette: true,
+        });
+        assert_eq!(entries[1].flags(), 0xB0);
+        assert_eq!(entries[0].flags(), 0x00);
+    }
+
+    #[test]
+    fn mmu_ppu_access_restrictions() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0x8000, 0x42);
+        mmu.write(0xFE00, 0x24);
+        mmu.write(0xFF80, 0xAB);
+
+        mmu.ppu_mut().lcd_status_mut().set_mode(LcdMode::Drawing);
+        assert_eq!(mmu.read(0x8000), 0xFF);
+        assert_eq!(mmu.read(0xFE00), 0xFF);
+        assert_eq!(mmu.read(0xFF80), 0xAB); // HRAM is always accessible
+        mmu.write(0x8000, 0x99); // ignored
+        assert_eq!(mmu.vram()[0], 0x42);
+
+        mmu.ppu_mut().lcd_status_mut().set_mode(LcdMode::OAM);
+        assert_eq!(mmu.read(0x8000), 0x42);
+        assert_eq!(mmu.read(0xFE00), 0xFF);
+
+        mmu.ppu_mut().lcd_status_mut().set_mode(LcdMode::Drawing);
+        mmu.ppu_mut().set_restrict_access(false);
+        assert_eq!(mmu.read(0x8000), 0x42);
+        assert_eq!(mmu.read(0xFE00), 0x24);
+    }
+
+    #[test]
+    fn oam_dma_blocks_bus() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        for offset in 0..0xA0 {
+            mmu.write(0xC000 + offset, offset as u8 + 1);
+        }
+        mmu.write(0xFF80, 0xAB);
+
+        mmu.write(0xFF46, 0xC0);
+        assert_eq!(mmu.cpu_read(0xC000), 0xFF);
+        assert_eq!(mmu.cpu_read(0x0100), 0xFF);
+        assert_eq!(mmu.cpu_read(0xFF80), 0xAB); // HRAM is always accessible
+        assert_eq!(mmu.read(0xC000), 0x01); // tools & debuggers are not blocked
+
+        mmu.cpu_write(0xC0A0, 0x12);
+        assert_eq!(mmu.read(0xC0A0), 0x00);
+        mmu.cpu_write(0xFF81, 0x34);
+        assert_eq!(mmu.read(0xFF81), 0x34);
+
+        // one byte is copied per machine cycle
+        mmu.update(MachineCycles::from_m(80));
+        assert_eq!(mmu.oam()[79], 80);
+        assert_eq!(mmu.oam()[80], 0);
+
+        mmu.update(MachineCycles::from_m(79));
+        assert_eq!(mmu.cpu_read(0xC000), 0xFF);
+        assert_eq!(mmu.oam()[158], 159);
+        assert_eq!(mmu.oam()[159], 0);
+
+        mmu.tick();
+        assert_eq!(mmu.cpu_read(0xC000), 0x01);
+        assert!(mmu.oam().iter().enumerate().all(|(offset, &value)| value == offset as u8 + 1));
+    }
+
+    #[test]
+    fn request_interrupt() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xFFFF, Interrupt::Timer.bit());
+        assert_eq!(mmu.check_interrupts(true, CoreMode::Normal), None);
+
+        mmu.request_interrupt(Interrupt::Timer);
+        assert_eq!(mmu.read(0xFF0F) & 0x1F, 0x04);
+        let interrupt = mmu.check_interrupts(true, CoreMode::Normal);
+        assert_eq!(interrupt, Some(Interrupt::Timer));
+        assert_eq!(interrupt.unwrap().address(), 0x0050);
+        assert_eq!(mmu.check_interrupts(true, CoreMode::Normal), None); // request is cleared
+    }
+
+    #[test]
+    fn interrupt_priority() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xFFFF, 0x1F);
+        mmu.request_interrupt(Interrupt::Joypad);
+        mmu.request_interrupt(Interrupt::Timer);
+        mmu.request_interrupt(Interrupt::VBlank);
+        mmu.request_interrupt(Interrupt::LcdStat);
+
+        assert_eq!(mmu.check_interrupts(true, CoreMode::Normal), Some(Interrupt::VBlank));
+        assert_eq!(mmu.read(0xFF0F) & 0x1F, 0x16); // only the VBlank request is cleared
+        assert_eq!(mmu.check_interrupts(true, CoreMode::Normal), Some(Interrupt::LcdStat));
+        assert_eq!(mmu.check_interrupts(true, CoreMode::Normal), Some(Interrupt::Timer));
+        assert_eq!(mmu.check_interrupts(true, CoreMode::Normal), Some(Interrupt::Joypad));
+        assert_eq!(mmu.check_interrupts(true, CoreMode::Normal), None);
+
+        // disabled interrupts are skipped but stay requested
+        mmu.write(0xFFFF, Interrupt::Serial.bit());
+        mmu.request_interrupt(Interrupt::VBlank);
+        mmu.request_interrupt(Interrupt::Serial);
+        assert_eq!(mmu.check_interrupts(true, CoreMode::Normal), Some(Interrupt::Serial));
+        assert_eq!(mmu.read(0xFF0F) & 0x1F, 0x01);
+    }
+
     #[test]
     fn mmu_interrupt_flags() {
         let mut mmu = MMU::from_rom(ROM).unwrap();
@@ -478,4 +1218,273 @@ mod tests {
         mmu.write(0xFFFF, 0x00); // Disable all interrupts
         assert_eq!(mmu.interrupt_enable.get(), 0x00);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn divider_cadence() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xFF04, 0x00); // reset DIV
+
+        // DIV increments at 16384 Hz, every 64 machine cycles
+        mmu.update(MachineCycles::from_m(63));
+        assert_eq!(mmu.read(0xFF04), 0);
+        mmu.update(MachineCycles::ONE);
+        assert_eq!(mmu.read(0xFF04), 1);
+
+        // stepping one machine cycle at a time is equivalent
+        for _ in 0..64 * 3 {
+            mmu.update(MachineCycles::ONE);
+        }
+        assert_eq!(mmu.read(0xFF04), 4);
+    }
+
+    #[test]
+    fn double_speed() {
+        let mut rom = ROM.to_vec();
+        rom[0x0143] = 0x00; // DMG only
+        let mut mmu = MMU::from_rom(&rom).unwrap();
+        mmu.write(0xFF4D, 0x01);
+        assert_eq!(mmu.read(0xFF4D), 0xFF); // KEY1 is not mapped on DMG carts
+        assert!(!mmu.switch_speed());
+
+        rom[0x0143] = 0xC0; // CGB only
+        let mut mmu = MMU::from_rom(&rom).unwrap();
+        mmu.write(0xFF04, 0x00);
+        mmu.write(0xFF4D, 0x01);
+        assert!(mmu.switch_speed());
+        assert_eq!(mmu.read(0xFF4D), 0xFE);
+
+        // the divider runs at CPU speed, a scanline takes twice as many CPU cycles
+        mmu.write(0xFF40, 0x00);
+        mmu.write(0xFF40, 0x80); // restart the LCD at the start of line 0
+        let mut cycles = 0;
+        while mmu.read(0xFF44) == 0 {
+            mmu.update(MachineCycles::ONE);
+            cycles += 1;
+        }
+        assert_eq!(cycles, 2 * 114);
+        assert_eq!(mmu.read(0xFF04), 3);
+    }
+
+    #[test]
+    fn pcm_registers() {
+        let play = |cgb_flag: u8| {
+            let mut rom = ROM.to_vec();
+            rom[0x0143] = cgb_flag;
+            let mut mmu = MMU::from_rom(&rom).unwrap();
+            mmu.write(0xFF26, 0x80); // APU on
+            mmu.write(0xFF11, 0x80); // channel 1, 50% duty
+            mmu.write(0xFF12, 0xF0); // channel 1, volume 15
+            mmu.write(0xFF13, 0xF0); // channel 1, period 0x7F0
+            mmu.write(0xFF14, 0x87); // channel 1, trigger
+            mmu.write(0xFF21, 0xA0); // channel 4, volume 10
+            mmu.write(0xFF23, 0x80); // channel 4, trigger
+
+            let mut pcm12 = BTreeSet::new();
+            let mut pcm34 = BTreeSet::new();
+            for _ in 0..1000 {
+                mmu.update(MachineCycles::ONE);
+                pcm12.insert(mmu.read(0xFF76));
+                pcm34.insert(mmu.read(0xFF77));
+            }
+            (pcm12, pcm34)
+        };
+
+        let (pcm12, pcm34) = play(0x80);
+        assert_eq!(pcm12, BTreeSet::from([0x00, 0x0F]));
+        assert_eq!(pcm34, BTreeSet::from([0x00, 0xA0]));
+
+        let (pcm12, pcm34) = play(0x00);
+        assert_eq!(pcm12, BTreeSet::from([0x00]));
+        assert_eq!(pcm34, BTreeSet::from([0x00]));
+    }
+
+    #[test]
+    fn cgb_palettes() {
+        let mut rom = ROM.to_vec();
+        rom[0x0143] = 0x00; // DMG only
+        let mut mmu = MMU::from_rom(&rom).unwrap();
+        mmu.write(0xFF68, 0x80);
+        assert_eq!(mmu.read(0xFF68), 0xFF); // BCPS is not mapped on DMG carts
+
+        rom[0x0143] = 0x80; // CGB enhanced
+        let mut mmu = MMU::from_rom(&rom).unwrap();
+        mmu.write(0xFF40, 0x00); // lcd off, palette RAM is always accessible
+        mmu.write(0xFF68, 0x82); // auto increment from palette 0 color 1
+        assert_eq!(mmu.read(0xFF68), 0xC2);
+        mmu.write(0xFF69, 0x1F);
+        mmu.write(0xFF69, 0x00);
+        assert_eq!(mmu.read(0xFF68), 0xC4);
+        assert_eq!(mmu.ppu().palette().cgb_background().color(0, 1), 0x001F);
+        assert_eq!(mmu.ppu().palette().cgb_background().rgb(0, 1), image::Rgb([0xFF, 0x00, 0x00]));
+        assert_eq!(mmu.ppu().palette().cgb_background().rgb(0, 0), image::Rgb([0xFF, 0xFF, 0xFF]));
+
+        mmu.write(0xFF68, 0x02); // reads never increment
+        assert_eq!(mmu.read(0xFF69), 0x1F);
+        assert_eq!(mmu.read(0xFF69), 0x1F);
+
+        // object palettes are separate
+        mmu.write(0xFF6A, 0xBF); // palette 7 color 3 high byte
+        mmu.write(0xFF6B, 0x7C);
+        assert_eq!(mmu.read(0xFF6A), 0xC0); // wraps around
+        assert_eq!(mmu.ppu().palette().cgb_object().color(7, 3) >> 8, 0x7C);
+        assert_eq!(mmu.ppu().palette().cgb_background().color(7, 3), 0x7FFF);
+
+        // inaccessible in mode 3 but writes still increment
+        mmu.write(0xFF40, 0x80);
+        while mmu.ppu().lcd_status().mode() != LcdMode::Drawing {
+            mmu.update(MachineCycles::ONE);
+        }
+        mmu.write(0xFF68, 0x80);
+        assert_eq!(mmu.read(0xFF69), 0xFF);
+        mmu.write(0xFF69, 0x00);
+        assert_eq!(mmu.read(0xFF68), 0xC1);
+        assert_eq!(mmu.ppu().palette().cgb_background().color(0, 0), 0x7FFF);
+    }
+
+    #[test]
+    fn joypad_interrupt() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xFF0F, 0x00);
+        mmu.write(0xFF00, 0x10); // select buttons
+        mmu.joypad_mut().press_button(JoypadButton::A);
+        mmu.update(MachineCycles::ONE);
+        assert_eq!(mmu.read(0xFF0F) & 0x10, 0x10);
+
+        // not requested again while held
+        mmu.write(0xFF0F, 0x00);
+        mmu.joypad_mut().press_button(JoypadButton::A);
+        mmu.update(MachineCycles::ONE);
+        assert_eq!(mmu.read(0xFF0F) & 0x10, 0);
+    }
+
+    #[test]
+    fn game_genie() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        let original = mmu.read(0x0150);
+        let patched = original.wrapping_add(1);
+        mmu.apply_cheat(GameGenie { address: 0x0150, value: patched, compare: Some(original) });
+        assert_eq!(mmu.read(0x0150), patched);
+        assert_eq!(mmu.read(0x0151), mmu.data()[0x0151]);
+
+        // compare value does not match
+        mmu.clear_cheats();
+        mmu.apply_cheat(GameGenie { address: 0x0150, value: patched, compare: Some(original.wrapping_add(2)) });
+        assert_eq!(mmu.read(0x0150), original);
+
+        // parsed: 3E at 0x4A17 with no compare
+        mmu.apply_cheat(GameGenie::parse("3EA-17B").unwrap());
+        assert_eq!(mmu.read(0x4A17), 0x3E);
+    }
+
+    #[test]
+    fn game_shark() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xFF40, 0x80); // enable lcd
+        mmu.apply_cheat(GameShark::parse("010F48D1").unwrap());
+        assert_eq!(mmu.read(0xD148), 0x00);
+
+        let frame = MachineCycles::from_m(154 * 456 / 4);
+        for _ in 0..frame.m_cycles() {
+            mmu.update(MachineCycles::ONE);
+        }
+        assert_eq!(mmu.read(0xD148), 0x0F);
+
+        // the game overwrites it, but it's patched again every frame
+        mmu.write(0xD148, 0x01);
+        for _ in 0..frame.m_cycles() {
+            mmu.update(MachineCycles::ONE);
+        }
+        assert_eq!(mmu.read(0xD148), 0x0F);
+    }
+
+    #[test]
+    fn ly_sweep() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xFF40, 0x80); // enable lcd
+        mmu.write(0xFF0F, 0x00);
+
+        let mut sequence = vec![mmu.read(0xFF44)];
+        let mut vblank_ly = None;
+        for _ in 0..154 * 456 / 4 {
+            mmu.update(MachineCycles::ONE);
+            let ly = mmu.read(0xFF44);
+            if ly != *sequence.last().unwrap() {
+                sequence.push(ly);
+            }
+            if vblank_ly.is_none() && mmu.read(0xFF0F) & 0x01 != 0 {
+                vblank_ly = Some(ly);
+            }
+        }
+
+        assert_eq!(sequence, (0..=153).chain([0]).collect::<Vec<u8>>());
+        assert_eq!(vblank_ly, Some(144));
+    }
+
+    #[test]
+    fn ly_is_read_only() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xFF40, 0x80); // enable lcd
+        for _ in 0..10 * 456 / 4 {
+            mmu.update(MachineCycles::ONE);
+        }
+        assert_eq!(mmu.read(0xFF44), 10);
+        mmu.write(0xFF44, 0x00);
+        assert_eq!(mmu.read(0xFF44), 10);
+        mmu.write(0xFF44, 0x50);
+        assert_eq!(mmu.ppu().ly(), 10);
+    }
+
+    #[test]
+    fn reset() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xC000, 0x12);
+        mmu.write(0xFF80, 0x34);
+        mmu.write(0xFF47, 0x1B);
+        mmu.update(MachineCycles::from_m(1000));
+
+        mmu.reset();
+        assert_eq!(mmu, MMU::from_rom(ROM).unwrap());
+    }
+
+    #[test]
+    fn timer_follows_divider() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write(0xFF04, 0x00);
+        mmu.write(0xFF05, 0x00);
+        mmu.write(0xFF07, 0b110); // every 16 machine cycles
+
+        // TIMA is clocked in phase with the divider counter
+        mmu.update(MachineCycles::from_m(15));
+        assert_eq!(mmu.read(0xFF05), 0);
+        mmu.update(MachineCycles::ONE);
+        assert_eq!(mmu.read(0xFF05), 1);
+
+        // resetting DIV while bit 5 is set is a falling edge, which clocks TIMA early
+        mmu.update(MachineCycles::from_m(8));
+        assert_eq!(mmu.divider().counter(), 0x60);
+        mmu.write(0xFF04, 0x00);
+        assert_eq!(mmu.read(0xFF05), 2);
+        assert_eq!(mmu.divider().counter(), 0);
+
+        // resetting DIV while bit 5 is clear does not, the next tick is delayed instead
+        mmu.update(MachineCycles::from_m(7));
+        mmu.write(0xFF04, 0x00);
+        assert_eq!(mmu.read(0xFF05), 2);
+        mmu.update(MachineCycles::from_m(15));
+        assert_eq!(mmu.read(0xFF05), 2);
+        mmu.update(MachineCycles::ONE);
+        assert_eq!(mmu.read(0xFF05), 3);
+    }
+
+    #[test]
+    fn divider_counter() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.divider_mut().set_counter(0);
+        for m_cycles in 1..=1000u16 {
+            mmu.update(MachineCycles::ONE);
+            let counter = mmu.divider().counter();
+            assert_eq!(counter, m_cycles * 4);
+            assert_eq!(mmu.read(0xFF04), (counter >> 8) as u8);
+        }
+    }
+}
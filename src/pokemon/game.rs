@@ -0,0 +1,27 @@
+use crate::header::CartHeader;
+
+/// Games supported by the `PokemonApi`, all RAM addresses are specific to a game
+#[derive(Debug, Clone, Copy, Eq, PartialEq, strum_macros::Display)]
+pub enum Game {
+    PokemonRed,
+}
+
+impl Game {
+    pub fn detect(header: &CartHeader) -> Result<Self, String> {
+        match header.title() {
+            "POKEMON RED" => Ok(Game::PokemonRed),
+            title => Err(format!("Unsupported game {}", title)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_game() {
+        let header = CartHeader::parse(crate::roms::blargg_cpu::ROM).unwrap();
+        assert_eq!(Game::detect(&header), Err("Unsupported game CPU_INSTRS".to_string()));
+    }
+}
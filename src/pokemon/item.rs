@@ -0,0 +1,155 @@
+/// Generation 1 item ids https://bulbapedia.bulbagarden.net/wiki/List_of_items_by_index_number_in_Generation_I
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::Display, strum_macros::FromRepr, serde::Serialize, serde::Deserialize)]
+#[repr(u8)]
+pub enum ItemId {
+    MasterBall = 0x01,
+    UltraBall = 0x02,
+    GreatBall = 0x03,
+    PokeBall = 0x04,
+    TownMap = 0x05,
+    Bicycle = 0x06,
+    Surfboard = 0x07,
+    SafariBall = 0x08,
+    Pokedex = 0x09,
+    MoonStone = 0x0A,
+    Antidote = 0x0B,
+    BurnHeal = 0x0C,
+    IceHeal = 0x0D,
+    Awakening = 0x0E,
+    ParlyzHeal = 0x0F,
+    FullRestore = 0x10,
+    MaxPotion = 0x11,
+    HyperPotion = 0x12,
+    SuperPotion = 0x13,
+    Potion = 0x14,
+    BoulderBadge = 0x15,
+    CascadeBadge = 0x16,
+    ThunderBadge = 0x17,
+    RainbowBadge = 0x18,
+    SoulBadge = 0x19,
+    MarshBadge = 0x1A,
+    VolcanoBadge = 0x1B,
+    EarthBadge = 0x1C,
+    EscapeRope = 0x1D,
+    Repel = 0x1E,
+    OldAmber = 0x1F,
+    FireStone = 0x20,
+    ThunderStone = 0x21,
+    WaterStone = 0x22,
+    HpUp = 0x23,
+    Protein = 0x24,
+    Iron = 0x25,
+    Carbos = 0x26,
+    Calcium = 0x27,
+    RareCandy = 0x28,
+    DomeFossil = 0x29,
+    HelixFossil = 0x2A,
+    SecretKey = 0x2B,
+    UnusedItem = 0x2C,
+    BikeVoucher = 0x2D,
+    XAccuracy = 0x2E,
+    LeafStone = 0x2F,
+    CardKey = 0x30,
+    Nugget = 0x31,
+    UnusedPpUp = 0x32,
+    PokeDoll = 0x33,
+    FullHeal = 0x34,
+    Revive = 0x35,
+    MaxRevive = 0x36,
+    GuardSpec = 0x37,
+    SuperRepel = 0x38,
+    MaxRepel = 0x39,
+    DireHit = 0x3A,
+    Coin = 0x3B,
+    FreshWater = 0x3C,
+    SodaPop = 0x3D,
+    Lemonade = 0x3E,
+    SsTicket = 0x3F,
+    GoldTeeth = 0x40,
+    XAttack = 0x41,
+    XDefend = 0x42,
+    XSpeed = 0x43,
+    XSpecial = 0x44,
+    CoinCase = 0x45,
+    OaksParcel = 0x46,
+    Itemfinder = 0x47,
+    SilphScope = 0x48,
+    PokeFlute = 0x49,
+    LiftKey = 0x4A,
+    ExpAll = 0x4B,
+    OldRod = 0x4C,
+    GoodRod = 0x4D,
+    SuperRod = 0x4E,
+    PpUp = 0x4F,
+    Ether = 0x50,
+    MaxEther = 0x51,
+    Elixer = 0x52,
+    MaxElixer = 0x53,
+    Hm01 = 0xC4,
+    Hm02 = 0xC5,
+    Hm03 = 0xC6,
+    Hm04 = 0xC7,
+    Hm05 = 0xC8,
+    Tm01 = 0xC9,
+    Tm02 = 0xCA,
+    Tm03 = 0xCB,
+    Tm04 = 0xCC,
+    Tm05 = 0xCD,
+    Tm06 = 0xCE,
+    Tm07 = 0xCF,
+    Tm08 = 0xD0,
+    Tm09 = 0xD1,
+    Tm10 = 0xD2,
+    Tm11 = 0xD3,
+    Tm12 = 0xD4,
+    Tm13 = 0xD5,
+    Tm14 = 0xD6,
+    Tm15 = 0xD7,
+    Tm16 = 0xD8,
+    Tm17 = 0xD9,
+    Tm18 = 0xDA,
+    Tm19 = 0xDB,
+    Tm20 = 0xDC,
+    Tm21 = 0xDD,
+    Tm22 = 0xDE,
+    Tm23 = 0xDF,
+    Tm24 = 0xE0,
+    Tm25 = 0xE1,
+    Tm26 = 0xE2,
+    Tm27 = 0xE3,
+    Tm28 = 0xE4,
+    Tm29 = 0xE5,
+    Tm30 = 0xE6,
+    Tm31 = 0xE7,
+    Tm32 = 0xE8,
+    Tm33 = 0xE9,
+    Tm34 = 0xEA,
+    Tm35 = 0xEB,
+    Tm36 = 0xEC,
+    Tm37 = 0xED,
+    Tm38 = 0xEE,
+    Tm39 = 0xEF,
+    Tm40 = 0xF0,
+    Tm41 = 0xF1,
+    Tm42 = 0xF2,
+    Tm43 = 0xF3,
+    Tm44 = 0xF4,
+    Tm45 = 0xF5,
+    Tm46 = 0xF6,
+    Tm47 = 0xF7,
+    Tm48 = 0xF8,
+    Tm49 = 0xF9,
+    Tm50 = 0xFA,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BagItem {
+    pub id: ItemId,
+    pub quantity: u8,
+}
+
+impl BagItem {
+    pub fn new(id: ItemId, quantity: u8) -> Self {
+        Self { id, quantity }
+    }
+}
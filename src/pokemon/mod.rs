@@ -1,6 +1,9 @@
 use std::ops::{Deref, DerefMut, Index, IndexMut};
 use badge::Badge;
+use game::Game;
+use item::{BagItem, ItemId};
 use map::Map;
+use options::GameOptions;
 use species::PokemonSpecies;
 use unicode_segmentation::UnicodeSegmentation;
 use crate::game_boy::GameBoy;
@@ -11,7 +14,10 @@ use crate::pokemon::pokemon::{Pokemon, PokemonStats, PokemonType};
 use crate::pokemon::sprite::{PictureId, Sprite                          };
 
 pub mod badge;
+pub mod game;
+pub mod item;
 pub mod map;
+pub mod options;
 pub mod pokemon;
 pub mod status;
 pub mod species;
@@ -20,12 +26,19 @@ mod sprite;
 
 #[derive(Debug)]
 pub struct PokemonApi<'a> {
-    game_boy: &'a mut GameBoy
+    game_boy: &'a mut GameBoy,
+    game: Game,
 }
 
 impl<'a> PokemonApi<'a> {
-    pub fn new(game_boy: &'a mut GameBoy) -> Self {
-        Self { game_boy }
+    /// Fails if the loaded ROM is not a supported Pokemon game
+    pub fn new(game_boy: &'a mut GameBoy) -> Result<Self, String> {
+        let game = Game::detect(game_boy.core().mmu().header())?;
+        Ok(Self { game_boy, game })
+    }
+
+    pub fn game(&self) -> Game {
+        self.game
     }
 
     fn mmu(&self) -> &MMU {
@@ -48,24 +61,53 @@ impl<'a> PokemonApi<'a> {
     }
 
     pub fn pokemon_party(&self) -> Result<PokemonParty, String> {
-        let mmu = self.mmu();
-        let count = mmu.read(0xD163);
-        let mut party = PokemonParty::default();
-        for i in 0..count {
-            let pokemon = mmu.read_pokemon(0xD16B, i as u16)?;
-            party.push(pokemon)?;
-        }
-        Ok(party)
+        self.mmu().read_party()
     }
 
     pub fn write_pokemon_party(&mut self, party: PokemonParty) {
-        let mmu = self.mmu_mut();
-        mmu.write(0xD163, party.len() as u8); // length
-        mmu.write(0xD164 + party.len() as u16, 0xFF); // list end
-        for (index, pokemon) in party.into_iter().enumerate() {
-            mmu.write_pokemon(0xD16B, index as u16, &pokemon);
-            mmu.write(0xD164 + index as u16, pokemon.species as u8);
+        self.mmu_mut().write_party(party)
+    }
+
+    /// Corrects the level of every party Pokemon whose level doesn't match its experience,
+    /// returns the number of Pokemon that were corrected
+    pub fn fix_experience_levels(&mut self) -> Result<usize, String> {
+        let mut party = self.pokemon_party()?;
+        let fixed = party.fix_experience_levels();
+        self.write_pokemon_party(party);
+        Ok(fixed)
+    }
+
+    /// Restores HP & PP and cures status conditions for the whole party
+    pub fn heal_party(&mut self) -> Result<(), String> {
+        let mut party = self.pokemon_party()?;
+        for pokemon in party.iter_mut() {
+            pokemon.heal();
         }
+        self.write_pokemon_party(party);
+        Ok(())
+    }
+
+    /// The Pokemon stored in the current PC box
+    pub fn pc_box(&self) -> Result<PokemonBox, String> {
+        self.mmu().read_pc_box()
+    }
+
+    pub fn write_pc_box(&mut self, pc_box: PokemonBox) {
+        self.mmu_mut().write_pc_box(pc_box)
+    }
+
+    /// Moves a Pokemon from the current PC box to the end of the party, fails if the party is full
+    pub fn withdraw_from_box(&mut self, box_index: usize) -> Result<(), String> {
+        self.mmu_mut().withdraw_from_box(box_index)
+    }
+
+    pub fn item_bag(&self) -> Result<Vec<BagItem>, String> {
+        self.mmu().read_item_bag()
+    }
+
+    /// Fails if there are more items than fit in the bag
+    pub fn write_item_bag(&mut self, items: &[BagItem]) -> Result<(), String> {
+        self.mmu_mut().write_item_bag(items)
     }
 
     pub fn map_state(&self) -> Result<MapState, String> {
@@ -78,6 +120,20 @@ impl<'a> PokemonApi<'a> {
     pub fn sprites(&self) -> Vec<Sprite> {
         self.mmu().read_sprites()
     }
+
+    /// Decodes the on-screen tile buffer, one line per tile row with trailing whitespace trimmed.
+    /// Text boxes are drawn into this buffer, so the current dialogue appears in the bottom rows.
+    pub fn screen_text(&self) -> String {
+        self.mmu().read_screen_text()
+    }
+
+    pub fn options(&self) -> Result<GameOptions, String> {
+        self.mmu().read_options()
+    }
+
+    pub fn set_options(&mut self, options: GameOptions) {
+        self.mmu_mut().write_options(options)
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -105,6 +161,54 @@ impl PokemonParty {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Pokemon> {
+        self.0.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Pokemon> {
+        self.0.iter_mut()
+    }
+
+    /// Index of the first Pokemon of this species
+    pub fn find_species(&self, species: PokemonSpecies) -> Option<usize> {
+        self.0.iter().position(|pokemon| pokemon.species == species)
+    }
+
+    /// Lowest level first, Pokemon of the same level keep their order
+    pub fn sort_by_level(&mut self) {
+        self.0.sort_by_key(|pokemon| pokemon.level);
+    }
+
+    /// Recalculates the level & stats of Pokemon whose level doesn't match their experience,
+    /// returns the number of Pokemon that were corrected
+    pub fn fix_experience_levels(&mut self) -> usize {
+        let mut fixed = 0;
+        for pokemon in self.0.iter_mut().filter(|pokemon| pokemon.level != pokemon.level_for_experience()) {
+            pokemon.recalculate();
+            fixed += 1;
+        }
+        fixed
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) -> Result<(), String> {
+        if let Some(&index) = [a, b].iter().find(|&&index| index >= self.0.len()) {
+            return Err(format!("No Pokemon in party slot {}, party has {}", index, self.0.len()));
+        }
+        self.0.swap(a, b);
+        Ok(())
+    }
+}
+
+/// Collects at most `PokemonBlockAddresses::PARTY_MAX` Pokemon, any further Pokemon are ignored
+impl FromIterator<Pokemon> for PokemonParty {
+    fn from_iter<T: IntoIterator<Item = Pokemon>>(iter: T) -> Self {
+        Self(iter.into_iter().take(PokemonBlockAddresses::PARTY_MAX as usize).collect())
+    }
 }
 
 impl Index<usize> for PokemonParty {
@@ -130,6 +234,66 @@ impl IntoIterator for PokemonParty {
     }
 }
 
+/// The current PC box, boxes hold more Pokemon than the party
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct PokemonBox(Vec<Pokemon>);
+
+impl PokemonBox {
+    pub fn push(&mut self, pokemon: Pokemon) -> Result<(), String> {
+        if self.0.len() >= PokemonBlockAddresses::BOX_MAX as usize {
+            Err("Box is full".to_string())
+        } else {
+            self.0.push(pokemon);
+            Ok(())
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Pokemon> {
+        self.0.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Pokemon> {
+        self.0.iter_mut()
+    }
+}
+
+/// Collects at most `PokemonBlockAddresses::BOX_MAX` Pokemon, any further Pokemon are ignored
+impl FromIterator<Pokemon> for PokemonBox {
+    fn from_iter<T: IntoIterator<Item = Pokemon>>(iter: T) -> Self {
+        Self(iter.into_iter().take(PokemonBlockAddresses::BOX_MAX as usize).collect())
+    }
+}
+
+impl Index<usize> for PokemonBox {
+    type Output = Pokemon;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.0.index(index)
+    }
+}
+
+impl IndexMut<usize> for PokemonBox {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.0.index_mut(index)
+    }
+}
+
+impl IntoIterator for PokemonBox {
+    type Item = Pokemon;
+    type IntoIter = std::vec::IntoIter<Pokemon>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct MapState {
@@ -144,9 +308,35 @@ trait PokemonEncoding {
 
     fn read_pokemon(&self, base_address: u16, index: u16) -> Result<Pokemon, String>;
 
+    /// Box records omit the level & calculated stats, these are recalculated from the box level
+    fn read_box_pokemon(&self, base_address: u16, index: u16) -> Result<Pokemon, String>;
+
+    fn read_party(&self) -> Result<PokemonParty, String>;
+
+    fn write_party(&mut self, party: PokemonParty);
+
+    fn read_pc_box(&self) -> Result<PokemonBox, String>;
+
+    fn write_pc_box(&mut self, pc_box: PokemonBox);
+
+    fn withdraw_from_box(&mut self, box_index: usize) -> Result<(), String>;
+
     fn write_pokemon(&mut self, base_address: u16, index: u16, pokemon: &Pokemon);
 
+    /// Box records omit the calculated stats, the level is written to the box level
+    fn write_box_pokemon(&mut self, base_address: u16, index: u16, pokemon: &Pokemon);
+
+    fn read_item_bag(&self) -> Result<Vec<BagItem>, String>;
+
+    fn write_item_bag(&mut self, items: &[BagItem]) -> Result<(), String>;
+
     fn read_sprites(&self) -> Vec<Sprite>;
+
+    fn read_screen_text(&self) -> String;
+
+    fn read_options(&self) -> Result<GameOptions, String>;
+
+    fn write_options(&mut self, options: GameOptions);
 }
 
 impl PokemonEncoding for MMU {
@@ -157,46 +347,9 @@ impl PokemonEncoding for MMU {
             let byte = self.read(address + i);
 
             match byte {
-                0x00 => utf8.push(b'\0'), // null
                 0x50 => break, // end: marks the end of a string
-                0x80..=0x99 => utf8.push(byte - 0x80 + b'A'), // A-Z
-                0x9A => utf8.push(b'('),
-                0x9B => utf8.push(b')'),
-                0x9C => utf8.push(b':'),
-                0x9D => utf8.push(b';'),
-                0x9E => utf8.push(b'['),
-                0x9F => utf8.push(b']'),
-                0xA0..=0xB9 => utf8.push(byte - 0xA0 + b'a'), // a-z
-                0xBA => utf8.push(b'e'),
-                0xBB => utf8.push(b'd'),
-                0xBC => utf8.push(b'l'),
-                0xBD => utf8.push(b's'),
-                0xBE => utf8.push(b't'),
-                0xBF => utf8.push(b'v'),
-                0xE0 => utf8.push(b'\''),
-                0xE1 => utf8.push(b'P'), // pk character
-                0xE2 => utf8.push(b'M'), // mn character
-                0xE3 => utf8.push(b'-'),
-                0xE4 => utf8.push(b'r'),
-                0xE5 => utf8.push(b'm'),
-                0xE6 => utf8.push(b'?'),
-                0xE7 => utf8.push(b'!'),
-                0xE8 => utf8.push(b'.'),
-                0xE9 => utf8.extend_from_slice("ァ".as_bytes()),
-                0xEA => utf8.extend_from_slice("ゥ".as_bytes()),
-                0xEB => utf8.extend_from_slice("ェ".as_bytes()),
-                0xEC => utf8.extend_from_slice("▷".as_bytes()),
-                0xED => utf8.extend_from_slice("▶".as_bytes()),
-                0xEE => utf8.extend_from_slice("▼".as_bytes()),
-                0xEF => utf8.extend_from_slice("♂".as_bytes()),
-                0xF1 => utf8.extend_from_slice("×".as_bytes()),
-                0xF2 => utf8.push(b'.'),
-                0xF3 => utf8.push(b'/'),
-                0xF4 => utf8.push(b','),
-                0xF5 => utf8.extend_from_slice("♀".as_bytes()),
-                0xF6..=0xFF => utf8.push(byte - 0xF6 + b'0'), // 0-9
-                _ => utf8.push(b' ') // Undefined characters simply print as spaces.
-            };
+                _ => decode_pokemon_char(byte, &mut utf8),
+            }
         }
         std::str::from_utf8(&utf8)
             .map_err(|_| "Invalid UTF-8 in string".to_string())
@@ -207,144 +360,131 @@ impl PokemonEncoding for MMU {
         // https://bulbapedia.bulbagarden.net/wiki/Character_encoding_(Generation_I)
         let graphemes = string.graphemes(true)
             .take(max_length as usize - 1); // -1 for terminator byte
-        for (index, grapheme) in graphemes.enumerate() {
-            let byte = if grapheme.bytes().count() > 1 {
-                // unicode
-                match grapheme {
-                    "ァ" => 0xE9,
-                    "ゥ" => 0xEA,
-                    "ェ" => 0xEB,
-                    "▷" => 0xEC,
-                    "▶" => 0xED,
-                    "▼" => 0xEE,
-                    "♂" => 0xEF,
-                    "×" => 0xF1,
-                    "♀" => 0xF5,
-                    _ => 0x00
-                }
-            } else {
-                // ascii
-                let char = grapheme.bytes().next().unwrap();
-                match char {
-                    b'A'..=b'Z' => (char - b'A') + 0x80,
-                    b'a'..=b'z' => (char - b'a') + 0xA0,
-                    b'0'..=b'9' => (char - b'0') + 0xF6,
-                    b'(' => 0x9A,
-                    b')' => 0x9B,
-                    b':' => 0x9C,
-                    b';' => 0x9D,
-                    b'[' => 0x9E,
-                    b']' => 0x9F,
-                    b'\'' => 0xE0,
-                    b'-' => 0xE3,
-                    b'?' => 0xE6,
-                    b'!' => 0xE7,
-                    b'.' => 0xE8,
-                    b'/' => 0xF3,
-                    b',' => 0xF4,
-                    b' ' => 0x7F,
-                    _ => 0x00
-                }
-            };
-            self.write(address + index as u16, byte);
+        let mut length = 0;
+        for grapheme in graphemes {
+            let byte = encode_pokemon_grapheme(grapheme).unwrap_or(0x00);
+            self.write(address + length, byte);
+            length += 1;
         }
-        self.write(address + string.len() as u16, 0x50);
+        // multi-byte graphemes are a single character so the terminator goes after the last character, not string.len()
+        self.write(address + length, 0x50);
     }
 
     fn read_pokemon(&self, base_address: u16, index: u16) -> Result<Pokemon, String> {
-        let addresses = PokemonBlockAddresses::of_indexed(base_address, index);
+        read_pokemon_block(self, PokemonBlockAddresses::of_indexed(base_address, index), false)
+    }
+
+    fn read_box_pokemon(&self, base_address: u16, index: u16) -> Result<Pokemon, String> {
+        let mut pokemon = read_pokemon_block(self, PokemonBlockAddresses::of_box_indexed(base_address, index), true)?;
+        pokemon.recalculate();
+        Ok(pokemon)
+    }
 
-        fn parse_type(mmu: &MMU, pkmn_base: u16, offset: u16) -> Result<PokemonType, String> {
-            PokemonType::from_repr(mmu.read(pkmn_base + 5 + offset))
-                .ok_or_else(|| format!("Invalid Pokemon type {}", offset + 1))
+    fn read_party(&self) -> Result<PokemonParty, String> {
+        let count = self.read(PARTY_COUNT_ADDRESS);
+        let mut party = PokemonParty::default();
+        for i in 0..count {
+            let pokemon = self.read_pokemon(PARTY_ADDRESS, i as u16)?;
+            party.push(pokemon)?;
         }
+        Ok(party)
+    }
 
-        fn parse_move(mmu: &MMU, pkmn_base: u16, offset: u16) -> Option<PokemonMove> {
-            if let Some(name) = PokemonMoveName::from_repr(mmu.read(pkmn_base + 8 + offset)) {
-                Some(
-                    PokemonMove {
-                        name,
-                        pp: mmu.read(pkmn_base + 29 + offset)
-                    }
-                )
-            } else {
-                None
-            }
+    fn write_party(&mut self, party: PokemonParty) {
+        self.write(PARTY_COUNT_ADDRESS, party.len() as u8); // length
+        self.write(PARTY_COUNT_ADDRESS + 1 + party.len() as u16, 0xFF); // list end
+        for (index, pokemon) in party.into_iter().enumerate() {
+            self.write_pokemon(PARTY_ADDRESS, index as u16, &pokemon);
+            self.write(PARTY_COUNT_ADDRESS + 1 + index as u16, pokemon.species as u8);
         }
+    }
 
-        fn read_stats(mmu: &MMU, pkmn_base: u16, offset: u16) -> PokemonStats {
-            PokemonStats {
-                hp: mmu.read_u16_be(pkmn_base + offset),
-                attack: mmu.read_u16_be(pkmn_base + offset + 2),
-                defense: mmu.read_u16_be(pkmn_base + offset + 4),
-                speed: mmu.read_u16_be(pkmn_base + offset + 6),
-                special: mmu.read_u16_be(pkmn_base + offset + 8),
-            }
+    fn read_pc_box(&self) -> Result<PokemonBox, String> {
+        let count = self.read(BOX_COUNT_ADDRESS);
+        let mut pc_box = PokemonBox::default();
+        for i in 0..count {
+            let pokemon = self.read_box_pokemon(BOX_ADDRESS, i as u16)?;
+            pc_box.push(pokemon)?;
         }
+        Ok(pc_box)
+    }
 
-        Ok(Pokemon {
-            nickname: self.read_pokemon_string(addresses.nickname, PokemonBlockAddresses::NAME_LENGTH)?,
-            trainer_name: self.read_pokemon_string(addresses.trainer_name, PokemonBlockAddresses::NAME_LENGTH)?,
-            species: PokemonSpecies::from_repr(self.read(addresses.pokemon)).ok_or_else(|| "Invalid Pokemon species".to_string())?,
-            current_hp: self.read_u16_be(addresses.pokemon + 1),
-            status: self.read(addresses.pokemon + 4).into(),
-            types: [
-                parse_type(self, addresses.pokemon, 0)?,
-                parse_type(self, addresses.pokemon, 1)?,
-            ],
-            moves: std::array::from_fn(|i| parse_move(self, addresses.pokemon, i as u16)),
-            trainer_id: self.read_u16_be(addresses.pokemon + 12),
-            experience: self.read_u32_be(addresses.pokemon + 13) & 0xFFFFFF, // 3 bytes so read as u32 offset -1 and trim top byte
-            effort_values: read_stats(self, addresses.pokemon, 17),
-            individual_values: PokemonStats::from_iv_bytes(
-                self.read(addresses.pokemon + 27),
-                self.read(addresses.pokemon + 28)
-            ),
-            level: self.read(addresses.pokemon + 33),
-            stats: read_stats(self, addresses.pokemon, 34),
-        })
+    fn write_pc_box(&mut self, pc_box: PokemonBox) {
+        self.write(BOX_COUNT_ADDRESS, pc_box.len() as u8); // length
+        self.write(BOX_COUNT_ADDRESS + 1 + pc_box.len() as u16, 0xFF); // list end
+        for (index, pokemon) in pc_box.into_iter().enumerate() {
+            self.write_box_pokemon(BOX_ADDRESS, index as u16, &pokemon);
+            self.write(BOX_COUNT_ADDRESS + 1 + index as u16, pokemon.species as u8);
+        }
     }
 
-    fn write_pokemon(&mut self, base_address: u16, index: u16, pokemon: &Pokemon) {
-        let addresses = PokemonBlockAddresses::of_indexed(base_address, index);
-
-        fn write_move(mmu: &mut MMU, pkmn_base: u16, offset: u16, move_: Option<PokemonMove>) {
-            if let Some(move_) = move_ {
-                mmu.write(pkmn_base + 8 + offset, move_.name as u8);
-                mmu.write(pkmn_base + 29 + offset, move_.pp);
-            } else {
-                mmu.write(pkmn_base + 8 + offset, 0x00);
-                mmu.write(pkmn_base + 29 + offset, 0x00);
-            }
+    fn withdraw_from_box(&mut self, box_index: usize) -> Result<(), String> {
+        let count = self.read(BOX_COUNT_ADDRESS) as usize;
+        if box_index >= count {
+            return Err(format!("No Pokemon in box slot {}, box has {}", box_index, count));
         }
+        let mut party = self.read_party()?;
+        let mut pokemon = self.read_box_pokemon(BOX_ADDRESS, box_index as u16)?;
+        pokemon.current_hp = pokemon.stats.hp; // withdrawn Pokemon join the party at full HP
+        party.push(pokemon)?;
+        self.write_party(party);
 
-        fn write_stats(mmu: &mut MMU, pkmn_base: u16, offset: u16, stats: PokemonStats) {
-            mmu.write_u16_be(pkmn_base + offset, stats.hp);
-            mmu.write_u16_be(pkmn_base + offset + 2, stats.attack);
-            mmu.write_u16_be(pkmn_base + offset + 4, stats.defense);
-            mmu.write_u16_be(pkmn_base + offset + 6, stats.speed);
-            mmu.write_u16_be(pkmn_base + offset + 8, stats.special);
+        // shift the rest of the box down a slot
+        for index in box_index as u16 + 1..count as u16 {
+            let from = PokemonBlockAddresses::of_box_indexed(BOX_ADDRESS, index);
+            let to = PokemonBlockAddresses::of_box_indexed(BOX_ADDRESS, index - 1);
+            let blocks = [
+                (from.pokemon, to.pokemon, PokemonBlockAddresses::BOX_POKEMON_BLOCK_SIZE),
+                (from.trainer_name, to.trainer_name, PokemonBlockAddresses::NAME_LENGTH),
+                (from.nickname, to.nickname, PokemonBlockAddresses::NAME_LENGTH),
+            ];
+            for (from, to, length) in blocks {
+                for offset in 0..length {
+                    self.write(to + offset, self.read(from + offset));
+                }
+            }
+            self.write(BOX_COUNT_ADDRESS + index, self.read(BOX_COUNT_ADDRESS + 1 + index)); // species list
         }
+        self.write(BOX_COUNT_ADDRESS, count as u8 - 1);
+        self.write(BOX_COUNT_ADDRESS + count as u16, 0xFF); // list end
+        Ok(())
+    }
 
-        self.write_pokemon_string(addresses.nickname, &pokemon.nickname, PokemonBlockAddresses::NAME_LENGTH);
-        self.write_pokemon_string(addresses.trainer_name, &pokemon.trainer_name, PokemonBlockAddresses::NAME_LENGTH);
-        self.write(addresses.pokemon, pokemon.species as u8);
-        self.write_u16_be(addresses.pokemon + 1, pokemon.current_hp);
-        self.write(addresses.pokemon + 4, pokemon.status.into());
-        self.write(addresses.pokemon + 5, pokemon.types[0] as u8);
-        self.write(addresses.pokemon + 6, pokemon.types[1] as u8);
-        for i in 0..4 {
-            write_move(self, addresses.pokemon, i as u16, pokemon.moves[i]);
+    fn write_pokemon(&mut self, base_address: u16, index: u16, pokemon: &Pokemon) {
+        write_pokemon_block(self, PokemonBlockAddresses::of_indexed(base_address, index), pokemon, false);
+    }
+
+    fn write_box_pokemon(&mut self, base_address: u16, index: u16, pokemon: &Pokemon) {
+        write_pokemon_block(self, PokemonBlockAddresses::of_box_indexed(base_address, index), pokemon, true);
+    }
+
+    fn read_item_bag(&self) -> Result<Vec<BagItem>, String> {
+        let count = self.read(BAG_COUNT_ADDRESS);
+        if count > BAG_MAX {
+            return Err(format!("Invalid bag item count {}", count));
         }
-        self.write_u32_be(addresses.pokemon + 13, pokemon.experience & 0xFFFFFF);
-        self.write_u16_be(addresses.pokemon + 12, pokemon.trainer_id);
-        write_stats(self, addresses.pokemon, 17, pokemon.effort_values);
+        (0..count as u16)
+            .map(|index| {
+                let address = BAG_COUNT_ADDRESS + 1 + index * 2;
+                let id = ItemId::from_repr(self.read(address))
+                    .ok_or_else(|| format!("Invalid item id {:#04x}", self.read(address)))?;
+                Ok(BagItem::new(id, self.read(address + 1)))
+            })
+            .collect()
+    }
 
-        let (attack_defense, speed_special) = pokemon.individual_values.into_iv_bytes();
-        self.write(addresses.pokemon + 27, attack_defense);
-        self.write(addresses.pokemon + 28, speed_special);
-        self.write(addresses.pokemon + 33, pokemon.level);
-        write_stats(self, addresses.pokemon, 34, pokemon.stats);
+    fn write_item_bag(&mut self, items: &[BagItem]) -> Result<(), String> {
+        if items.len() > BAG_MAX as usize {
+            return Err(format!("Bag holds at most {} items, got {}", BAG_MAX, items.len()));
+        }
+        self.write(BAG_COUNT_ADDRESS, items.len() as u8);
+        for (index, item) in items.iter().enumerate() {
+            let address = BAG_COUNT_ADDRESS + 1 + index as u16 * 2;
+            self.write(address, item.id as u8);
+            self.write(address + 1, item.quantity);
+        }
+        self.write(BAG_COUNT_ADDRESS + 1 + items.len() as u16 * 2, 0xFF); // list end
+        Ok(())
     }
 
     fn read_sprites(&self) -> Vec<Sprite> {
@@ -380,8 +520,44 @@ impl PokemonEncoding for MMU {
         }
         sprites
     }
+
+    fn read_screen_text(&self) -> String {
+        let mut lines = Vec::with_capacity(SCREEN_TILES_HEIGHT as usize);
+        for row in 0..SCREEN_TILES_HEIGHT {
+            let mut utf8 = vec![];
+            for column in 0..SCREEN_TILES_WIDTH {
+                let byte = self.read(SCREEN_TILE_MAP_ADDRESS + row * SCREEN_TILES_WIDTH + column);
+                match byte {
+                    // null & string terminators are never drawn
+                    0x00 | 0x50 => utf8.push(b' '),
+                    _ => decode_pokemon_char(byte, &mut utf8),
+                }
+            }
+            lines.push(String::from_utf8_lossy(&utf8).trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    fn read_options(&self) -> Result<GameOptions, String> {
+        GameOptions::parse(self.read(OPTIONS_ADDRESS))
+    }
+
+    fn write_options(&mut self, options: GameOptions) {
+        self.write(OPTIONS_ADDRESS, options.bits());
+    }
 }
 
+const SCREEN_TILE_MAP_ADDRESS: u16 = 0xC3A0;
+const SCREEN_TILES_WIDTH: u16 = 20;
+const SCREEN_TILES_HEIGHT: u16 = 18;
+const OPTIONS_ADDRESS: u16 = 0xD355;
+const BAG_COUNT_ADDRESS: u16 = 0xD31D; // followed by item id & quantity pairs
+const BAG_MAX: u8 = 20;
+const PARTY_COUNT_ADDRESS: u16 = 0xD163; // followed by the species list
+const PARTY_ADDRESS: u16 = 0xD16B;
+const BOX_COUNT_ADDRESS: u16 = 0xDA80; // followed by the species list
+const BOX_ADDRESS: u16 = 0xDA96;
+
 pub struct PokemonBlockAddresses {
     pub pokemon: u16,
     pub trainer_name: u16,
@@ -390,15 +566,211 @@ pub struct PokemonBlockAddresses {
 
 impl PokemonBlockAddresses {
     pub const PARTY_MAX: u16 = 6;
+    pub const BOX_MAX: u16 = 20;
     pub const POKEMON_BLOCK_SIZE: u16 = 0x2C;
+    pub const BOX_POKEMON_BLOCK_SIZE: u16 = 0x21;
     pub const NAME_LENGTH: u16 = 0xB;
 
     fn of_indexed(base_address: u16, index: u16) -> Self {
+        Self::of_list(base_address, index, Self::PARTY_MAX, Self::POKEMON_BLOCK_SIZE)
+    }
+
+    fn of_box_indexed(base_address: u16, index: u16) -> Self {
+        Self::of_list(base_address, index, Self::BOX_MAX, Self::BOX_POKEMON_BLOCK_SIZE)
+    }
+
+    /// Lists store every Pokemon block, then every trainer name, then every nickname
+    fn of_list(base_address: u16, index: u16, max: u16, block_size: u16) -> Self {
         Self {
-            pokemon: base_address + index * Self::POKEMON_BLOCK_SIZE,
-            trainer_name: base_address + Self::PARTY_MAX * Self::POKEMON_BLOCK_SIZE + index * Self::NAME_LENGTH,
-            nickname: base_address + Self::PARTY_MAX * Self::POKEMON_BLOCK_SIZE + Self::PARTY_MAX * Self::NAME_LENGTH + index * Self::NAME_LENGTH,
+            pokemon: base_address + index * block_size,
+            trainer_name: base_address + max * block_size + index * Self::NAME_LENGTH,
+            nickname: base_address + max * block_size + max * Self::NAME_LENGTH + index * Self::NAME_LENGTH,
+        }
+    }
+}
+
+/// Returns `None` for graphemes that are not in the Gen 1 character table
+fn encode_pokemon_grapheme(grapheme: &str) -> Option<u8> {
+    // https://bulbapedia.bulbagarden.net/wiki/Character_encoding_(Generation_I)
+    let byte = if grapheme.len() > 1 {
+        // unicode
+        match grapheme {
+            "ァ" => 0xE9,
+            "ゥ" => 0xEA,
+            "ェ" => 0xEB,
+            "▷" => 0xEC,
+            "▶" => 0xED,
+            "▼" => 0xEE,
+            "♂" => 0xEF,
+            "×" => 0xF1,
+            "♀" => 0xF5,
+            _ => return None
+        }
+    } else {
+        // ascii
+        let char = grapheme.bytes().next()?;
+        match char {
+            b'A'..=b'Z' => (char - b'A') + 0x80,
+            b'a'..=b'z' => (char - b'a') + 0xA0,
+            b'0'..=b'9' => (char - b'0') + 0xF6,
+            b'(' => 0x9A,
+            b')' => 0x9B,
+            b':' => 0x9C,
+            b';' => 0x9D,
+            b'[' => 0x9E,
+            b']' => 0x9F,
+            b'\'' => 0xE0,
+            b'-' => 0xE3,
+            b'?' => 0xE6,
+            b'!' => 0xE7,
+            b'.' => 0xE8,
+            b'/' => 0xF3,
+            b',' => 0xF4,
+            b' ' => 0x7F,
+            _ => return None
         }
+    };
+    Some(byte)
+}
+
+fn decode_pokemon_char(byte: u8, utf8: &mut Vec<u8>) {
+    // https://bulbapedia.bulbagarden.net/wiki/Character_encoding_(Generation_I)
+    match byte {
+        0x00 => utf8.push(b'\0'), // null
+        0x80..=0x99 => utf8.push(byte - 0x80 + b'A'), // A-Z
+        0x9A => utf8.push(b'('),
+        0x9B => utf8.push(b')'),
+        0x9C => utf8.push(b':'),
+        0x9D => utf8.push(b';'),
+        0x9E => utf8.push(b'['),
+        0x9F => utf8.push(b']'),
+        0xA0..=0xB9 => utf8.push(byte - 0xA0 + b'a'), // a-z
+        0xBA => utf8.push(b'e'),
+        0xBB => utf8.push(b'd'),
+        0xBC => utf8.push(b'l'),
+        0xBD => utf8.push(b's'),
+        0xBE => utf8.push(b't'),
+        0xBF => utf8.push(b'v'),
+        0xE0 => utf8.push(b'\''),
+        0xE1 => utf8.push(b'P'), // pk character
+        0xE2 => utf8.push(b'M'), // mn character
+        0xE3 => utf8.push(b'-'),
+        0xE4 => utf8.push(b'r'),
+        0xE5 => utf8.push(b'm'),
+        0xE6 => utf8.push(b'?'),
+        0xE7 => utf8.push(b'!'),
+        0xE8 => utf8.push(b'.'),
+        0xE9 => utf8.extend_from_slice("ァ".as_bytes()),
+        0xEA => utf8.extend_from_slice("ゥ".as_bytes()),
+        0xEB => utf8.extend_from_slice("ェ".as_bytes()),
+        0xEC => utf8.extend_from_slice("▷".as_bytes()),
+        0xED => utf8.extend_from_slice("▶".as_bytes()),
+        0xEE => utf8.extend_from_slice("▼".as_bytes()),
+        0xEF => utf8.extend_from_slice("♂".as_bytes()),
+        0xF1 => utf8.extend_from_slice("×".as_bytes()),
+        0xF2 => utf8.push(b'.'),
+        0xF3 => utf8.push(b'/'),
+        0xF4 => utf8.push(b','),
+        0xF5 => utf8.extend_from_slice("♀".as_bytes()),
+        0xF6..=0xFF => utf8.push(byte - 0xF6 + b'0'), // 0-9
+        _ => utf8.push(b' ') // Undefined characters simply print as spaces.
+    }
+}
+
+fn read_pokemon_block(mmu: &MMU, addresses: PokemonBlockAddresses, boxed: bool) -> Result<Pokemon, String> {
+    fn parse_type(mmu: &MMU, pkmn_base: u16, offset: u16) -> Result<PokemonType, String> {
+        PokemonType::from_repr(mmu.read(pkmn_base + 5 + offset))
+            .ok_or_else(|| format!("Invalid Pokemon type {}", offset + 1))
+    }
+
+    fn parse_move(mmu: &MMU, pkmn_base: u16, offset: u16) -> Option<PokemonMove> {
+        if let Some(name) = PokemonMoveName::from_repr(mmu.read(pkmn_base + 8 + offset)) {
+            Some(
+                PokemonMove {
+                    name,
+                    pp: mmu.read(pkmn_base + 29 + offset)
+                }
+            )
+        } else {
+            None
+        }
+    }
+
+    fn read_stats(mmu: &MMU, pkmn_base: u16, offset: u16) -> PokemonStats {
+        PokemonStats {
+            hp: mmu.read_u16_be(pkmn_base + offset),
+            attack: mmu.read_u16_be(pkmn_base + offset + 2),
+            defense: mmu.read_u16_be(pkmn_base + offset + 4),
+            speed: mmu.read_u16_be(pkmn_base + offset + 6),
+            special: mmu.read_u16_be(pkmn_base + offset + 8),
+        }
+    }
+
+    Ok(Pokemon {
+        nickname: mmu.read_pokemon_string(addresses.nickname, PokemonBlockAddresses::NAME_LENGTH)?,
+        trainer_name: mmu.read_pokemon_string(addresses.trainer_name, PokemonBlockAddresses::NAME_LENGTH)?,
+        species: PokemonSpecies::from_repr(mmu.read(addresses.pokemon)).ok_or_else(|| "Invalid Pokemon species".to_string())?,
+        current_hp: mmu.read_u16_be(addresses.pokemon + 1),
+        status: mmu.read(addresses.pokemon + 4).into(),
+        types: [
+            parse_type(mmu, addresses.pokemon, 0)?,
+            parse_type(mmu, addresses.pokemon, 1)?,
+        ],
+        moves: std::array::from_fn(|i| parse_move(mmu, addresses.pokemon, i as u16)),
+        trainer_id: mmu.read_u16_be(addresses.pokemon + 12),
+        experience: mmu.read_u32_be(addresses.pokemon + 13) & 0xFFFFFF, // 3 bytes so read as u32 offset -1 and trim top byte
+        effort_values: read_stats(mmu, addresses.pokemon, 17),
+        individual_values: PokemonStats::from_iv_bytes(
+            mmu.read(addresses.pokemon + 27),
+            mmu.read(addresses.pokemon + 28)
+        ),
+        // box records end before the level & stats, use the box level and leave the stats to be recalculated
+        level: mmu.read(addresses.pokemon + if boxed { 3 } else { 33 }),
+        stats: if boxed { PokemonStats::ZERO } else { read_stats(mmu, addresses.pokemon, 34) },
+    })
+}
+
+fn write_pokemon_block(mmu: &mut MMU, addresses: PokemonBlockAddresses, pokemon: &Pokemon, boxed: bool) {
+    fn write_move(mmu: &mut MMU, pkmn_base: u16, offset: u16, move_: Option<PokemonMove>) {
+        if let Some(move_) = move_ {
+            mmu.write(pkmn_base + 8 + offset, move_.name as u8);
+            mmu.write(pkmn_base + 29 + offset, move_.pp);
+        } else {
+            mmu.write(pkmn_base + 8 + offset, 0x00);
+            mmu.write(pkmn_base + 29 + offset, 0x00);
+        }
+    }
+
+    fn write_stats(mmu: &mut MMU, pkmn_base: u16, offset: u16, stats: PokemonStats) {
+        mmu.write_u16_be(pkmn_base + offset, stats.hp);
+        mmu.write_u16_be(pkmn_base + offset + 2, stats.attack);
+        mmu.write_u16_be(pkmn_base + offset + 4, stats.defense);
+        mmu.write_u16_be(pkmn_base + offset + 6, stats.speed);
+        mmu.write_u16_be(pkmn_base + offset + 8, stats.special);
+    }
+
+    mmu.write_pokemon_string(addresses.nickname, &pokemon.nickname, PokemonBlockAddresses::NAME_LENGTH);
+    mmu.write_pokemon_string(addresses.trainer_name, &pokemon.trainer_name, PokemonBlockAddresses::NAME_LENGTH);
+    mmu.write(addresses.pokemon, pokemon.species as u8);
+    mmu.write_u16_be(addresses.pokemon + 1, pokemon.current_hp);
+    mmu.write(addresses.pokemon + 3, pokemon.level); // box level
+    mmu.write(addresses.pokemon + 4, pokemon.status.into());
+    mmu.write(addresses.pokemon + 5, pokemon.types[0] as u8);
+    mmu.write(addresses.pokemon + 6, pokemon.types[1] as u8);
+    for i in 0..4 {
+        write_move(mmu, addresses.pokemon, i as u16, pokemon.moves[i]);
+    }
+    mmu.write_u32_be(addresses.pokemon + 13, pokemon.experience & 0xFFFFFF);
+    mmu.write_u16_be(addresses.pokemon + 12, pokemon.trainer_id);
+    write_stats(mmu, addresses.pokemon, 17, pokemon.effort_values);
+
+    let (attack_defense, speed_special) = pokemon.individual_values.into_iv_bytes();
+    mmu.write(addresses.pokemon + 27, attack_defense);
+    mmu.write(addresses.pokemon + 28, speed_special);
+    if !boxed {
+        // box records end before the level & stats
+        mmu.write(addresses.pokemon + 33, pokemon.level);
+        write_stats(mmu, addresses.pokemon, 34, pokemon.stats);
     }
 }
 
@@ -416,10 +788,21 @@ fn reverse_bcd(mut value: u32) -> u32 {
 
 #[cfg(test)]
 mod tests {
+    use crate::pokemon::options::{BattleStyle, Sound, TextSpeed};
     use crate::pokemon::status::PokemonStatus;
     use crate::roms::blargg_cpu::ROM;
+    use crate::roms::commercial::{POKEMON_RED, TETRIS};
     use super::*;
 
+    #[test]
+    fn test_detect_game() {
+        let mut tetris = GameBoy::dmg(TETRIS);
+        assert_eq!(PokemonApi::new(&mut tetris).unwrap_err(), "Unsupported game TETRIS");
+
+        let mut red = GameBoy::dmg(POKEMON_RED);
+        assert_eq!(PokemonApi::new(&mut red).unwrap().game(), Game::PokemonRed);
+    }
+
     #[test]
     fn test_reverse_bcd() {
         assert_eq!(reverse_bcd(0x3000), 3000);
@@ -431,9 +814,7 @@ mod tests {
         assert_eq!(reverse_bcd(0x0100), 100);
     }
 
-    #[test]
-    fn test_pokemon_encoding() {
-        let mut mmu = MMU::from_rom(ROM).unwrap();
+    fn charizard() -> Pokemon {
         let mut charizard = Pokemon {
             nickname: "BACON".to_string(),
             species: PokemonSpecies::Charizard,
@@ -465,8 +846,263 @@ mod tests {
         };
 
         charizard.recalculate();
+        charizard
+    }
+
+    #[test]
+    fn test_pokemon_encoding() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        let charizard = charizard();
 
         mmu.write_pokemon(0xD16B, 0, &charizard);
         assert_eq!(charizard, mmu.read_pokemon(0xD16B, 0).unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_pokemon_json() {
+        let charizard = charizard();
+        let json = serde_json::to_string(&charizard).unwrap();
+        assert!(json.contains(r#""species":"Charizard""#), "{}", json);
+        assert!(json.contains(r#""types":["Fire","Flying"]"#), "{}", json);
+        assert!(json.contains(r#""status":"None""#), "{}", json);
+        assert!(json.contains(r#"{"name":"Flamethrower","pp":10}"#), "{}", json);
+        assert!(json.contains(r#"null]"#), "{}", json); // empty move slot
+        assert_eq!(serde_json::from_str::<Pokemon>(&json).unwrap(), charizard);
+
+        let mut sleeping = charizard;
+        sleeping.status = PokemonStatus::Asleep { counter: 3 };
+        let json = serde_json::to_string(&sleeping).unwrap();
+        assert_eq!(serde_json::from_str::<Pokemon>(&json).unwrap(), sleeping);
+    }
+
+    #[test]
+    fn test_screen_text() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        for i in 0..SCREEN_TILES_WIDTH * SCREEN_TILES_HEIGHT {
+            mmu.write(SCREEN_TILE_MAP_ADDRESS + i, 0x7F); // blank tile
+        }
+        // text box border tiles decode as spaces
+        mmu.write(SCREEN_TILE_MAP_ADDRESS + 12 * SCREEN_TILES_WIDTH, 0x79);
+        mmu.write_pokemon_string(SCREEN_TILE_MAP_ADDRESS + 14 * SCREEN_TILES_WIDTH + 1, "OAK: Hello there!", SCREEN_TILES_WIDTH);
+        mmu.write_pokemon_string(SCREEN_TILE_MAP_ADDRESS + 16 * SCREEN_TILES_WIDTH + 1, "Welcome to the", SCREEN_TILES_WIDTH);
+
+        let text = mmu.read_screen_text();
+        let lines: Vec<&str> = text.split('\n').collect();
+        assert_eq!(lines.len(), SCREEN_TILES_HEIGHT as usize);
+        assert_eq!(lines[12], "");
+        assert_eq!(lines[14], " OAK: Hello there!");
+        assert_eq!(lines[16], " Welcome to the");
+        assert!(text.contains("Hello there!\n\n Welcome"));
+    }
+
+    #[test]
+    fn test_options() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        let options = GameOptions {
+            text_speed: TextSpeed::Fast,
+            battle_style: BattleStyle::Set,
+            battle_animations: true,
+            sound: Sound::Mono,
+        };
+        mmu.write_options(options);
+        assert_eq!(mmu.read(OPTIONS_ADDRESS), 0x41);
+
+        let read = mmu.read_options().unwrap();
+        assert_eq!(read.text_speed, TextSpeed::Fast);
+        assert_eq!(read.battle_style, BattleStyle::Set);
+        assert!(read.battle_animations);
+        assert_eq!(read, options);
+    }
+
+    #[test]
+    fn test_item_bag() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        let items = vec![
+            BagItem::new(ItemId::PokeBall, 5),
+            BagItem::new(ItemId::Potion, 99),
+            BagItem::new(ItemId::Hm01, 1),
+            BagItem::new(ItemId::Tm50, 1),
+        ];
+        mmu.write_item_bag(&items).unwrap();
+        assert_eq!(mmu.read(BAG_COUNT_ADDRESS), 4);
+        assert_eq!(mmu.read(BAG_COUNT_ADDRESS + 3), ItemId::Potion as u8);
+        assert_eq!(mmu.read(BAG_COUNT_ADDRESS + 9), 0xFF);
+        assert_eq!(mmu.read_item_bag().unwrap(), items);
+
+        // shrinking the bag moves the terminator
+        mmu.write_item_bag(&items[..1]).unwrap();
+        assert_eq!(mmu.read(BAG_COUNT_ADDRESS + 3), 0xFF);
+        assert_eq!(mmu.read_item_bag().unwrap(), items[..1]);
+
+        let full = vec![BagItem::new(ItemId::RareCandy, 1); BAG_MAX as usize + 1];
+        assert_eq!(mmu.write_item_bag(&full), Err("Bag holds at most 20 items, got 21".to_string()));
+        assert_eq!(mmu.read_item_bag().unwrap(), items[..1]); // bag untouched
+        mmu.write_item_bag(&full[..BAG_MAX as usize]).unwrap();
+        assert_eq!(mmu.read_item_bag().unwrap().len(), BAG_MAX as usize);
+
+        mmu.write(BAG_COUNT_ADDRESS + 1, 0x60); // unused id
+        assert_eq!(mmu.read_item_bag(), Err("Invalid item id 0x60".to_string()));
+    }
+
+    #[test]
+    fn test_withdraw_from_box() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        let party: PokemonParty = [PokemonSpecies::Bulbasaur]
+            .into_iter()
+            .map(|species| Pokemon::maxed(species, "BULBA", [PokemonMoveName::Tackle; 4], "RED".to_string(), 1))
+            .collect();
+        mmu.write_party(party);
+
+        // box two Pokemon, boxed records are written by truncating a party block
+        let mut boxed = [
+            Pokemon::maxed(PokemonSpecies::Pikachu, "SPARKY", [PokemonMoveName::Thunderbolt; 4], "RED".to_string(), 1),
+            Pokemon::maxed(PokemonSpecies::Mew, "MEW", [PokemonMoveName::Psychic; 4], "RED".to_string(), 1),
+        ];
+        boxed[0].current_hp = 1;
+        mmu.write(BOX_COUNT_ADDRESS, boxed.len() as u8);
+        for (index, pokemon) in boxed.iter().enumerate() {
+            let mut scratch = MMU::from_rom(ROM).unwrap();
+            scratch.write_pokemon(PARTY_ADDRESS, 0, pokemon);
+            let addresses = PokemonBlockAddresses::of_box_indexed(BOX_ADDRESS, index as u16);
+            for offset in 0..PokemonBlockAddresses::BOX_POKEMON_BLOCK_SIZE {
+                mmu.write(addresses.pokemon + offset, scratch.read(PARTY_ADDRESS + offset));
+            }
+            mmu.write(addresses.pokemon + 3, pokemon.level); // box level
+            mmu.write_pokemon_string(addresses.nickname, &pokemon.nickname, PokemonBlockAddresses::NAME_LENGTH);
+            mmu.write_pokemon_string(addresses.trainer_name, &pokemon.trainer_name, PokemonBlockAddresses::NAME_LENGTH);
+            mmu.write(BOX_COUNT_ADDRESS + 1 + index as u16, pokemon.species as u8);
+        }
+        mmu.write(BOX_COUNT_ADDRESS + 1 + boxed.len() as u16, 0xFF);
+
+        mmu.withdraw_from_box(0).unwrap();
+
+        let party = mmu.read_party().unwrap();
+        assert_eq!(party.len(), 2);
+        let pikachu = &party[1];
+        assert_eq!(pikachu.nickname, "SPARKY");
+        assert_eq!(pikachu.level, 100);
+        assert_eq!(pikachu.stats, boxed[0].stats);
+        assert_eq!(pikachu.current_hp, pikachu.stats.hp); // fully healed
+        assert_eq!(mmu.read(PARTY_COUNT_ADDRESS + 2), PokemonSpecies::Pikachu as u8);
+
+        // mew shifted to the first box slot
+        assert_eq!(mmu.read(BOX_COUNT_ADDRESS), 1);
+        assert_eq!(mmu.read(BOX_COUNT_ADDRESS + 1), PokemonSpecies::Mew as u8);
+        assert_eq!(mmu.read(BOX_COUNT_ADDRESS + 2), 0xFF);
+        assert_eq!(mmu.read_box_pokemon(BOX_ADDRESS, 0).unwrap(), boxed[1]);
+
+        assert_eq!(mmu.withdraw_from_box(1), Err("No Pokemon in box slot 1, box has 1".to_string()));
+    }
+
+    #[test]
+    fn test_pc_box() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        let mut pc_box: PokemonBox = [PokemonSpecies::Pikachu, PokemonSpecies::Mew, PokemonSpecies::Onix]
+            .into_iter()
+            .map(|species| Pokemon::maxed(species, "BOXED", [PokemonMoveName::Tackle; 4], "RED".to_string(), 1))
+            .collect();
+        pc_box[1].current_hp = 1; // boxed Pokemon keep their current HP
+        mmu.write_pc_box(pc_box.clone());
+        assert_eq!(mmu.read(BOX_COUNT_ADDRESS), 3);
+        assert_eq!(mmu.read(BOX_COUNT_ADDRESS + 2), PokemonSpecies::Mew as u8);
+        assert_eq!(mmu.read(BOX_COUNT_ADDRESS + 4), 0xFF);
+
+        // nothing is written past the box block
+        let second = PokemonBlockAddresses::of_box_indexed(BOX_ADDRESS, 1);
+        assert_eq!(mmu.read(second.pokemon - 1), PokemonMoveName::Tackle.metadata().pp);
+        assert_eq!(mmu.read(second.pokemon), PokemonSpecies::Mew as u8);
+
+        assert_eq!(mmu.read_pc_box().unwrap(), pc_box);
+
+        let full: PokemonBox = std::iter::repeat_n(PokemonSpecies::Pidgey, 25)
+            .map(|species| Pokemon::maxed(species, "BIRD", [PokemonMoveName::Gust; 4], "RED".to_string(), 1))
+            .collect();
+        assert_eq!(full.len(), PokemonBlockAddresses::BOX_MAX as usize);
+        mmu.write_pc_box(full.clone());
+        assert_eq!(mmu.read_pc_box().unwrap(), full);
+    }
+
+    #[test]
+    fn test_withdraw_from_box_party_full() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        let party: PokemonParty = std::iter::repeat_n(PokemonSpecies::Rattata, PokemonBlockAddresses::PARTY_MAX as usize)
+            .map(|species| Pokemon::maxed(species, "RAT", [PokemonMoveName::Tackle; 4], "RED".to_string(), 1))
+            .collect();
+        mmu.write_party(party);
+        mmu.write(BOX_COUNT_ADDRESS, 1);
+        mmu.write(BOX_ADDRESS, PokemonSpecies::Pidgey as u8);
+
+        assert!(mmu.withdraw_from_box(0).is_err());
+        assert_eq!(mmu.read(BOX_COUNT_ADDRESS), 1); // box untouched
+    }
+
+    #[test]
+    fn test_party_iter() {
+        let party: PokemonParty = [PokemonSpecies::Bulbasaur, PokemonSpecies::Charmander, PokemonSpecies::Squirtle]
+            .into_iter()
+            .enumerate()
+            .map(|(index, species)| {
+                let mut pokemon = Pokemon::maxed(species, "MON", [PokemonMoveName::Tackle; 4], "RED".to_string(), 1);
+                pokemon.level = 5 + index as u8;
+                pokemon
+            })
+            .collect();
+
+        assert_eq!(party.len(), 3);
+        assert!(!party.is_empty());
+        assert_eq!(party.iter().map(|pokemon| pokemon.level as u32).sum::<u32>(), 18);
+        assert_eq!(party[2].species, PokemonSpecies::Squirtle);
+    }
+
+    #[test]
+    fn test_party_from_iter_truncates() {
+        let party: PokemonParty = std::iter::repeat_n(PokemonSpecies::Pikachu, 8)
+            .map(|species| Pokemon::maxed(species, "PIKA", [PokemonMoveName::Thunderbolt; 4], "ASH".to_string(), 1))
+            .collect();
+        assert_eq!(party.len(), PokemonBlockAddresses::PARTY_MAX as usize);
+        assert!(PokemonParty::default().is_empty());
+    }
+
+    #[test]
+    fn test_party_fix_experience_levels() {
+        let mut party: PokemonParty = [PokemonSpecies::Bulbasaur, PokemonSpecies::Charmander]
+            .into_iter()
+            .map(|species| Pokemon::maxed(species, "MON", [PokemonMoveName::Tackle; 4], "RED".to_string(), 1))
+            .collect();
+        assert_eq!(party.fix_experience_levels(), 0);
+
+        // save editors may set the experience without touching the level
+        party[1].experience = PokemonSpecies::Charmander.metadata().experience_group.experience_for_level(25);
+        assert_eq!(party.fix_experience_levels(), 1);
+        assert_eq!(party[0].level, 100);
+        assert_eq!(party[1].level, 25);
+        assert!(party[1].stats.hp < party[0].stats.hp);
+        assert_eq!(party.fix_experience_levels(), 0);
+    }
+
+    #[test]
+    fn test_party_sort_and_search() {
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        let mut party: PokemonParty = [(PokemonSpecies::Mew, 40), (PokemonSpecies::Pidgey, 3), (PokemonSpecies::Onix, 14)]
+            .into_iter()
+            .map(|(species, level)| {
+                let mut pokemon = Pokemon::maxed(species, "MON", [PokemonMoveName::Tackle; 4], "RED".to_string(), 1);
+                pokemon.level = level;
+                pokemon
+            })
+            .collect();
+
+        party.sort_by_level();
+        assert_eq!(party.iter().map(|pokemon| pokemon.level).collect::<Vec<_>>(), vec![3, 14, 40]);
+        assert_eq!(party.find_species(PokemonSpecies::Mew), Some(2));
+        assert_eq!(party.find_species(PokemonSpecies::Pikachu), None);
+
+        party.swap(0, 1).unwrap();
+        assert_eq!(party[0].species, PokemonSpecies::Onix);
+        assert_eq!(party.swap(0, 3), Err("No Pokemon in party slot 3, party has 3".to_string()));
+
+        mmu.write_party(party.clone());
+        assert_eq!(mmu.read(PARTY_COUNT_ADDRESS + 1), PokemonSpecies::Onix as u8);
+        assert_eq!(mmu.read_party().unwrap(), party);
+    }
+}
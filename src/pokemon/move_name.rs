@@ -1,8 +1,8 @@
-use crate::pokemon::pokemon::PokemonType;
+use crate::pokemon::pokemon::{Pokemon, PokemonType};
 use PokemonType::*;
 use MoveCategory::*;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct PokemonMove {
     pub name: PokemonMoveName,
     pub pp: u8
@@ -15,9 +15,27 @@ impl PokemonMove {
             pp: name.metadata().pp
         }
     }
+
+    pub fn restore_pp(&mut self) {
+        self.pp = self.name.metadata().pp;
+    }
+
+    /// Damage multiplier of this move against both of the defending Pokemon's types
+    pub fn effectiveness_against(&self, defending: &Pokemon) -> f32 {
+        PokemonType::effectiveness_against(self.name.metadata().move_type, defending.types)
+    }
+
+    /// Returns false if the move is out of PP and cannot be used
+    pub fn use_pp(&mut self) -> bool {
+        if self.pp == 0 {
+            return false;
+        }
+        self.pp -= 1;
+        true
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::Display, strum_macros::FromRepr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::Display, strum_macros::FromRepr, serde::Serialize, serde::Deserialize)]
 #[repr(u8)]
 pub enum PokemonMoveName {
     Pound = 0x1,
@@ -552,4 +570,26 @@ impl PokemonMoveMetadata {
     pub const SUBSTITUTE: Self = Self::new("Substitute", Normal, Status, None, None, 10);
     pub const STRUGGLE: Self = Self::new("Struggle", Normal, Physical, Some(50), None, 1);
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn use_and_restore_pp() {
+        let mut flamethrower = PokemonMove::new(PokemonMoveName::Flamethrower);
+        let max_pp = PokemonMoveName::Flamethrower.metadata().pp;
+        assert_eq!(flamethrower.pp, max_pp);
+
+        for remaining in (0..max_pp).rev() {
+            assert!(flamethrower.use_pp());
+            assert_eq!(flamethrower.pp, remaining);
+        }
+        assert!(!flamethrower.use_pp());
+        assert_eq!(flamethrower.pp, 0);
+
+        flamethrower.restore_pp();
+        assert_eq!(flamethrower.pp, max_pp);
+    }
+}
@@ -0,0 +1,89 @@
+/// The options menu settings, packed into a single byte
+/// https://github.com/pret/pokered/blob/master/ram/wram.asm (wOptions)
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct GameOptions {
+    pub text_speed: TextSpeed,
+    pub battle_style: BattleStyle,
+    pub battle_animations: bool,
+    pub sound: Sound,
+}
+
+/// Frames to delay after printing each letter
+#[derive(Debug, Clone, Copy, Eq, PartialEq, strum_macros::Display, strum_macros::FromRepr)]
+#[repr(u8)]
+pub enum TextSpeed {
+    Fast = 1,
+    Medium = 3,
+    Slow = 5,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, strum_macros::Display)]
+pub enum BattleStyle {
+    /// Offered to switch Pokemon after knocking out an opponent
+    Shift,
+    Set,
+}
+
+/// Red & Blue have no sound option, these bits are only read by Yellow but are preserved by every game
+#[derive(Debug, Clone, Copy, Eq, PartialEq, strum_macros::Display, strum_macros::FromRepr)]
+#[repr(u8)]
+pub enum Sound {
+    Mono = 0,
+    Earphone1 = 1,
+    Earphone2 = 2,
+    Earphone3 = 3,
+}
+
+const TEXT_SPEED_MASK: u8 = 0x0F;
+const SOUND_SHIFT: u8 = 4;
+const BATTLE_STYLE_SET: u8 = 0x40;
+const BATTLE_ANIMATIONS_OFF: u8 = 0x80;
+
+impl GameOptions {
+    pub fn parse(value: u8) -> Result<Self, String> {
+        Ok(Self {
+            text_speed: TextSpeed::from_repr(value & TEXT_SPEED_MASK)
+                .ok_or_else(|| format!("Invalid text speed {}", value & TEXT_SPEED_MASK))?,
+            battle_style: if value & BATTLE_STYLE_SET != 0 { BattleStyle::Set } else { BattleStyle::Shift },
+            battle_animations: value & BATTLE_ANIMATIONS_OFF == 0,
+            sound: Sound::from_repr((value >> SOUND_SHIFT) & 0x3).unwrap(),
+        })
+    }
+
+    pub fn bits(&self) -> u8 {
+        let mut value = self.text_speed as u8 | (self.sound as u8) << SOUND_SHIFT;
+        if self.battle_style == BattleStyle::Set {
+            value |= BATTLE_STYLE_SET;
+        }
+        if !self.battle_animations {
+            value |= BATTLE_ANIMATIONS_OFF;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        // new game default: medium text, animations on, shift style
+        assert_eq!(
+            GameOptions::parse(0x03).unwrap(),
+            GameOptions { text_speed: TextSpeed::Medium, battle_style: BattleStyle::Shift, battle_animations: true, sound: Sound::Mono }
+        );
+        assert_eq!(
+            GameOptions::parse(0xE5).unwrap(),
+            GameOptions { text_speed: TextSpeed::Slow, battle_style: BattleStyle::Set, battle_animations: false, sound: Sound::Earphone2 }
+        );
+        assert_eq!(GameOptions::parse(0x00), Err("Invalid text speed 0".to_string()));
+    }
+
+    #[test]
+    fn bits_round_trip() {
+        for value in [0x01, 0x03, 0x05, 0x41, 0x83, 0xF5] {
+            assert_eq!(GameOptions::parse(value).unwrap().bits(), value);
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use std::fmt::Write;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::pokemon::{encode_pokemon_grapheme, PokemonBlockAddresses};
 use crate::pokemon::move_name::{PokemonMove, PokemonMoveName};
 use crate::pokemon::species::PokemonSpecies;
 use crate::pokemon::status::PokemonStatus;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Pokemon {
     pub nickname: String,
     pub species: PokemonSpecies,
@@ -41,12 +44,22 @@ impl Pokemon {
         result
     }
     
+    pub fn set_nickname(&mut self, nickname: &str) -> Result<(), String> {
+        self.nickname = validate_name(nickname)?;
+        Ok(())
+    }
+
+    pub fn set_trainer_name(&mut self, trainer_name: &str) -> Result<(), String> {
+        self.trainer_name = validate_name(trainer_name)?;
+        Ok(())
+    }
+
     pub fn recalculate(&mut self) {
         let metadata = self.species.metadata();
         
         self.experience &= 0xFFFFFF;
         self.individual_values = self.individual_values.truncated_to_iv();
-        self.level = metadata.experience_group.level_from_experience(self.experience);
+        self.level = self.level_for_experience();
         self.stats = self.recalculated_stats();
         self.current_hp = self.current_hp.min(self.stats.hp);
 
@@ -63,6 +76,11 @@ impl Pokemon {
         self.types[1] = metadata.type2.unwrap_or(metadata.type1);
     }
 
+    /// The level this Pokemon should be at for its experience, which may not match the stored level
+    pub fn level_for_experience(&self) -> u8 {
+        self.species.metadata().experience_group.level_from_experience(self.experience & 0xFFFFFF)
+    }
+
     pub fn recalculated_stats(&self) -> PokemonStats {
         let base = self.species.metadata().base_stats;
         PokemonStats {
@@ -75,17 +93,63 @@ impl Pokemon {
     }
 
     fn stat0(&self, base_stat: u16, iv: u16, ev: u16) -> u16 {
-        //floor((((B + I) × 2 + floor(ceil(sqrt(E)) ÷ 4)) × L) ÷ 100)
-        ((2 * (base_stat + iv) + (ev as f64).sqrt().ceil() as u16 / 4) * self.level as u16) / 100
+        //floor((((B + I) × 2 + floor(min(255, ceil(sqrt(E))) ÷ 4)) × L) ÷ 100)
+        // the game's square root loop stops at 255, so max stat exp gives 63 rather than 64
+        let stat_exp = ((ev as f64).sqrt().ceil() as u16).min(255) / 4;
+        ((2 * (base_stat + iv) + stat_exp) * self.level as u16) / 100
     }
 
     fn stat(&self, base_stat: u16, iv: u16, ev: u16) -> u16 {
         self.stat0(base_stat, iv, ev) + 5
     }
 
+    /// Restores HP & PP and cures status conditions, like a Pokemon Center
+    pub fn heal(&mut self) {
+        self.current_hp = self.stats.hp;
+        self.status = PokemonStatus::None;
+        for pokemon_move in self.moves.iter_mut().flatten() {
+            pokemon_move.restore_pp();
+        }
+    }
+
+    /// Multi-line, human readable battle stats
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        writeln!(summary, "{} ({}) Lv{}", self.nickname, self.species.metadata().name, self.level).unwrap();
+        if self.types[0] == self.types[1] {
+            writeln!(summary, "Type: {}", self.types[0]).unwrap();
+        } else {
+            writeln!(summary, "Type: {}/{}", self.types[0], self.types[1]).unwrap();
+        }
+        writeln!(summary, "HP: {}/{}", self.current_hp, self.stats.hp).unwrap();
+        writeln!(
+            summary,
+            "Attack: {} Defense: {} Speed: {} Special: {}",
+            self.stats.attack, self.stats.defense, self.stats.speed, self.stats.special
+        ).unwrap();
+        summary.push_str("Moves:");
+        for pokemon_move in self.moves.iter().flatten() {
+            let metadata = pokemon_move.name.metadata();
+            write!(summary, "\n  {} {}/{}", metadata.name, pokemon_move.pp, metadata.pp).unwrap();
+        }
+        summary
+    }
+}
+
+/// Names must fit in the name buffer (less the terminator) & only use characters from the Gen 1 table
+fn validate_name(name: &str) -> Result<String, String> {
+    let max_length = PokemonBlockAddresses::NAME_LENGTH as usize - 1;
+    let graphemes: Vec<&str> = name.graphemes(true).collect();
+    if graphemes.len() > max_length {
+        return Err(format!("Name {} is longer than {} characters", name, max_length));
+    }
+    if let Some(grapheme) = graphemes.iter().find(|grapheme| encode_pokemon_grapheme(grapheme).is_none()) {
+        return Err(format!("Name {} contains unsupported character {}", name, grapheme));
+    }
+    Ok(name.to_string())
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct PokemonStats {
     pub attack: u16,
     pub defense: u16,
@@ -152,7 +216,7 @@ impl PokemonStats {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, strum_macros::Display, strum_macros::FromRepr)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, strum_macros::Display, strum_macros::FromRepr, serde::Serialize, serde::Deserialize)]
 #[repr(u8)]
 pub enum PokemonType {
     Normal = 0,
@@ -171,4 +235,183 @@ pub enum PokemonType {
     Psychic,
     Ice,
     Dragon,
-}
\ No newline at end of file
+}
+
+impl PokemonType {
+    /// Generation 1 damage multiplier of an attacking type against a single defending type
+    /// https://bulbapedia.bulbagarden.net/wiki/Type/Type_chart#Generation_I
+    pub fn effectiveness(attacking: PokemonType, defending: PokemonType) -> f32 {
+        use PokemonType::*;
+        match (attacking, defending) {
+            (Normal, Rock) => 0.5,
+            (Normal, Ghost) => 0.0,
+            (Fighting, Normal | Rock | Ice) => 2.0,
+            (Fighting, Flying | Poison | Bug | Psychic) => 0.5,
+            (Fighting, Ghost) => 0.0,
+            (Flying, Fighting | Bug | Grass) => 2.0,
+            (Flying, Rock | Electric) => 0.5,
+            (Poison, Bug | Grass) => 2.0,
+            (Poison, Poison | Ground | Rock | Ghost) => 0.5,
+            (Ground, Poison | Rock | Fire | Electric) => 2.0,
+            (Ground, Bug | Grass) => 0.5,
+            (Ground, Flying) => 0.0,
+            (Rock, Flying | Bug | Fire | Ice) => 2.0,
+            (Rock, Fighting | Ground) => 0.5,
+            (Bug, Poison | Grass | Psychic) => 2.0,
+            (Bug, Fighting | Flying | Ghost | Fire) => 0.5,
+            (Ghost, Ghost) => 2.0,
+            (Ghost, Normal) => 0.0,
+            (Ghost, Psychic) => 0.0, // meant to be super effective, but the game's type table has it as no effect
+            (Fire, Bug | Grass | Ice) => 2.0,
+            (Fire, Rock | Fire | Water | Dragon) => 0.5,
+            (Water, Ground | Rock | Fire) => 2.0,
+            (Water, Water | Grass | Dragon) => 0.5,
+            (Grass, Ground | Rock | Water) => 2.0,
+            (Grass, Flying | Poison | Bug | Fire | Grass | Dragon) => 0.5,
+            (Electric, Flying | Water) => 2.0,
+            (Electric, Grass | Electric | Dragon) => 0.5,
+            (Electric, Ground) => 0.0,
+            (Psychic, Fighting | Poison) => 2.0,
+            (Psychic, Psychic) => 0.5,
+            (Ice, Flying | Ground | Grass | Dragon) => 2.0,
+            (Ice, Water | Ice) => 0.5,
+            (Dragon, Dragon) => 2.0,
+            _ => 1.0,
+        }
+    }
+
+    /// Damage multiplier against both types of a Pokemon, single type Pokemon store their type twice
+    pub fn effectiveness_against(attacking: PokemonType, defending: [PokemonType; 2]) -> f32 {
+        let [type1, type2] = defending;
+        let effectiveness = Self::effectiveness(attacking, type1);
+        if type1 == type2 {
+            effectiveness
+        } else {
+            effectiveness * Self::effectiveness(attacking, type2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::MMU;
+    use crate::pokemon::PokemonEncoding;
+    use crate::roms::blargg_cpu::ROM;
+    use super::*;
+
+    fn pikachu() -> Pokemon {
+        Pokemon::maxed(PokemonSpecies::Pikachu, "PIKACHU", [PokemonMoveName::Thunderbolt; 4], "ASH".to_string(), 1)
+    }
+
+    #[test]
+    fn summary() {
+        let mut charizard = Pokemon::maxed(
+            PokemonSpecies::Charizard,
+            "BACON",
+            [PokemonMoveName::Flamethrower, PokemonMoveName::FireBlast, PokemonMoveName::Fly, PokemonMoveName::Slash],
+            "LLM".to_string(),
+            57937,
+        );
+        charizard.experience = 6457;
+        charizard.recalculate();
+
+        let summary = charizard.summary();
+        assert!(summary.starts_with("BACON (Charizard) Lv20\n"), "{}", summary);
+        assert!(summary.contains("Type: Fire/Flying"), "{}", summary);
+        assert!(summary.contains(&format!("HP: {}/{}", charizard.current_hp, charizard.stats.hp)), "{}", summary);
+        assert!(summary.contains("Flamethrower 15/15"), "{}", summary);
+        assert_eq!(summary.lines().count(), 9);
+    }
+
+    #[test]
+    fn recalculate_max_stats() {
+        let tauros = Pokemon::maxed(PokemonSpecies::Tauros, "TAUROS", [PokemonMoveName::BodySlam; 4], "RED".to_string(), 1);
+        assert_eq!(tauros.level, 100);
+        assert_eq!(tauros.stats, PokemonStats::new(353, 298, 288, 318, 238));
+
+        let mut weak = tauros.clone();
+        weak.effort_values = PokemonStats::ZERO;
+        weak.individual_values = PokemonStats::ZERO;
+        weak.recalculate();
+        assert_eq!(weak.stats, PokemonStats::new(260, 205, 195, 225, 145));
+    }
+
+    #[test]
+    fn level_for_experience() {
+        let mut pokemon = pikachu(); // medium fast
+        pokemon.experience = 1000;
+        assert_eq!(pokemon.level_for_experience(), 10);
+        pokemon.experience = 1330;
+        assert_eq!(pokemon.level_for_experience(), 10);
+        pokemon.experience = 1331;
+        assert_eq!(pokemon.level_for_experience(), 11);
+        assert_eq!(pokemon.level, 100); // the stored level is untouched
+    }
+
+    #[test]
+    fn type_effectiveness() {
+        use PokemonType::*;
+        assert_eq!(PokemonType::effectiveness(Water, Fire), 2.0);
+        assert_eq!(PokemonType::effectiveness(Fire, Water), 0.5);
+        assert_eq!(PokemonType::effectiveness(Normal, Ghost), 0.0);
+        assert_eq!(PokemonType::effectiveness(Ghost, Psychic), 0.0); // gen 1 bug
+        assert_eq!(PokemonType::effectiveness(Bug, Poison), 2.0); // only in gen 1
+        assert_eq!(PokemonType::effectiveness(Poison, Bug), 2.0);
+        assert_eq!(PokemonType::effectiveness(Ice, Fire), 1.0);
+        assert_eq!(PokemonType::effectiveness(Normal, Normal), 1.0);
+
+        assert_eq!(PokemonType::effectiveness_against(Ice, [Dragon, Flying]), 4.0);
+        assert_eq!(PokemonType::effectiveness_against(Electric, [Water, Water]), 2.0);
+        assert_eq!(PokemonType::effectiveness_against(Ground, [Rock, Flying]), 0.0);
+        assert_eq!(PokemonType::effectiveness_against(Fire, [Grass, Poison]), 2.0);
+    }
+
+    #[test]
+    fn move_effectiveness() {
+        let charizard = Pokemon::maxed(PokemonSpecies::Charizard, "BACON", [PokemonMoveName::Flamethrower; 4], "LLM".to_string(), 1);
+        assert_eq!(PokemonMove::new(PokemonMoveName::RockSlide).effectiveness_against(&charizard), 4.0);
+        assert_eq!(PokemonMove::new(PokemonMoveName::Earthquake).effectiveness_against(&charizard), 0.0);
+        assert_eq!(PokemonMove::new(PokemonMoveName::Tackle).effectiveness_against(&charizard), 1.0);
+    }
+
+    #[test]
+    fn heal() {
+        let mut pokemon = pikachu();
+        pokemon.current_hp = 1;
+        pokemon.status = PokemonStatus::Poisoned;
+        pokemon.moves[0].as_mut().unwrap().pp = 0;
+        pokemon.heal();
+
+        assert_eq!(pokemon, pikachu());
+    }
+
+    #[test]
+    fn set_name_too_long() {
+        let mut pokemon = pikachu();
+        assert_eq!(pokemon.set_nickname("SPARKYSPARK"), Err("Name SPARKYSPARK is longer than 10 characters".to_string()));
+        assert_eq!(pokemon.set_trainer_name("AAAAAAAAAAA"), Err("Name AAAAAAAAAAA is longer than 10 characters".to_string()));
+        assert_eq!(pokemon.nickname, "PIKACHU");
+        assert_eq!(pokemon.trainer_name, "ASH");
+    }
+
+    #[test]
+    fn set_name_unsupported_character() {
+        let mut pokemon = pikachu();
+        assert_eq!(pokemon.set_nickname("PIKA⚡"), Err("Name PIKA⚡ contains unsupported character ⚡".to_string()));
+        assert_eq!(pokemon.set_nickname("PIKA_"), Err("Name PIKA_ contains unsupported character _".to_string()));
+        assert_eq!(pokemon.nickname, "PIKACHU");
+    }
+
+    #[test]
+    fn set_name_round_trip() {
+        let mut pokemon = pikachu();
+        pokemon.set_nickname("Sparky♂").unwrap();
+        pokemon.set_trainer_name("RED-10").unwrap();
+
+        let mut mmu = MMU::from_rom(ROM).unwrap();
+        mmu.write_pokemon(0xD16B, 0, &pokemon);
+        let read = mmu.read_pokemon(0xD16B, 0).unwrap();
+        assert_eq!(read.nickname, "Sparky♂");
+        assert_eq!(read.trainer_name, "RED-10");
+    }
+}
@@ -1,6 +1,6 @@
 use crate::pokemon::pokemon::{Pokemon, PokemonStats, PokemonType};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::Display, strum_macros::FromRepr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::Display, strum_macros::FromRepr, serde::Serialize, serde::Deserialize)]
 #[repr(u8)]
 pub enum PokemonSpecies {
     Rhydon = 0x1,
@@ -13,7 +13,10 @@ bitflags! {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Set by Toxic in the battle status 3 byte (wPlayerBattleStatus3/wEnemyBattleStatus3), alongside the poisoned bit in the status byte
+const BADLY_POISONED: u8 = 0b0000_0001; // bit 0
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
 pub enum PokemonStatus {
     #[default]
     None,
@@ -21,9 +24,46 @@ pub enum PokemonStatus {
     Frozen,
     Burned,
     Poisoned,
+    /// Toxic, only exists in battle, it is written to the status byte as regular poison as the game does after battle
+    BadlyPoisoned,
     Asleep { counter: u8 },
 }
 
+impl PokemonStatus {
+    /// The status of a Pokemon in battle, Toxic is tracked in the battle status 3 byte rather than the status byte
+    pub fn in_battle(status: u8, battle_status3: u8) -> Self {
+        match Self::from(status) {
+            PokemonStatus::Poisoned if battle_status3 & BADLY_POISONED != 0 => PokemonStatus::BadlyPoisoned,
+            status => status,
+        }
+    }
+
+    /// Turns of sleep remaining, stored in the low 3 bits of the status byte
+    pub fn sleep_turns(&self) -> Option<u8> {
+        match self {
+            PokemonStatus::Asleep { counter } => Some(*counter),
+            _ => None,
+        }
+    }
+
+    /// Either regular poison or Toxic
+    pub fn is_poisoned(&self) -> bool {
+        matches!(self, PokemonStatus::Poisoned | PokemonStatus::BadlyPoisoned)
+    }
+
+    pub fn is_badly_poisoned(&self) -> bool {
+        *self == PokemonStatus::BadlyPoisoned
+    }
+
+    /// Only one status condition can be active so this replaces any other, zero turns wakes the Pokemon up
+    pub fn set_sleep(&mut self, turns: u8) {
+        *self = match turns & 0b111 {
+            0 => PokemonStatus::None,
+            counter => PokemonStatus::Asleep { counter },
+        };
+    }
+}
+
 impl From<PokemonStatusFlags> for PokemonStatus {
     fn from(value: PokemonStatusFlags) -> Self {
         if value.contains(PokemonStatusFlags::Paralyzed) {
@@ -55,7 +95,7 @@ impl Into<PokemonStatusFlags> for PokemonStatus {
             PokemonStatus::Paralyzed => PokemonStatusFlags::Paralyzed,
             PokemonStatus::Frozen => PokemonStatusFlags::Frozen,
             PokemonStatus::Burned => PokemonStatusFlags::Burned,
-            PokemonStatus::Poisoned => PokemonStatusFlags::Poisoned,
+            PokemonStatus::Poisoned | PokemonStatus::BadlyPoisoned => PokemonStatusFlags::Poisoned,
             PokemonStatus::Asleep { counter } => PokemonStatusFlags::from_bits(counter & 0b111).unwrap(),
         }
     }
@@ -66,4 +106,52 @@ impl Into<u8> for PokemonStatus {
         let flags: PokemonStatusFlags = self.into();
         flags.bits()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_turns() {
+        let status = PokemonStatus::from(0b0000_0011);
+        assert_eq!(status.sleep_turns(), Some(3));
+        assert!(!status.is_poisoned());
+        assert_eq!(PokemonStatus::Burned.sleep_turns(), None);
+    }
+
+    #[test]
+    fn set_sleep() {
+        let mut status = PokemonStatus::Poisoned;
+        assert!(status.is_poisoned());
+        status.set_sleep(5);
+        assert!(!status.is_poisoned());
+        assert_eq!(status, PokemonStatus::Asleep { counter: 5 });
+        assert_eq!(Into::<u8>::into(status), 0b0000_0101);
+
+        status.set_sleep(0);
+        assert_eq!(status, PokemonStatus::None);
+    }
+
+    #[test]
+    fn poisoned() {
+        assert!(PokemonStatus::from(0b0000_1000).is_poisoned());
+        assert!(!PokemonStatus::from(0b0100_0000).is_poisoned());
+        assert!(!PokemonStatus::from(0b0000_1000).is_badly_poisoned());
+    }
+
+    #[test]
+    fn badly_poisoned() {
+        let status = PokemonStatus::in_battle(0b0000_1000, BADLY_POISONED);
+        assert!(status.is_badly_poisoned());
+        assert!(status.is_poisoned());
+
+        // the toxic bit alone is not poison
+        assert_eq!(PokemonStatus::in_battle(0b0000_0000, BADLY_POISONED), PokemonStatus::None);
+        assert_eq!(PokemonStatus::in_battle(0b0000_1000, 0), PokemonStatus::Poisoned);
+
+        // written back to the status byte as regular poison
+        assert_eq!(Into::<u8>::into(status), 0b0000_1000);
+        assert!(!PokemonStatus::from(Into::<u8>::into(status)).is_badly_poisoned());
+    }
+}
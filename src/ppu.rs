@@ -5,7 +5,7 @@ use crate::geometry::Point8;
 use crate::activation::Activation;
 use crate::lcd_control::{LcdControl, ObjectSizeMode, TileDataMode, TileMapMode};
 use crate::lcd_dma::LcdDma;
-use crate::lcd_palette::{DMGColor, DMGPaletteRegister, LcdPalette};
+use crate::lcd_palette::{CgbPalette, DMGColor, DMGPalette, DMGPaletteRegister, LcdPalette};
 use crate::lcd_status::{LcdMode, LcdStatus};
 use image::{ImageBuffer, Rgb, RgbImage};
 use itertools::Itertools;
@@ -23,40 +23,49 @@ pub struct PPU {
     dma: LcdDma,
     lcd: [DMGColor; LCD_WIDTH * LCD_HEIGHT],
     current_ticks: usize, // Current machine cycles
+    restrict_access: bool, // block CPU access to VRAM & OAM while the PPU is using them
+    oam_bug: bool, // emulate OAM corruption from 16-bit inc/dec during OAM scan
 
     // TODO move all these into a separate struct for the current frame state
     current_x: usize,
     window_state: WindowRenderState,
-    scanline_sprites: Vec<Sprite>
+    scanline_sprites: Vec<OamEntry>,
+    drawing_ticks: usize, // length of mode 3 on the current scanline
+    frame_count: u64, // frames completed since power on
+    frame_ready: bool, // the last update entered VBlank
 }
 
+/// The window has its own line counter which only advances on scanlines where the window was drawn,
+/// so hiding the window mid-frame continues from the same window line when it is shown again.
+/// https://gbdev.io/pandocs/Tile_Maps.html#window
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Decode, Encode)]
 pub struct WindowRenderState {
-    is_active: bool,
-    max_y: usize,
-    window_y: usize,
+    is_active: bool, // WY = LY has been true at some point in the current frame
+    line: usize, // internal window line counter
+    line_drawn: bool, // the window was drawn on the current scanline
 }
 
 impl WindowRenderState {
-    pub fn update_if_active(&mut self, y: usize) {
-        if self.is_active {
-            if y > self.max_y {
-                self.window_y += 1;
-                self.max_y = y;
-            }
-        }
+    pub fn activate(&mut self) {
+        self.is_active = true;
     }
 
-    pub fn activate(&mut self, y: usize, window_position: Point8) {
-        self.is_active = true;
-        self.max_y = y;
-        self.window_y = y - window_position.y as usize;
+    pub fn mark_drawn(&mut self) {
+        self.line_drawn = true;
+    }
+
+    /// Advance the window line counter if the window was drawn on the scanline that just finished
+    pub fn end_scanline(&mut self) {
+        if self.line_drawn {
+            self.line += 1;
+            self.line_drawn = false;
+        }
     }
 
     pub fn deactivate(&mut self) {
         self.is_active = false;
-        self.max_y = 0;
-        self.window_y = 0;
+        self.line = 0;
+        self.line_drawn = false;
     }
 }
 
@@ -74,9 +83,14 @@ impl Default for PPU {
             dma: LcdDma::default(),
             lcd: [DMGColor::White; LCD_WIDTH * LCD_HEIGHT],
             current_ticks: 0,
+            restrict_access: true,
+            oam_bug: true,
             current_x: 0,
             window_state: WindowRenderState::default(),
             scanline_sprites: vec![],
+            drawing_ticks: MIN_DRAWING_TICKS,
+            frame_count: 0,
+            frame_ready: false,
         }
     }
 }
@@ -86,8 +100,72 @@ impl PPU {
         &self.lcd
     }
 
+    /// Raw VRAM, ignores PPU mode access restrictions
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+
+    pub fn vram_mut(&mut self) -> &mut [u8] {
+        &mut self.vram
+    }
+
+    /// Raw OAM, ignores PPU mode access restrictions
+    pub fn oam(&self) -> &[u8] {
+        &self.oam
+    }
+
+    pub fn oam_mut(&mut self) -> &mut [u8] {
+        &mut self.oam
+    }
+
+    /// Enabled by default, when disabled VRAM & OAM are accessible in every PPU mode for lenient emulation
+    pub fn set_restrict_access(&mut self, enabled: bool) {
+        self.restrict_access = enabled;
+    }
+
+    /// Enabled by default, when disabled OAM is never corrupted by 16-bit inc/dec
+    pub fn set_oam_bug(&mut self, enabled: bool) {
+        self.oam_bug = enabled;
+    }
+
+    /// A 16-bit inc/dec with a register in 0xFE00-0xFEFF corrupts the OAM row being scanned in mode 2
+    /// https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+    pub fn trigger_oam_bug(&mut self, address: u16) {
+        if !self.oam_bug || !(0xFE00..=0xFEFF).contains(&address)
+            || !self.lcd_control.is_enabled() || self.lcd_status.mode() != LcdMode::OAM {
+            return;
+        }
+
+        // the PPU reads one 8 byte row per machine cycle, the first row is never corrupted
+        let row = self.current_ticks / 4;
+        if row == 0 || row >= OAM_ROWS {
+            return;
+        }
+        let offset = row * OAM_ROW_BYTES;
+        let word = |index: usize| u16::from_le_bytes([self.oam[index], self.oam[index + 1]]);
+        let a = word(offset);
+        let b = word(offset - OAM_ROW_BYTES);
+        let c = word(offset - OAM_ROW_BYTES + 4);
+        let corrupted = ((a ^ c) & (b ^ c)) ^ c;
+        self.oam[offset..offset + 2].copy_from_slice(&corrupted.to_le_bytes());
+        self.oam.copy_within(offset - OAM_ROW_BYTES + 2..offset, offset + 2);
+    }
+
+    fn vram_accessible(&self) -> bool {
+        !self.restrict_access || self.lcd_status.mode().vram_accessible()
+    }
+
+    fn oam_accessible(&self) -> bool {
+        !self.restrict_access || self.lcd_status.mode().oam_accessible()
+    }
+
+    /// The CPU can only access HRAM & IO registers while an OAM DMA is in progress
+    pub fn dma_blocks_bus(&self) -> bool {
+        self.restrict_access && self.dma.is_active()
+    }
+
     pub fn read_vram(&self, address: u16) -> u8 {
-        if self.lcd_status.mode().vram_accessible() || self.dma.is_active() {
+        if self.vram_accessible() {
             self.vram[address as usize]
         } else {
             // garbage data https://gbdev.io/pandocs/Rendering.html
@@ -96,13 +174,13 @@ impl PPU {
     }
 
     pub fn write_vram(&mut self, address: u16, value: u8) {
-        if self.lcd_status.mode().vram_accessible() || self.dma.is_active() {
+        if self.vram_accessible() {
             self.vram[address as usize] = value;
         }
     }
 
     pub fn read_oam(&self, address: u16) -> u8 {
-        if self.lcd_status.mode().oam_accessible() || self.dma.is_active() {
+        if self.oam_accessible() {
             self.oam[address as usize]
         } else {
             // garbage data https://gbdev.io/pandocs/Rendering.html
@@ -111,11 +189,34 @@ impl PPU {
     }
 
     pub fn write_oam(&mut self, address: u16, value: u8) {
-        if self.lcd_status.mode().oam_accessible() || self.dma.is_active() {
+        if self.oam_accessible() {
             self.oam[address as usize] = value;
         }
     }
 
+    /// BCPD or OCPD, CGB palette RAM is inaccessible in mode 3 like VRAM https://gbdev.io/pandocs/Palettes.html#ff69--bcpdbgpd-cgb-mode-only-background-color-palette-data--background-palette-data
+    pub fn read_cgb_palette(&self, object: bool) -> u8 {
+        if self.vram_accessible() {
+            self.cgb_palette(object).data()
+        } else {
+            0xff
+        }
+    }
+
+    pub fn write_cgb_palette(&mut self, object: bool, value: u8) {
+        let accessible = self.vram_accessible();
+        let palette = if object { self.palette.cgb_object_mut() } else { self.palette.cgb_background_mut() };
+        if accessible {
+            palette.set_data(value);
+        } else {
+            palette.increment();
+        }
+    }
+
+    fn cgb_palette(&self, object: bool) -> &CgbPalette {
+        if object { self.palette.cgb_object() } else { self.palette.cgb_background() }
+    }
+
     pub fn lcd_control(&self) -> &LcdControl {
         &self.lcd_control
     }
@@ -124,6 +225,33 @@ impl PPU {
         &mut self.lcd_control
     }
 
+    /// Write LCDC, switching the LCD off stops the PPU and blanks the screen, switching it back on restarts the frame
+    pub fn set_lcd_control(&mut self, value: u8) {
+        let was_enabled = self.lcd_control.is_enabled();
+        self.lcd_control.set(value);
+        match (was_enabled, self.lcd_control.is_enabled()) {
+            (true, false) => {
+                // https://gbdev.io/pandocs/LCDC.html#lcdc7--lcd-enable
+                self.lcd_status.reset(LcdMode::HBlank);
+                self.lcd = [DMGColor::White; LCD_WIDTH * LCD_HEIGHT];
+                self.reset_frame();
+            }
+            (false, true) => {
+                self.lcd_status.reset(LcdMode::OAM);
+                self.reset_frame();
+            }
+            _ => {}
+        }
+    }
+
+    fn reset_frame(&mut self) {
+        self.current_ticks = 0;
+        self.current_x = 0;
+        self.window_state.deactivate();
+        self.scanline_sprites.clear();
+        self.vblank_interrupt_pending = false;
+    }
+
     pub fn lcd_status(&self) -> &LcdStatus {
         &self.lcd_status
     }
@@ -132,6 +260,11 @@ impl PPU {
         &mut self.lcd_status
     }
 
+    /// The scanline currently being drawn, 0-143 are visible and 144-153 are VBlank
+    pub fn ly(&self) -> u8 {
+        self.lcd_status.ly()
+    }
+
     pub fn scroll(&self) -> &Point8 {
         &self.scroll
     }
@@ -164,18 +297,62 @@ impl PPU {
         &mut self.dma
     }
 
-    /// Generate a screenshot of the current PPU state as an in-memory RGB image
+    /// The LCD as 2-bit shade indices, i.e. after the BGP/OBP registers are applied but before any RGB conversion
+    pub fn color_indices(&self) -> [u8; LCD_WIDTH * LCD_HEIGHT] {
+        self.lcd.map(|color| color as u8)
+    }
+
+    /// A stable FNV-1a hash of the LCD shade indices, for comparing frames without reference images
+    pub fn frame_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xCBF29CE484222325;
+        const FNV_PRIME: u64 = 0x100000001B3;
+        self.lcd.iter().fold(FNV_OFFSET_BASIS, |hash, &color| (hash ^ color as u64).wrapping_mul(FNV_PRIME))
+    }
+
+    /// The LCD through the palette color map as packed `0x00RRGGBB` pixels in row order
+    pub fn framebuffer(&self) -> Vec<u32> {
+        let color_map = self.palette.color_map();
+        self.lcd.iter()
+            .map(|&color| {
+                let [r, g, b] = color_map.rgb(color).0;
+                u32::from_be_bytes([0, r, g, b])
+            })
+            .collect()
+    }
+
+    /// Generate a screenshot of the current PPU state as an in-memory RGB image, through the palette color map
     pub fn screenshot(&self) -> RgbImage {
+        self.to_rgb8(&self.palette.color_map())
+    }
+
+    pub fn to_rgb8(&self, palette: &DMGPalette) -> RgbImage {
         let mut img = ImageBuffer::new(LCD_WIDTH as u32, LCD_HEIGHT as u32);
         for y in 0..LCD_HEIGHT {
             for x in 0..LCD_WIDTH {
-                let rgb_color = self.lcd[y * LCD_WIDTH + x].to_rgb();
+                let rgb_color = palette.rgb(self.lcd[y * LCD_WIDTH + x]);
                 img.put_pixel(x as u32, y as u32, rgb_color);
             }
         }
         img
     }
 
+    /// Every tile in VRAM 0x8000-0x97FF as 2-bit color indices in row order, before any palette is applied
+    pub fn tile_data(&self) -> Vec<[u8; TILE_PIXELS * TILE_PIXELS]> {
+        self.vram[..TILE_COUNT * TILE_BYTES]
+            .chunks_exact(TILE_BYTES)
+            .map(|data| {
+                let tile = Tile::new(data);
+                std::array::from_fn(|i| tile.pixel(i % TILE_PIXELS, i / TILE_PIXELS))
+            })
+            .collect()
+    }
+
+    /// Tile indices of the 0x9800 or 0x9C00 tile map, indexed by row then column
+    pub fn background_map(&self, map: TileMapMode) -> [[u8; TILE_MAP_SIZE]; TILE_MAP_SIZE] {
+        let tile_map = self.tile_map(map);
+        std::array::from_fn(|y| std::array::from_fn(|x| tile_map.tile_index(x, y)))
+    }
+
     pub fn dump_tilemap(&self, tile_map_mode: TileMapMode, data_mode: TileDataMode) -> RgbImage {
         let tile_map = self.tile_map(tile_map_mode);
         let mut img = ImageBuffer::new(TILE_MAP_PIXELS as u32, TILE_MAP_PIXELS as u32);
@@ -189,13 +366,23 @@ impl PPU {
         img
     }
 
+    /// Frames completed since power on, a frame completes when the PPU enters VBlank
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// True only for the update that completed a frame
+    pub fn frame_ready(&self) -> bool {
+        self.frame_ready
+    }
+
     pub fn update(&mut self, delta_machine_cycles: MachineCycles) {
+        self.frame_ready = false;
         if !self.lcd_control.is_enabled() {
-            // TODO should the screen be blanked?
-            return
+            return // the screen is blanked when the LCD is switched off
         }
 
-        self.current_ticks += delta_machine_cycles.t_cycles(); // TODO the PPU is twice as slow in CGB double speed mode
+        self.current_ticks += delta_machine_cycles.t_cycles(); // always normal speed cycles, the MMU scales them in CGB double speed mode
 
         match self.lcd_status.mode() {
             LcdMode::OAM => {
@@ -206,37 +393,35 @@ impl PPU {
                     let y = self.lcd_status.ly() as isize;
                     let sprite_height = self.lcd_control.object_size().height() as isize;
                     self.scanline_sprites = if self.lcd_control.objects_enabled() {
-                        self.sprites().into_iter()
+                        self.oam_entries().into_iter()
                             .filter(|sprite| y >= sprite.y && y < sprite.y + sprite_height)
+                            // the first 10 in OAM order are selected, even if they are off screen horizontally
                             .take(MAX_SPRITES_PER_SCANLINE)
                             .collect()
                     } else {
                         vec![]
-                    }
+                    };
+                    self.drawing_ticks = self.scanline_drawing_ticks();
                 }
             }
             LcdMode::Drawing => {
-                let drawing_ticks = INITIAL_FIFO_LOAD_TICKS + LCD_WIDTH;
-
-                if self.current_ticks >= drawing_ticks {
+                if self.current_ticks >= self.drawing_ticks {
                     self.lcd_status.set_mode(LcdMode::HBlank); // drawing done
-                    self.current_ticks -= drawing_ticks;
+                    self.current_ticks -= self.drawing_ticks;
                 } else if self.current_ticks >= INITIAL_FIFO_LOAD_TICKS {
                     let start_x = self.current_x;
                     let end_x = start_x + self.current_ticks - INITIAL_FIFO_LOAD_TICKS + 1;
                     let y = self.lcd_status.ly() as usize;
 
-                    if self.lcd_status.ly() == self.window_position.y && !self.window_state.is_active {
-                        self.window_state.activate(y, self.window_position);
+                    if self.lcd_status.ly() == self.window_position.y {
+                        self.window_state.activate();
                     }
 
-                    let mut row_in_window = false;
                     for x in start_x..end_x {
                         if x < LCD_WIDTH {
-                            let pixel_in_window = self.in_window(x, y);
-                            if pixel_in_window && !row_in_window {
-                                row_in_window = true;
-                                self.window_state.update_if_active(y);
+                            let pixel_in_window = self.in_window(x);
+                            if pixel_in_window {
+                                self.window_state.mark_drawn();
                             }
 
                             let bg_color_index = if pixel_in_window {
@@ -246,16 +431,21 @@ impl PPU {
                             } else {
                                 0
                             } as usize;
-                            let bg_color = self.palette.background()[bg_color_index];
+                            // with LCDC bit 0 clear the background & window are blank, regardless of the palette
+                            let bg_color = if self.lcd_control.background_enabled() {
+                                self.palette.background()[bg_color_index]
+                            } else {
+                                DMGColor::White
+                            };
 
                             let color = self.scanline_sprites.iter()
                                 .filter(|sprite| sprite.x <= x as isize && sprite.x + TILE_PIXELS as isize > x as isize)
                                 .map(|sprite| (sprite, self.sprite_pixel(sprite, x, y)))
                                 .filter(|&(_, sprite_color)| sprite_color != 0) // filter out transparent pixels
-                                .sorted_by_key(|&(sprite, _)| sprite.x) // overlapping sprites are sorted by x position
+                                .sorted_by_key(|&(sprite, _)| sprite.x) // smaller x wins, the stable sort keeps OAM order for equal x
                                 .next()
                                 .map_or(bg_color, |(sprite, sprite_color)| {
-                                    if sprite_color == 0 || sprite.bg_priority && bg_color_index != 0 {
+                                    if sprite_color == 0 || sprite.priority && bg_color_index != 0 {
                                         bg_color
                                     } else {
                                         sprite.palette(&self.palette)[sprite_color as usize]
@@ -269,17 +459,20 @@ impl PPU {
                 }
             }
             LcdMode::HBlank => {
-                // TODO vary the length of the HBlank period based on the length of the Drawing phase
-                let hblank_ticks = SCANLINE_TICKS - OAM_TICKS - INITIAL_FIFO_LOAD_TICKS - LCD_WIDTH;
+                // HBlank absorbs whatever the Drawing phase didn't use so every scanline is the same length
+                let hblank_ticks = SCANLINE_TICKS - OAM_TICKS - self.drawing_ticks;
                 if self.current_ticks >= hblank_ticks {
                     // hblank finished, go to next scanline
                     self.current_ticks -= hblank_ticks;
                     self.current_x = 0; // reset X for the next scanline
+                    self.window_state.end_scanline();
                     let next_ly = self.lcd_status.increment_ly();
 
                     if next_ly >= LCD_HEIGHT as u8 {
                         // Enter VBlank mode
                         self.vblank_interrupt_pending = true;
+                        self.frame_ready = true;
+                        self.frame_count += 1;
                         self.lcd_status.set_mode(LcdMode::VBlank);
                     } else {
                         // Continue to OAM mode for the next scanline
@@ -301,6 +494,39 @@ impl PPU {
         }
     }
 
+    /// Mode 3 is extended by discarding SCX % 8 pixels, fetching the window & fetching each sprite on the scanline.
+    /// https://gbdev.io/pandocs/Rendering.html#mode-3-length
+    fn scanline_drawing_ticks(&self) -> usize {
+        let mut ticks = MIN_DRAWING_TICKS + self.scroll.x as usize % TILE_PIXELS;
+
+        let window_visible = self.lcd_control.window_enabled()
+            && (self.window_state.is_active || self.lcd_status.ly() == self.window_position.y)
+            && (self.window_position.x as usize) < LCD_WIDTH + 7;
+        if window_visible {
+            ticks += WINDOW_FETCH_TICKS;
+        }
+
+        let mut fetched_tiles = vec![];
+        for sprite in self.scanline_sprites.iter().sorted_by_key(|sprite| sprite.x) {
+            let oam_x = (sprite.x + 8) as usize;
+            if oam_x >= LCD_WIDTH + 8 {
+                continue; // never reached by the fetcher
+            }
+            ticks += SPRITE_FETCH_TICKS;
+
+            // the first sprite in each background tile also waits for that tile fetch to finish
+            let scrolled_x = oam_x + self.scroll.x as usize;
+            let tile = scrolled_x / TILE_PIXELS;
+            if oam_x == 0 {
+                ticks += 5;
+            } else if !fetched_tiles.contains(&tile) {
+                ticks += 5usize.saturating_sub(scrolled_x % TILE_PIXELS);
+            }
+            fetched_tiles.push(tile);
+        }
+        ticks
+    }
+
     fn tile(&self, mode: TileDataMode, index: u8) -> Tile {
         let address = mode.tile_address(index) as usize - VRAM_BASE_ADDRESS;
         Tile::new(&self.vram[address..address + TILE_BYTES])
@@ -315,7 +541,7 @@ impl PPU {
     ///     Bit 5 of the LCDC register is set to 1
     ///     The condition WY = LY has been true at any point in the currently rendered frame.
     ///     The current X-position of the shifter is greater than or equal to WX - 7
-    fn in_window(&self, x: usize, y: usize) -> bool {
+    fn in_window(&self, x: usize) -> bool {
         self.lcd_control.window_enabled() &&
             self.window_state.is_active &&
             x >= self.window_position.x.saturating_sub(7) as usize
@@ -328,8 +554,8 @@ impl PPU {
             self.lcd_control.tile_data_mode(),
             // x+7 because window starts at x position - 7
             x + 7 - self.window_position.x as usize,
-            // the y coordinate is derived from the total number of window lines rendered
-            self.window_state.window_y
+            // the y coordinate is the internal window line counter, not LY - WY
+            self.window_state.line
         )
     }
 
@@ -343,21 +569,21 @@ impl PPU {
         )
     }
 
-    fn sprite_pixel(&self, sprite: &Sprite, x: usize, y: usize) -> u8 {
+    fn sprite_pixel(&self, sprite: &OamEntry, x: usize, y: usize) -> u8 {
         let object_size = self.lcd_control.object_size();
         let sprite_x = (x as isize - sprite.x) as usize;
-        let pixel_x = if sprite.flip_x { TILE_PIXELS - 1 - sprite_x } else { sprite_x };
+        let pixel_x = if sprite.x_flip { TILE_PIXELS - 1 - sprite_x } else { sprite_x };
         let sprite_y = (y as isize - sprite.y) as usize;
-        let pixel_y = if sprite.flip_y { object_size.height() - 1 - sprite_y } else { sprite_y };
+        let pixel_y = if sprite.y_flip { object_size.height() - 1 - sprite_y } else { sprite_y };
 
         match object_size {
-            ObjectSizeMode::Single => self.tile(TileDataMode::Lower, sprite.tile_index).pixel(pixel_x, pixel_y),
+            ObjectSizeMode::Single => self.tile(TileDataMode::Lower, sprite.tile).pixel(pixel_x, pixel_y),
             ObjectSizeMode::Double => {
                 if pixel_y < TILE_PIXELS {
-                    self.tile(TileDataMode::Lower, sprite.tile_index & 0xFE)
+                    self.tile(TileDataMode::Lower, sprite.tile & 0xFE)
                         .pixel(pixel_x, pixel_y)
                 } else {
-                    self.tile(TileDataMode::Lower, sprite.tile_index | 0x01)
+                    self.tile(TileDataMode::Lower, sprite.tile | 0x01)
                         .pixel(pixel_x, pixel_y - TILE_PIXELS)
                 }
             }
@@ -372,13 +598,12 @@ impl PPU {
 
 
 
-    fn sprites(&self) -> Vec<Sprite> {
-        let mut sprites = Vec::with_capacity(SPRITE_COUNT);
-        for i in 0..SPRITE_COUNT {
+    /// All 40 hardware sprites in OAM order, whether or not they are on screen
+    pub fn oam_entries(&self) -> [OamEntry; SPRITE_COUNT] {
+        std::array::from_fn(|i| {
             let start = i * SPRITE_BYTES;
-            sprites.push(Sprite::new(&self.oam[start..start + SPRITE_BYTES]));
-        }
-        sprites
+            OamEntry::new(&self.oam[start..start + SPRITE_BYTES])
+        })
     }
 }
 
@@ -388,16 +613,23 @@ pub const LCD_WIDTH: usize = 160;
 pub const LCD_HEIGHT: usize = 144;
 pub const TILE_BYTES: usize = 16;
 const TILE_PIXELS: usize = 8;
+pub const TILE_COUNT: usize = 384; // tiles in blocks 0-2
+const OAM_ROWS: usize = 20;
+const OAM_ROW_BYTES: usize = 8;
 const TILE_MAP_SIZE: usize = 32;
 const TILE_MAP_BYTES: usize = TILE_MAP_SIZE * TILE_MAP_SIZE;
 const TILE_MAP_PIXELS: usize = TILE_MAP_SIZE * TILE_PIXELS; // 256 pixels
 const SPRITE_BYTES: usize = 4;
-const SPRITE_COUNT: usize = 40;
+pub const SPRITE_COUNT: usize = 40;
 const MAX_SPRITES_PER_SCANLINE: usize = 10;
 
 const OAM_TICKS: usize = 80;
 const INITIAL_FIFO_LOAD_TICKS: usize = 12;
 const SCANLINE_TICKS: usize = 456;
+pub const FRAME_TICKS: usize = SCANLINE_TICKS * 154;
+const MIN_DRAWING_TICKS: usize = INITIAL_FIFO_LOAD_TICKS + LCD_WIDTH;
+const WINDOW_FETCH_TICKS: usize = 6;
+const SPRITE_FETCH_TICKS: usize = 6;
 
 impl Activation for PPU {
     fn is_activation_pending(&self) -> bool {
@@ -450,33 +682,44 @@ impl<'a> Tile<'a> {
     }
 }
 
+/// A decoded OAM entry https://gbdev.io/pandocs/OAM.html
+/// OAM stores positions offset by (8, 16) so that objects can be partially or fully off screen,
+/// `x` & `y` have that offset removed and are the screen position of the top left pixel.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Decode, Encode)]
-struct Sprite {
-    y: isize,
-    x: isize,
-    tile_index: u8,
-    bg_priority: bool, // bit 7 - 0 = No, 1 = BG and Window color indices 1–3 are drawn over this OBJ
-    flip_y: bool, // bit 6 - 0 = Normal, 1 = Entire OBJ is vertically mirrored
-    flip_x: bool, // bit 5 - 0 = Normal, 1 = Entire OBJ is horizontally mirrored
-    alt_palette: bool, // bit 4 (DMG only) - 0 = Use OBJ palette 0, 1 = Use OBJ palette 1
+pub struct OamEntry {
+    pub y: isize, // byte 0 - 16
+    pub x: isize, // byte 1 - 8
+    pub tile: u8,
+    pub priority: bool, // bit 7 - 0 = No, 1 = BG and Window color indices 1–3 are drawn over this OBJ
+    pub y_flip: bool, // bit 6 - 0 = Normal, 1 = Entire OBJ is vertically mirrored
+    pub x_flip: bool, // bit 5 - 0 = Normal, 1 = Entire OBJ is horizontally mirrored
+    pub palette: bool, // bit 4 (DMG only) - 0 = Use OBJ palette 0, 1 = Use OBJ palette 1
 }
 
-impl Sprite {
+impl OamEntry {
     pub fn new(data: &[u8]) -> Self {
-        debug_assert!(data.len() == SPRITE_BYTES, "Sprite data must be exactly 4 bytes");
+        debug_assert!(data.len() == SPRITE_BYTES, "OAM entry data must be exactly 4 bytes");
         Self {
             y: data[0] as isize - 16, // Y coordinate is offset by 16 pixels
             x: data[1] as isize - 8, // X coordinate is offset by 8 pixels
-            tile_index: data[2],
-            bg_priority: (data[3] & 0x80) != 0,
-            flip_y: (data[3] & 0x40) != 0,
-            flip_x: (data[3] & 0x20) != 0,
-            alt_palette: (data[3] & 0x10) != 0,
+            tile: data[2],
+            priority: (data[3] & 0x80) != 0,
+            y_flip: (data[3] & 0x40) != 0,
+            x_flip: (data[3] & 0x20) != 0,
+            palette: (data[3] & 0x10) != 0,
         }
     }
 
+    /// The DMG attribute bits of byte 3, bits 0-3 are CGB only and not decoded
+    pub fn flags(&self) -> u8 {
+        (self.priority as u8) << 7
+            | (self.y_flip as u8) << 6
+            | (self.x_flip as u8) << 5
+            | (self.palette as u8) << 4
+    }
+
     pub fn palette<'a>(&self, register: &'a LcdPalette) -> &'a DMGPaletteRegister {
-        if self.alt_palette {
+        if self.palette {
             register.object1()
         } else {
             register.object0()
@@ -490,6 +733,385 @@ mod tests {
     use DMGColor::*;
     use super::*;
 
+    /// lcd & objects enabled, background disabled, 8x8 objects & identity palettes
+    fn sprite_ppu() -> PPU {
+        let mut ppu = PPU::default();
+        ppu.lcd_control.set(0x82);
+        ppu.palette.background_mut().set_from_byte(0xE4);
+        ppu.palette.object0_mut().set_from_byte(0xE4);
+        ppu.palette.object1_mut().set_from_byte(0xE4);
+        ppu
+    }
+
+    fn write_tile(ppu: &mut PPU, index: usize, rows: [(u8, u8); TILE_PIXELS]) {
+        for (y, (low, high)) in rows.into_iter().enumerate() {
+            ppu.vram[index * TILE_BYTES + y * 2] = low;
+            ppu.vram[index * TILE_BYTES + y * 2 + 1] = high;
+        }
+    }
+
+    /// write a sprite to OAM, x & y are screen coordinates
+    fn write_sprite(ppu: &mut PPU, index: usize, x: u8, y: u8, tile_index: u8, attributes: u8) {
+        let start = index * SPRITE_BYTES;
+        ppu.oam[start..start + SPRITE_BYTES].copy_from_slice(&[y + 16, x + 8, tile_index, attributes]);
+    }
+
+    fn render_frames(ppu: &mut PPU, frames: usize) {
+        for _ in 0..frames * FRAME_TICKS / 4 {
+            ppu.update(MachineCycles::ONE);
+        }
+    }
+
+    fn lcd_pixel(ppu: &PPU, x: usize, y: usize) -> DMGColor {
+        ppu.lcd[y * LCD_WIDTH + x]
+    }
+
+    /// t-cycles spent in each mode on a single scanline, indexed by mode
+    fn scanline_mode_ticks(ppu: &mut PPU, ly: u8) -> [usize; 4] {
+        let mut ticks = [0; 4];
+        while ppu.lcd_status.ly() != ly || ppu.lcd_status.mode() != LcdMode::OAM {
+            ppu.update(MachineCycles::ONE);
+        }
+        while ppu.lcd_status.ly() == ly {
+            ticks[ppu.lcd_status.mode() as usize] += MachineCycles::ONE.t_cycles();
+            ppu.update(MachineCycles::ONE);
+        }
+        ticks
+    }
+
+    #[test]
+    fn sprites_extend_drawing() {
+        let mut ppu = sprite_ppu();
+        for index in 0..MAX_SPRITES_PER_SCANLINE {
+            write_sprite(&mut ppu, index, 12 * index as u8 + 3, 10, 0, 0);
+        }
+
+        let sprite_free = scanline_mode_ticks(&mut ppu, 2);
+        let with_sprites = scanline_mode_ticks(&mut ppu, 12);
+
+        assert_eq!(sprite_free[LcdMode::Drawing as usize], MIN_DRAWING_TICKS);
+        assert!(with_sprites[LcdMode::Drawing as usize] >= sprite_free[LcdMode::Drawing as usize] + MAX_SPRITES_PER_SCANLINE * SPRITE_FETCH_TICKS);
+        assert!(with_sprites[LcdMode::HBlank as usize] < sprite_free[LcdMode::HBlank as usize]);
+        assert_eq!(with_sprites[LcdMode::OAM as usize], OAM_TICKS);
+        assert_eq!(sprite_free.iter().sum::<usize>(), SCANLINE_TICKS);
+        assert_eq!(with_sprites.iter().sum::<usize>(), SCANLINE_TICKS);
+    }
+
+    #[test]
+    fn sprite_limit_per_scanline() {
+        let mut ppu = sprite_ppu();
+        write_tile(&mut ppu, 1, [(0xFF, 0xFF); TILE_PIXELS]);
+        for index in 0..=MAX_SPRITES_PER_SCANLINE {
+            write_sprite(&mut ppu, index, 12 * index as u8, 8, 1, 0);
+        }
+        render_frames(&mut ppu, 2);
+
+        for index in 0..MAX_SPRITES_PER_SCANLINE {
+            assert_eq!(lcd_pixel(&ppu, 12 * index, 8), Black, "sprite {}", index);
+        }
+        // the 11th sprite in OAM order is dropped, even though it is on screen
+        assert_eq!(lcd_pixel(&ppu, 12 * MAX_SPRITES_PER_SCANLINE, 8), White);
+    }
+
+    #[test]
+    fn sprite_priority() {
+        let mut ppu = sprite_ppu();
+        write_tile(&mut ppu, 1, [(0xFF, 0x00); TILE_PIXELS]); // color 1
+        write_tile(&mut ppu, 2, [(0xFF, 0xFF); TILE_PIXELS]); // color 3
+        write_sprite(&mut ppu, 0, 12, 8, 1, 0x00);
+        write_sprite(&mut ppu, 1, 8, 8, 2, 0x00); // smaller x wins despite the higher OAM index
+        write_sprite(&mut ppu, 2, 40, 8, 1, 0x00);
+        write_sprite(&mut ppu, 3, 40, 8, 2, 0x00); // same x, the lower OAM index wins
+        render_frames(&mut ppu, 2);
+
+        assert_eq!(lcd_pixel(&ppu, 12, 8), Black);
+        assert_eq!(lcd_pixel(&ppu, 16, 8), LightGray);
+        assert_eq!(lcd_pixel(&ppu, 40, 8), LightGray);
+    }
+
+    fn run_until_ly(ppu: &mut PPU, ly: u8) {
+        while ppu.lcd_status.ly() != ly || ppu.lcd_status.mode() != LcdMode::OAM {
+            ppu.update(MachineCycles::ONE);
+        }
+    }
+
+    #[test]
+    fn window_line_counter() {
+        let mut ppu = sprite_ppu();
+        write_tile(&mut ppu, 1, [(0xFF, 0xFF); TILE_PIXELS]); // color 3
+        write_tile(&mut ppu, 2, [(0xFF, 0x00); TILE_PIXELS]); // color 1
+        // window tile map at 0x9C00, first row of tiles is black & the second is light gray
+        ppu.vram[0x1C00..0x1C00 + TILE_MAP_SIZE].fill(1);
+        ppu.vram[0x1C00 + TILE_MAP_SIZE..0x1C00 + 2 * TILE_MAP_SIZE].fill(2);
+        ppu.window_position = Point8 { x: 7, y: 10 };
+
+        // WY = LY is reached on scanline 10 but the window is only enabled from scanline 20
+        ppu.lcd_control.set(0xD1);
+        run_until_ly(&mut ppu, 20);
+        ppu.lcd_control.set(0xF1);
+        run_until_ly(&mut ppu, 24);
+        ppu.lcd_control.set(0xD1);
+        run_until_ly(&mut ppu, 40);
+        ppu.lcd_control.set(0xF1);
+        run_until_ly(&mut ppu, LCD_HEIGHT as u8 - 1);
+
+        // window lines 0-3
+        assert_eq!(lcd_pixel(&ppu, 0, 20), Black);
+        assert_eq!(lcd_pixel(&ppu, 0, 23), Black);
+        assert_eq!(lcd_pixel(&ppu, 0, 30), White); // background
+        // window lines 4-7 continue from the counter rather than LY - WY, then the second tile row
+        assert_eq!(lcd_pixel(&ppu, 0, 40), Black);
+        assert_eq!(lcd_pixel(&ppu, 0, 43), Black);
+        assert_eq!(lcd_pixel(&ppu, 0, 44), LightGray);
+        assert_eq!(lcd_pixel(&ppu, 0, 51), LightGray);
+
+        // the counter is reset for the next frame
+        run_until_ly(&mut ppu, 11);
+        assert_eq!(lcd_pixel(&ppu, 0, 10), Black);
+    }
+
+    #[test]
+    fn scroll_extends_drawing() {
+        let mut ppu = sprite_ppu();
+        ppu.scroll.x = 3;
+        let ticks = scanline_mode_ticks(&mut ppu, 2);
+        // mode boundaries are only observed on machine cycles
+        assert!(ticks[LcdMode::Drawing as usize] >= MIN_DRAWING_TICKS + 3);
+        assert_eq!(ticks.iter().sum::<usize>(), SCANLINE_TICKS);
+    }
+
+    #[test]
+    fn sprite_flip_x() {
+        let mut ppu = sprite_ppu();
+        // leftmost pixel is color 3, rightmost pixel is color 1
+        write_tile(&mut ppu, 1, [(0x81, 0x80); TILE_PIXELS]);
+        write_sprite(&mut ppu, 0, 8, 8, 1, 0x00);
+        write_sprite(&mut ppu, 1, 24, 8, 1, 0x20); // X flip
+        render_frames(&mut ppu, 2);
+
+        assert_eq!(lcd_pixel(&ppu, 8, 8), Black);
+        assert_eq!(lcd_pixel(&ppu, 15, 8), LightGray);
+
+        assert_eq!(lcd_pixel(&ppu, 24, 8), LightGray);
+        assert_eq!(lcd_pixel(&ppu, 31, 8), Black);
+    }
+
+    #[test]
+    fn sprite_flip_y() {
+        let mut ppu = sprite_ppu();
+        // top row is color 3, all other rows are color 1
+        let mut rows = [(0xFF, 0x00); TILE_PIXELS];
+        rows[0] = (0xFF, 0xFF);
+        write_tile(&mut ppu, 1, rows);
+        write_sprite(&mut ppu, 0, 8, 8, 1, 0x00);
+        write_sprite(&mut ppu, 1, 24, 8, 1, 0x40); // Y flip
+        render_frames(&mut ppu, 2);
+
+        assert_eq!(lcd_pixel(&ppu, 8, 8), Black);
+        assert_eq!(lcd_pixel(&ppu, 8, 15), LightGray);
+
+        assert_eq!(lcd_pixel(&ppu, 24, 8), LightGray);
+        assert_eq!(lcd_pixel(&ppu, 24, 15), Black);
+    }
+
+    #[test]
+    fn sprite_flip_y_double_height() {
+        let mut ppu = sprite_ppu();
+        ppu.lcd_control.set(0x86); // 8x16 objects
+        write_tile(&mut ppu, 2, [(0xFF, 0xFF); TILE_PIXELS]); // top tile is color 3
+        write_tile(&mut ppu, 3, [(0xFF, 0x00); TILE_PIXELS]); // bottom tile is color 1
+        // bit 0 of the tile index is ignored in 8x16 mode
+        write_sprite(&mut ppu, 0, 8, 8, 3, 0x00);
+        write_sprite(&mut ppu, 1, 24, 8, 3, 0x40); // Y flip
+        render_frames(&mut ppu, 2);
+
+        assert_eq!(lcd_pixel(&ppu, 8, 8), Black);
+        assert_eq!(lcd_pixel(&ppu, 8, 15), Black);
+        assert_eq!(lcd_pixel(&ppu, 8, 16), LightGray);
+        assert_eq!(lcd_pixel(&ppu, 8, 23), LightGray);
+
+        // Y flip also swaps the top & bottom tiles
+        assert_eq!(lcd_pixel(&ppu, 24, 8), LightGray);
+        assert_eq!(lcd_pixel(&ppu, 24, 15), LightGray);
+        assert_eq!(lcd_pixel(&ppu, 24, 16), Black);
+        assert_eq!(lcd_pixel(&ppu, 24, 23), Black);
+    }
+
+    #[test]
+    fn sprite_object_palettes() {
+        let mut ppu = sprite_ppu();
+        ppu.palette.object1_mut().set_from_byte(0x1B); // reversed palette, color 0 would be black
+        // left half is color 1, right half is transparent
+        write_tile(&mut ppu, 1, [(0xF0, 0x00); TILE_PIXELS]);
+        write_sprite(&mut ppu, 0, 8, 8, 1, 0x00); // OBP0
+        write_sprite(&mut ppu, 1, 12, 8, 1, 0x10); // OBP1, overlaps the transparent half of sprite 0
+        render_frames(&mut ppu, 2);
+
+        // sprite 0 is drawn with OBP0
+        assert_eq!(lcd_pixel(&ppu, 8, 8), LightGray);
+        assert_eq!(lcd_pixel(&ppu, 11, 8), LightGray);
+
+        // sprite 1 shows through the transparent pixels of sprite 0 & is drawn with OBP1
+        assert_eq!(lcd_pixel(&ppu, 12, 8), DarkGray);
+        assert_eq!(lcd_pixel(&ppu, 15, 8), DarkGray);
+
+        // color 0 of sprite 1 is transparent rather than OBP1 shade 0
+        assert_eq!(lcd_pixel(&ppu, 16, 8), White);
+        assert_eq!(lcd_pixel(&ppu, 19, 8), White);
+    }
+
+    #[test]
+    fn color_indices_to_rgb() {
+        let mut ppu = sprite_ppu();
+        // one pixel of each color
+        write_tile(&mut ppu, 0, [(0b0101_0000, 0b0011_0000), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0)]);
+        write_sprite(&mut ppu, 0, 0, 0, 0, 0);
+        render_frames(&mut ppu, 2);
+
+        let indices = ppu.color_indices();
+        assert!(indices.iter().all(|&index| index <= 3));
+        assert_eq!(&indices[..4], &[0, 1, 2, 3]);
+
+        let image = ppu.to_rgb8(&DMGPalette::GREEN);
+        for x in 0..4 {
+            assert_eq!(*image.get_pixel(x, 0), DMGPalette::GREEN.0[x as usize]);
+        }
+        assert_eq!(*image.get_pixel(0, 1), DMGPalette::GREEN.0[0]);
+        assert_eq!(ppu.screenshot(), ppu.to_rgb8(&DMGPalette::GRAYSCALE));
+    }
+
+    #[test]
+    fn tile_data() {
+        let mut ppu = PPU::default();
+        write_tile(&mut ppu, 0, [(0b0101_0000, 0b0011_0000), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0xFF, 0xFF)]);
+        write_tile(&mut ppu, TILE_COUNT - 1, [(0x80, 0x00); TILE_PIXELS]);
+
+        let tiles = ppu.tile_data();
+        assert_eq!(tiles.len(), TILE_COUNT);
+        assert_eq!(&tiles[0][..8], &[0, 1, 2, 3, 0, 0, 0, 0]);
+        assert_eq!(&tiles[0][56..], &[3; 8]);
+        assert!(tiles[TILE_COUNT - 1].chunks(8).all(|row| row == [1, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(tiles[1].iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn background_map() {
+        let mut ppu = PPU::default();
+        ppu.vram[0x1800 + 33] = 0x12; // 0x9800 row 1, column 1
+        ppu.vram[0x1C00 + 31 * 32] = 0x34; // 0x9C00 row 31, column 0
+
+        let lower = ppu.background_map(TileMapMode::Lower);
+        assert_eq!(lower[1][1], 0x12);
+        assert_eq!(lower.iter().flatten().filter(|&&index| index != 0).count(), 1);
+        let upper = ppu.background_map(TileMapMode::Upper);
+        assert_eq!(upper[31][0], 0x34);
+        assert_eq!(upper[1][1], 0x00);
+    }
+
+    #[test]
+    fn frame_hash() {
+        let mut ppu = sprite_ppu();
+        let blank = ppu.frame_hash();
+        assert_eq!(blank, PPU::default().frame_hash());
+
+        write_tile(&mut ppu, 0, [(0b0101_0000, 0b0011_0000), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0)]);
+        write_sprite(&mut ppu, 0, 0, 0, 0, 0);
+        render_frames(&mut ppu, 2);
+        let hash = ppu.frame_hash();
+        assert_ne!(hash, blank);
+
+        render_frames(&mut ppu, 1);
+        assert_eq!(ppu.frame_hash(), hash);
+        // the color map does not change the hash
+        ppu.palette_mut().set_color_map(DMGPalette::GREEN);
+        assert_eq!(ppu.frame_hash(), hash);
+
+        let framebuffer = ppu.framebuffer();
+        assert_eq!(framebuffer.len(), LCD_WIDTH * LCD_HEIGHT);
+        assert_eq!(framebuffer[0], 0x9BBC0F);
+        assert_eq!(framebuffer[3], 0x0F380F);
+    }
+
+    #[test]
+    fn color_map() {
+        let mut ppu = sprite_ppu();
+        write_tile(&mut ppu, 0, [(0b0101_0000, 0b0011_0000), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0)]);
+        write_sprite(&mut ppu, 0, 0, 0, 0, 0);
+        render_frames(&mut ppu, 2);
+        let lcd = *ppu.lcd();
+
+        let custom = DMGPalette([Rgb([0xE0, 0xF8, 0xD0]), Rgb([0x88, 0xC0, 0x70]), Rgb([0x34, 0x68, 0x56]), Rgb([0x08, 0x18, 0x20])]);
+        ppu.palette_mut().set_color_map(custom);
+        assert_eq!(ppu.screenshot(), ppu.to_rgb8(&custom));
+        assert_eq!(*ppu.screenshot().get_pixel(3, 0), custom.0[3]);
+
+        // emulation is unaffected
+        render_frames(&mut ppu, 1);
+        assert_eq!(*ppu.lcd(), lcd);
+        assert_eq!(ppu.palette().object0().to_byte(), 0xE4);
+    }
+
+    #[test]
+    fn lcd_disable() {
+        let mut ppu = sprite_ppu();
+        write_tile(&mut ppu, 0, [(0xFF, 0xFF); TILE_PIXELS]);
+        write_sprite(&mut ppu, 0, 0, 0, 0, 0);
+        render_frames(&mut ppu, 1);
+        assert_eq!(lcd_pixel(&ppu, 0, 0), Black);
+
+        ppu.set_lcd_control(0x02); // lcd off
+        assert_eq!(ppu.ly(), 0);
+        assert_eq!(ppu.lcd_status.mode(), LcdMode::HBlank);
+        render_frames(&mut ppu, 1);
+        assert_eq!(ppu.ly(), 0);
+        assert!(ppu.lcd.iter().all(|&color| color == White));
+        assert!(!ppu.is_activation_pending());
+
+        ppu.set_lcd_control(0x82); // lcd on
+        assert_eq!(ppu.ly(), 0);
+        assert_eq!(ppu.lcd_status.mode(), LcdMode::OAM);
+        ppu.update(MachineCycles::from_t(OAM_TICKS));
+        assert_eq!(ppu.lcd_status.mode(), LcdMode::Drawing);
+        render_frames(&mut ppu, 1);
+        assert_eq!(lcd_pixel(&ppu, 0, 0), Black);
+    }
+
+    #[test]
+    fn frame_count() {
+        let mut ppu = sprite_ppu();
+        while !ppu.frame_ready() {
+            ppu.update(MachineCycles::ONE);
+        }
+        assert_eq!(ppu.frame_count(), 1);
+        assert_eq!(ppu.lcd_status.mode(), LcdMode::VBlank);
+
+        let mut pulses = 0;
+        for _ in 0..FRAME_TICKS / 4 {
+            ppu.update(MachineCycles::ONE);
+            if ppu.frame_ready() {
+                pulses += 1;
+            }
+        }
+        assert_eq!(pulses, 1);
+        assert_eq!(ppu.frame_count(), 2);
+        assert!(ppu.frame_ready()); // exactly one frame later
+
+        ppu.update(MachineCycles::ONE);
+        assert!(!ppu.frame_ready());
+    }
+
+    #[test]
+    fn background_disable_is_white() {
+        let mut ppu = sprite_ppu();
+        ppu.palette.background_mut().set_from_byte(0xFF); // every color is black
+        render_frames(&mut ppu, 1);
+        assert_eq!(lcd_pixel(&ppu, 80, 72), White);
+
+        ppu.set_lcd_control(0x83); // background on
+        render_frames(&mut ppu, 1);
+        assert_eq!(lcd_pixel(&ppu, 80, 72), Black);
+    }
+
     #[test]
     fn parse_tile() {
         let tile = Tile::new(&[
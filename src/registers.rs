@@ -50,6 +50,22 @@ pub struct RegisterSet {
 }
 
 impl RegisterSet {
+    /// Power on state before the boot ROM has run, execution starts at 0x0000
+    pub fn power_on() -> Self {
+        Self {
+            a: 0x00,
+            flags: FlagsRegister::new(),
+            b: 0x00,
+            c: 0x00,
+            d: 0x00,
+            e: 0x00,
+            h: 0x00,
+            l: 0x00,
+            sp: 0x0000,
+            pc: 0x0000,
+        }
+    }
+
     pub fn dmg() -> Self {
         Self {
             a: 0x01,
@@ -70,6 +86,26 @@ impl RegisterSet {
         }
     }
 
+    pub fn cgb() -> Self {
+        Self {
+            a: 0x11,
+            flags: FlagsRegister {
+                z: true,
+                n: false,
+                h: false,
+                c: false,
+            },
+            b: 0x00,
+            c: 0x00,
+            d: 0xFF,
+            e: 0x56,
+            h: 0x00,
+            l: 0x0D,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        }
+    }
+
     pub fn hl(&self) -> u16 {
         u16::from_be_bytes([self.h, self.l])
     }
@@ -125,6 +161,61 @@ impl RegisterSet {
     }
 }
 
+/// A plain copy of the CPU registers, with the flags packed into F as they are on hardware
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RegisterSnapshot {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+impl RegisterSnapshot {
+    pub fn flags(&self) -> FlagsRegister {
+        FlagsRegister::from_byte(self.f)
+    }
+}
+
+impl From<RegisterSet> for RegisterSnapshot {
+    fn from(registers: RegisterSet) -> Self {
+        Self {
+            a: registers.a,
+            f: registers.flags.to_byte(),
+            b: registers.b,
+            c: registers.c,
+            d: registers.d,
+            e: registers.e,
+            h: registers.h,
+            l: registers.l,
+            sp: registers.sp,
+            pc: registers.pc,
+        }
+    }
+}
+
+impl From<RegisterSnapshot> for RegisterSet {
+    fn from(snapshot: RegisterSnapshot) -> Self {
+        Self {
+            a: snapshot.a,
+            flags: snapshot.flags(),
+            b: snapshot.b,
+            c: snapshot.c,
+            d: snapshot.d,
+            e: snapshot.e,
+            h: snapshot.h,
+            l: snapshot.l,
+            sp: snapshot.sp,
+            pc: snapshot.pc,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,49 @@
+use crate::lcd_palette::{DMGColor, DMGPalette};
+use crate::ppu::{LCD_HEIGHT, LCD_WIDTH};
+
+pub const RGBA_BYTES: usize = 4;
+pub const FRAME_BYTES: usize = LCD_WIDTH * LCD_HEIGHT * RGBA_BYTES;
+
+/// Converts the LCD into tightly packed RGBA8888 rows for front-ends that do not use SDL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SoftwareRenderer {
+    pub palette: DMGPalette,
+}
+
+impl SoftwareRenderer {
+    pub fn new(palette: DMGPalette) -> Self {
+        Self { palette }
+    }
+
+    pub fn render(&self, lcd: &[DMGColor; LCD_WIDTH * LCD_HEIGHT]) -> Vec<u8> {
+        let mut frame = vec![0; FRAME_BYTES];
+        self.render_into(lcd, &mut frame);
+        frame
+    }
+
+    /// Renders into an existing buffer of `FRAME_BYTES`, to avoid allocating every frame
+    pub fn render_into(&self, lcd: &[DMGColor; LCD_WIDTH * LCD_HEIGHT], frame: &mut [u8]) {
+        for (pixel, &color) in frame.chunks_exact_mut(RGBA_BYTES).zip(lcd.iter()) {
+            let [r, g, b] = self.palette.rgb(color).0;
+            pixel.copy_from_slice(&[r, g, b, 0xFF]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let mut lcd = [DMGColor::White; LCD_WIDTH * LCD_HEIGHT];
+        lcd[1] = DMGColor::Black;
+        lcd[LCD_WIDTH * LCD_HEIGHT - 1] = DMGColor::DarkGray;
+
+        let frame = SoftwareRenderer::new(DMGPalette::GREEN).render(&lcd);
+        assert_eq!(frame.len(), 92160);
+        assert_eq!(&frame[..4], &[0x9B, 0xBC, 0x0F, 0xFF]);
+        assert_eq!(&frame[4..8], &[0x0F, 0x38, 0x0F, 0xFF]);
+        assert_eq!(&frame[FRAME_BYTES - 4..], &[0x30, 0x62, 0x30, 0xFF]);
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+
+/// Fixed capacity ring of compressed save states captured every `interval` frames, the oldest is dropped when full
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RewindBuffer {
+    states: VecDeque<Vec<u8>>,
+    capacity: usize,
+    interval: usize, // frames between captures
+    frames_since_capture: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, interval: usize) -> Self {
+        Self {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+            interval: interval.max(1),
+            frames_since_capture: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn interval(&self) -> usize {
+        self.interval
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Total size of the retained save states in bytes
+    pub fn size_bytes(&self) -> usize {
+        self.states.iter().map(|state| state.len()).sum()
+    }
+
+    /// Count a completed frame, returns true if a state should be captured
+    pub fn frame_completed(&mut self) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        self.frames_since_capture += 1;
+        if self.frames_since_capture >= self.interval {
+            self.frames_since_capture = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if !self.is_enabled() {
+            return;
+        }
+        if self.states.len() >= self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(state);
+    }
+
+    /// The most recent state, the next capture is a full interval later
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.frames_since_capture = 0;
+        self.states.pop_back()
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+        self.frames_since_capture = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_interval() {
+        let mut rewind = RewindBuffer::new(4, 3);
+        let captures: Vec<_> = (0..7).map(|_| rewind.frame_completed()).collect();
+        assert_eq!(captures, vec![false, false, true, false, false, true, false]);
+
+        let mut disabled = RewindBuffer::default();
+        assert!(!disabled.frame_completed());
+        disabled.push(vec![1]);
+        assert!(disabled.is_empty());
+    }
+
+    #[test]
+    fn ring() {
+        let mut rewind = RewindBuffer::new(3, 1);
+        for state in 0..5u8 {
+            rewind.push(vec![state; 2]);
+        }
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.size_bytes(), 6);
+        assert_eq!(rewind.pop(), Some(vec![4, 4]));
+        assert_eq!(rewind.pop(), Some(vec![3, 3]));
+        assert_eq!(rewind.pop(), Some(vec![2, 2]));
+        assert_eq!(rewind.pop(), None);
+    }
+}
@@ -15,6 +15,24 @@ pub mod blargg_cpu {
     pub const OP_A_HL_11: &[u8] = include_bytes!("cpu_instrs/11-op a,(hl).gb");
 
     pub const INSTRUCTION_TIMING: &[u8] = include_bytes!("instr_timing.gb");
+
+    /// Every individual test, all of which report their result over serial. The combined `ROM` is excluded.
+    pub fn all() -> &'static [(&'static str, &'static [u8])] {
+        &[
+            ("01-special", SPECIAL_01),
+            ("02-interrupts", INTERRUPTS_02),
+            ("03-op sp,hl", OP_SP_HL_03),
+            ("04-op r,imm", OP_R_IMM_04),
+            ("05-op rp", OP_RP_05),
+            ("06-ld r,r", LD_R_R_06),
+            ("07-jr,jp,call,ret,rst", JR_JP_CALL_RET_RST_07),
+            ("08-misc instrs", MISC_INSTRUCTIONS_08),
+            ("09-op r,r", OP_R_R_09),
+            ("10-bit ops", BIT_OPS_10),
+            ("11-op a,(hl)", OP_A_HL_11),
+            ("instr_timing", INSTRUCTION_TIMING),
+        ]
+    }
 }
 
 pub mod blargg_dmg_sound {
@@ -37,18 +55,40 @@ pub mod blargg_dmg_sound {
     pub const LENGTH_COUNTER_DURING_POWER: &[u8] = include_bytes!("dmg_sound/08-len ctr during power.gb");
     pub const EXPECTED_LENGTH_COUNTER_DURING_POWER: &[u8] = include_bytes!("dmg_sound/08-len ctr during power.png");
     pub const WAVE_READ_WHILE_ON: &[u8] = include_bytes!("dmg_sound/09-wave read while on.gb");
-    pub const EXPECTED_WAVE_READ_WHILE_ON: &[u8] = EXPECTED_REGISTERS; // TODO
+    pub const EXPECTED_WAVE_READ_WHILE_ON: &[u8] = include_bytes!("dmg_sound/09-wave read while on.png");
     pub const WAVE_TRIGGER_WHILE_ON: &[u8] = include_bytes!("dmg_sound/10-wave trigger while on.gb");
-    pub const EXPECTED_WAVE_TRIGGER_WHILE_ON: &[u8] = EXPECTED_REGISTERS; // TODO
+    pub const EXPECTED_WAVE_TRIGGER_WHILE_ON: &[u8] = include_bytes!("dmg_sound/10-wave trigger while on.png");
     pub const REGISTERS_AFTER_POWER: &[u8] = include_bytes!("dmg_sound/11-regs after power.gb");
     pub const EXPECTED_REGISTERS_AFTER_POWER: &[u8] = include_bytes!("dmg_sound/11-regs after power.png");
     pub const WAVE_WRITE_WHILE_ON: &[u8] = include_bytes!("dmg_sound/12-wave write while on.gb");
-    pub const EXPECTED_WAVE_WRITE_WHILE_ON: &[u8] = EXPECTED_REGISTERS; // TODO
+    pub const EXPECTED_WAVE_WRITE_WHILE_ON: &[u8] = include_bytes!("dmg_sound/12-wave write while on.png");
+
+    /// Every individual test, the combined `ROM` is excluded
+    pub fn all() -> &'static [(&'static str, &'static [u8])] {
+        &[
+            ("01-registers", REGISTERS),
+            ("02-len ctr", LENGTH_COUNTER),
+            ("03-trigger", TRIGGER),
+            ("04-sweep", SWEEP),
+            ("05-sweep details", SWEEP_DETAILS),
+            ("06-overflow on trigger", OVERFLOW_ON_TRIGGER),
+            ("07-len sweep period sync", LENGTH_SWEEP_PERIOD_SYNC),
+            ("08-len ctr during power", LENGTH_COUNTER_DURING_POWER),
+            ("09-wave read while on", WAVE_READ_WHILE_ON),
+            ("10-wave trigger while on", WAVE_TRIGGER_WHILE_ON),
+            ("11-regs after power", REGISTERS_AFTER_POWER),
+            ("12-wave write while on", WAVE_WRITE_WHILE_ON),
+        ]
+    }
 }
 
 pub mod acid {
     pub const ROM: &[u8] = include_bytes!("dmg-acid2/dmg-acid2.gb");
     pub const EXPECTED_DMG: &[u8] = include_bytes!("dmg-acid2/reference-dmg.png");
+
+    pub fn all() -> &'static [(&'static str, &'static [u8])] {
+        &[("dmg-acid2", ROM)]
+    }
 }
 
 pub mod button_test {
@@ -82,4 +122,48 @@ pub mod roms {
             .expect("Failed to decode expected image")
             .to_rgb8()
     }
-}
\ No newline at end of file
+
+    /// Exact comparison of a reference PNG with packed `0x00RRGGBB` pixels, e.g. from `PPU::framebuffer`
+    pub fn png_matches(expected: &[u8], framebuffer: &[u32]) -> bool {
+        let expected = parse_png(expected);
+        expected.pixels().len() == framebuffer.len()
+            && expected.pixels().zip(framebuffer).all(|(pixel, &actual)| {
+                let [r, g, b] = pixel.0;
+                u32::from_be_bytes([0, r, g, b]) == actual
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+
+    #[test]
+    fn blargg_cpu_all() {
+        let all = blargg_cpu::all();
+        assert_eq!(all.len(), 12);
+        assert_eq!(all.iter().map(|(name, _)| name).collect::<HashSet<_>>().len(), all.len());
+        assert!(all.iter().all(|(_, rom)| !rom.is_empty()));
+    }
+
+    #[test]
+    fn png_matches() {
+        let image = roms::parse_png(acid::EXPECTED_DMG);
+        let mut framebuffer: Vec<u32> = image.pixels()
+            .map(|pixel| u32::from_be_bytes([0, pixel.0[0], pixel.0[1], pixel.0[2]]))
+            .collect();
+        assert!(roms::png_matches(acid::EXPECTED_DMG, &framebuffer));
+
+        framebuffer[100] ^= 0x010101;
+        assert!(!roms::png_matches(acid::EXPECTED_DMG, &framebuffer));
+        assert!(!roms::png_matches(acid::EXPECTED_DMG, &framebuffer[1..]));
+    }
+
+    #[test]
+    fn blargg_dmg_sound_all() {
+        let all = blargg_dmg_sound::all();
+        assert_eq!(all.len(), 12);
+        assert_eq!(all.iter().map(|(name, _)| name).collect::<HashSet<_>>().len(), all.len());
+    }
+}
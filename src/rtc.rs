@@ -0,0 +1,201 @@
+use bincode::{Decode, Encode};
+use crate::cycles::MachineCycles;
+
+/// Clock registers of an MBC3 cartridge, as stored in battery backed save files
+/// https://gbdev.io/pandocs/MBC3.html#the-clock-counter-registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Decode, Encode)]
+pub struct RtcState {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    /// 9-bit day counter
+    pub days: u16,
+    pub halt: bool,
+    /// Set when the day counter overflows, stays set until cleared by the game
+    pub day_carry: bool,
+}
+
+impl RtcState {
+    const DAY_HIGH_MASK: u8 = 0x01;
+    const HALT: u8 = 0x40;
+    const DAY_CARRY: u8 = 0x80;
+
+    fn tick_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days += 1;
+        if self.days > 0x1FF {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+
+    fn read(&self, register: RtcRegister) -> u8 {
+        match register {
+            RtcRegister::Seconds => self.seconds,
+            RtcRegister::Minutes => self.minutes,
+            RtcRegister::Hours => self.hours,
+            RtcRegister::DayLow => self.days as u8,
+            RtcRegister::DayHigh => {
+                let mut value = (self.days >> 8) as u8 & Self::DAY_HIGH_MASK;
+                if self.halt {
+                    value |= Self::HALT;
+                }
+                if self.day_carry {
+                    value |= Self::DAY_CARRY;
+                }
+                value
+            }
+        }
+    }
+
+    fn write(&mut self, register: RtcRegister, value: u8) {
+        match register {
+            RtcRegister::Seconds => self.seconds = value & 0x3F,
+            RtcRegister::Minutes => self.minutes = value & 0x3F,
+            RtcRegister::Hours => self.hours = value & 0x1F,
+            RtcRegister::DayLow => self.days = (self.days & 0x100) | value as u16,
+            RtcRegister::DayHigh => {
+                self.days = (self.days & 0xFF) | (((value & Self::DAY_HIGH_MASK) as u16) << 8);
+                self.halt = value & Self::HALT != 0;
+                self.day_carry = value & Self::DAY_CARRY != 0;
+            }
+        }
+    }
+}
+
+/// Selected through the RAM bank register with values 0x08-0x0C
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::FromRepr, Decode, Encode)]
+#[repr(u8)]
+pub enum RtcRegister {
+    Seconds = 0x08,
+    Minutes = 0x09,
+    Hours = 0x0A,
+    DayLow = 0x0B,
+    DayHigh = 0x0C,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Decode, Encode)]
+pub struct RealTimeClock {
+    state: RtcState,
+    latched: RtcState,
+    latch_armed: bool, // 0x00 was written to the latch register
+    cycles_since_tick: MachineCycles,
+}
+
+impl RealTimeClock {
+    const CYCLES_PER_SECOND: MachineCycles = MachineCycles::from_hz(1);
+
+    pub fn state(&self) -> RtcState {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: RtcState) {
+        self.state = state;
+        self.cycles_since_tick = MachineCycles::ZERO;
+    }
+
+    /// The clock runs in emulated time so that it stays in step with the game
+    pub fn update(&mut self, cycles: MachineCycles) {
+        if self.state.halt {
+            return;
+        }
+        self.cycles_since_tick += cycles;
+        while self.cycles_since_tick >= Self::CYCLES_PER_SECOND {
+            self.cycles_since_tick -= Self::CYCLES_PER_SECOND;
+            self.state.tick_second();
+        }
+    }
+
+    /// Writing 0x00 then 0x01 copies the running clock into the readable registers
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.state;
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    pub fn read(&self, register: RtcRegister) -> u8 {
+        self.latched.read(register)
+    }
+
+    /// Writes go to the running clock, writing seconds also resets the sub-second counter
+    pub fn write(&mut self, register: RtcRegister, value: u8) {
+        self.state.write(register, value);
+        self.latched.write(register, value);
+        if register == RtcRegister::Seconds {
+            self.cycles_since_tick = MachineCycles::ZERO;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latch() {
+        let mut rtc = RealTimeClock::default();
+        rtc.update(MachineCycles::from_hz(1) * 61);
+        assert_eq!(rtc.read(RtcRegister::Seconds), 0); // not latched yet
+
+        rtc.write_latch(0x01); // not armed
+        assert_eq!(rtc.read(RtcRegister::Seconds), 0);
+
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(RtcRegister::Seconds), 1);
+        assert_eq!(rtc.read(RtcRegister::Minutes), 1);
+
+        rtc.update(MachineCycles::from_hz(1));
+        assert_eq!(rtc.read(RtcRegister::Seconds), 1); // latched value is held
+        assert_eq!(rtc.state().seconds, 2);
+    }
+
+    #[test]
+    fn day_carry() {
+        let mut rtc = RealTimeClock::default();
+        rtc.set_state(RtcState { seconds: 59, minutes: 59, hours: 23, days: 0x1FF, halt: false, day_carry: false });
+        rtc.update(MachineCycles::from_hz(1));
+        assert_eq!(rtc.state(), RtcState { days: 0, day_carry: true, ..RtcState::default() });
+
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(RtcRegister::DayHigh), 0x80);
+
+        // carry stays set as the clock keeps running
+        rtc.update(MachineCycles::from_hz(1) * 86_400);
+        assert_eq!(rtc.state().days, 1);
+        assert!(rtc.state().day_carry);
+    }
+
+    #[test]
+    fn day_high_register() {
+        let mut rtc = RealTimeClock::default();
+        rtc.write(RtcRegister::DayLow, 0x34);
+        rtc.write(RtcRegister::DayHigh, 0xC1); // carry, halt & day bit 8
+        assert_eq!(rtc.state(), RtcState { days: 0x134, halt: true, day_carry: true, ..RtcState::default() });
+        assert_eq!(rtc.read(RtcRegister::DayHigh), 0xC1);
+        assert_eq!(rtc.read(RtcRegister::DayLow), 0x34);
+
+        // halted
+        rtc.update(MachineCycles::from_hz(1) * 10);
+        assert_eq!(rtc.state().seconds, 0);
+
+        rtc.write(RtcRegister::DayLow, 0xFF);
+        assert_eq!(rtc.state().days, 0x1FF); // day bit 8 preserved
+    }
+}
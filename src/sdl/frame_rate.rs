@@ -1,4 +1,5 @@
 use std::time::{Duration, SystemTime};
+use crate::cycles::MachineCycles;
 
 #[derive(Debug, Copy, Clone)]
 pub struct FrameRate {
@@ -23,3 +24,60 @@ impl FrameRate {
         Ok(delta)
     }
 }
+
+const SPEED_SAMPLE_PERIOD: Duration = Duration::from_secs(1);
+
+/// Tracks emulated machine cycles per real second, sampled once per `SPEED_SAMPLE_PERIOD`
+#[derive(Debug, Copy, Clone)]
+pub struct EmulationSpeed {
+    t0: SystemTime,
+    cycles: u64,
+    cycles_per_second: f64,
+}
+
+impl EmulationSpeed {
+    pub fn new(cycles: u64) -> Self {
+        Self { t0: SystemTime::now(), cycles, cycles_per_second: 0.0 }
+    }
+
+    /// Registers the current machine cycle count, returns the latest sampled cycles per second
+    pub fn update(&mut self, cycles: u64) -> Result<f64, String> {
+        let now = SystemTime::now();
+        let elapsed = now.duration_since(self.t0).map_err(|e| e.to_string())?;
+        if elapsed >= SPEED_SAMPLE_PERIOD {
+            // cycles can go backwards when a save state is loaded
+            self.cycles_per_second = cycles_per_second(cycles.saturating_sub(self.cycles), elapsed);
+            self.t0 = now;
+            self.cycles = cycles;
+        }
+        Ok(self.cycles_per_second)
+    }
+}
+
+/// Emulated machine cycles per real second
+pub fn cycles_per_second(cycles: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        0.0
+    } else {
+        cycles as f64 / elapsed.as_secs_f64()
+    }
+}
+
+/// Emulation speed relative to real hardware, 1.0 is full speed
+pub fn relative_speed(cycles_per_second: f64) -> f64 {
+    cycles_per_second / MachineCycles::from_t(MachineCycles::CPU_FREQ).m_cycles() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed() {
+        let cps = cycles_per_second(2_097_152, Duration::from_millis(500));
+        assert_eq!(cps, 4_194_304.0);
+        assert_eq!(relative_speed(cps), 4.0);
+        assert_eq!(relative_speed(1_048_576.0), 1.0);
+        assert_eq!(cycles_per_second(100, Duration::ZERO), 0.0);
+    }
+}
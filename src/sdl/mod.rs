@@ -1,3 +1,4 @@
 mod frame_rate;
 pub mod render;
-mod font;
\ No newline at end of file
+mod font;
+pub mod viewport;
\ No newline at end of file
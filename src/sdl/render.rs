@@ -2,27 +2,28 @@ use std::thread::sleep;
 use std::time::{Duration, Instant};
 use std::collections::VecDeque;
 use itertools::Itertools;
-use rubato::{Resampler, SincInterpolationParameters, SincInterpolationType, WindowFunction, Async, FixedAsync};
-use audioadapter::direct::InterleavedSlice;
 use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use sdl2::pixels::PixelFormatEnum;
 use crate::audio::GB_SAMPLE_RATE;
+use crate::audio::resampler::Resampler;
 use crate::cycles::MachineCycles;
 use crate::game_boy::GameBoy;
 use crate::lcd_control::{TileDataMode, TileMapMode};
 use crate::pokemon::{PokemonApi, PokemonParty};
-use crate::sdl::frame_rate::FrameRate;
+use crate::sdl::frame_rate::{relative_speed, EmulationSpeed, FrameRate};
 use crate::ppu::{LCD_HEIGHT, LCD_WIDTH};
+use crate::render::{SoftwareRenderer, FRAME_BYTES, RGBA_BYTES};
 use crate::sdl::font::FontTextures;
+use crate::sdl::viewport::{RenderConfig, Viewport};
 
-const SCALE_FACTOR: u32 = 4; // Scale the 160x144 LCD to fit the 640x480 window
 const TARGET_FRAME_TIME: Duration = Duration::from_nanos(16666666); // 60fps
 const FPS_WINDOW_SIZE: usize = 600; // 10 seconds at 60fps
+const TURBO_SPEED_MULTIPLIER: f32 = 4.0; // while tab is held
 
-pub fn render() -> Result<(), String> {
+pub fn render(mut config: RenderConfig) -> Result<(), String> {
     let mut gb = GameBoy::dmg(crate::roms::commercial::POKEMON_RED);
     if let Err(e) = gb.restore_sram_from_file("pokemon-red.sav") {
         println!("Could not load save file: {}", e);
@@ -32,8 +33,9 @@ pub fn render() -> Result<(), String> {
     let video_subsystem = sdl_context.video()?;
     let audio_subsystem = sdl_context.audio()?;
 
-    let window = video_subsystem.window("gb", LCD_WIDTH as u32 * SCALE_FACTOR, LCD_HEIGHT as u32 * SCALE_FACTOR)
+    let window = video_subsystem.window("gb", LCD_WIDTH as u32 * config.scale, LCD_HEIGHT as u32 * config.scale)
         .position_centered()
+        .resizable()
         .build()
         .map_err(|e| e.to_string())?;
 
@@ -49,34 +51,17 @@ pub fn render() -> Result<(), String> {
     let audio_spec = audio_queue.spec();
     audio_queue.resume();
 
-    // Create audio resampler from Game Boy native frequency (1048576 Hz) to SDL2 frequency
-    // TODO use a much simpler resampler with lower latency and fewer dependencies
-    //      E.g. GameBoy audio is 1048576hz, to get to 48khz we need to resample by a factor of 1048576/48000 = 8192/375
-    //      So, (ref: https://en.wikipedia.org/wiki/Downsampling_(signal_processing)) we can:
-    //      1. Increase (resample) the sequence by a factor of 375 (i.e. insert 374 zeros between each sample)
-    //      2. Apply a low-pass filter (probably an FFT, not sure what the cut off frequency should be)
-    //      3. Decrease (resample) the sequence by a factor of 8192 (i.e. keep every 8192nd sample, simple decimation)
-    let mut resampler = Async::<f32>::new_sinc(
-        audio_spec.freq as usize as f64 / GB_SAMPLE_RATE as f64,
-        2.0,  // max_resample_ratio_relative
-        SincInterpolationParameters {
-            sinc_len: 256,
-            f_cutoff: 0.95,
-            interpolation: SincInterpolationType::Linear,
-            oversampling_factor: 256,
-            window: WindowFunction::BlackmanHarris2,
-        },
-        1024, // chunk_size, 1024 is a close common factor of the GB sample rate and 44100hz
-        audio_spec.channels as usize,
-        FixedAsync::Input,
-    ).map_err(|e| e.to_string())?;
-    let mut resampled_audio_buffer = vec![0.0f32; resampler.input_frames_max() * 2];
+    // Resample from the Game Boy native frequency (1048576 Hz) to the SDL2 frequency
+    let mut resampler = Resampler::new(GB_SAMPLE_RATE, audio_spec.freq as usize);
+    let mut resampled_audio_buffer = Vec::new();
 
     // Create texture creator for LCD rendering
     let texture_creator = canvas.texture_creator();
     let mut lcd_texture = texture_creator.create_texture_streaming(
-        PixelFormatEnum::RGB24, LCD_WIDTH as u32, LCD_HEIGHT as u32
+        PixelFormatEnum::RGBA32, LCD_WIDTH as u32, LCD_HEIGHT as u32
     ).map_err(|e| e.to_string())?;
+    let mut renderer = SoftwareRenderer::default();
+    let mut frame = vec![0; FRAME_BYTES];
     let mut font = FontTextures::roboto_regular(
         &texture_creator,
         16.0,
@@ -84,6 +69,7 @@ pub fn render() -> Result<(), String> {
     )?;
 
     let mut frame_rate = FrameRate::default();
+    let mut emulation_speed = EmulationSpeed::new(gb.core().machine_cycles());
     let mut event_pump = sdl_context.event_pump()?;
 
     let mut since_last_render = Duration::ZERO;
@@ -94,12 +80,13 @@ pub fn render() -> Result<(), String> {
 
     let mut iteration_count = 0;
     let mut cycle_count = MachineCycles::ZERO;
+    let mut presented_frame_count = 0;
 
     'running: loop {
         iteration_count += 1;
         let delta = frame_rate.update()?;
         since_last_render += delta;
-        since_last_update += delta;
+        since_last_update += delta.mul_f32(gb.speed_multiplier());
 
         for event in event_pump.poll_iter() {
             match event {
@@ -128,6 +115,8 @@ pub fn render() -> Result<(), String> {
                                 .save("screenshot.png")
                                 .map_err(|e| e.to_string())?;
                         }
+                        Keycode::F5 => gb.reset(),
+                        Keycode::Tab => gb.set_speed_multiplier(TURBO_SPEED_MULTIPLIER),
                         Keycode::F7 => {
                             // TODO write to this file on change
                             gb.dump_sram_to_file("pokemon-red.sav")?;
@@ -138,57 +127,57 @@ pub fn render() -> Result<(), String> {
                         Keycode::F9 => {
                             gb.load_state_from_file("pokemon-red.bin")?;
                         }
-                        Keycode::F10 => {
-                            let pokemon_api = PokemonApi::new(&mut gb);
-                            // println!("{:?}", pokemon_api.player_state());
-                            // println!("{:?}", pokemon_api.pokemon_party());
-                            // println!("{:?}", pokemon_api.map_state());
+                        Keycode::F10 => match PokemonApi::new(&mut gb) {
+                            Ok(pokemon_api) => {
+                                // println!("{:?}", pokemon_api.player_state());
+                                // println!("{:?}", pokemon_api.pokemon_party());
+                                // println!("{:?}", pokemon_api.map_state());
+                                println!("{}", pokemon_api.screen_text());
 
-                            for sprite in pokemon_api.sprites() {
-                                println!("{:?}", sprite);
+                                for sprite in pokemon_api.sprites() {
+                                    println!("{:?}", sprite);
+                                }
                             }
+                            Err(e) => println!("Could not read game state: {}", e),
                         },
                         Keycode::F12 => {
-                            let mut pokemon_api = PokemonApi::new(&mut gb);
-                            let player_state = pokemon_api.player_state()?;
-                            let mut party = pokemon_api.pokemon_party()?;
-                            let charizard = crate::pokemon::pokemon::Pokemon::maxed(
-                                crate::pokemon::species::PokemonSpecies::Charizard,
-                                "CHARIZARD",
-                                [
-                                    crate::pokemon::move_name::PokemonMoveName::Flamethrower,
-                                    crate::pokemon::move_name::PokemonMoveName::Slash,
-                                    crate::pokemon::move_name::PokemonMoveName::Fly,
-                                    crate::pokemon::move_name::PokemonMoveName::Earthquake,
-                                ],
-                                player_state.name,
-                                player_state.player_id
-                            );
-                            party.push(charizard)?;
-                            pokemon_api.write_pokemon_party(party);
+                            if let Err(e) = add_charizard(&mut gb) {
+                                println!("Could not add Charizard: {}", e);
+                            }
                         }
-                        Keycode::Up => gb.core_mut().mmu_mut().joypad_mut().press_button(Up),
-                        Keycode::Down => gb.core_mut().mmu_mut().joypad_mut().press_button(Down),
-                        Keycode::Left => gb.core_mut().mmu_mut().joypad_mut().press_button(Left),
-                        Keycode::Right => gb.core_mut().mmu_mut().joypad_mut().press_button(Right),
-                        Keycode::X => gb.core_mut().mmu_mut().joypad_mut().press_button(A),
-                        Keycode::Z => gb.core_mut().mmu_mut().joypad_mut().press_button(B),
-                        Keycode::Return => gb.core_mut().mmu_mut().joypad_mut().press_button(Start),
-                        Keycode::Backspace => gb.core_mut().mmu_mut().joypad_mut().press_button(Select),
+                        Keycode::Equals | Keycode::Plus | Keycode::KpPlus => {
+                            config.increase_scale();
+                            canvas.window_mut().set_size(LCD_WIDTH as u32 * config.scale, LCD_HEIGHT as u32 * config.scale)
+                                .map_err(|e| e.to_string())?;
+                        }
+                        Keycode::Minus | Keycode::KpMinus => {
+                            config.decrease_scale();
+                            canvas.window_mut().set_size(LCD_WIDTH as u32 * config.scale, LCD_HEIGHT as u32 * config.scale)
+                                .map_err(|e| e.to_string())?;
+                        }
+                        Keycode::Up => gb.press(Up),
+                        Keycode::Down => gb.press(Down),
+                        Keycode::Left => gb.press(Left),
+                        Keycode::Right => gb.press(Right),
+                        Keycode::X => gb.press(A),
+                        Keycode::Z => gb.press(B),
+                        Keycode::Return => gb.press(Start),
+                        Keycode::Backspace => gb.press(Select),
                         _ => {}
                     };
                 }
                 Event::KeyUp { keycode: Some(keycode), repeat: false, .. } => {
                     use crate::joypad::JoypadButton::*;
                     match keycode {
-                        Keycode::Up => gb.core_mut().mmu_mut().joypad_mut().release_button(Up),
-                        Keycode::Down => gb.core_mut().mmu_mut().joypad_mut().release_button(Down),
-                        Keycode::Left => gb.core_mut().mmu_mut().joypad_mut().release_button(Left),
-                        Keycode::Right => gb.core_mut().mmu_mut().joypad_mut().release_button(Right),
-                        Keycode::X => gb.core_mut().mmu_mut().joypad_mut().release_button(A),
-                        Keycode::Z => gb.core_mut().mmu_mut().joypad_mut().release_button(B),
-                        Keycode::Return => gb.core_mut().mmu_mut().joypad_mut().release_button(Start),
-                        Keycode::Backspace => gb.core_mut().mmu_mut().joypad_mut().release_button(Select),
+                        Keycode::Tab => gb.set_speed_multiplier(1.0),
+                        Keycode::Up => gb.release(Up),
+                        Keycode::Down => gb.release(Down),
+                        Keycode::Left => gb.release(Left),
+                        Keycode::Right => gb.release(Right),
+                        Keycode::X => gb.release(A),
+                        Keycode::Z => gb.release(B),
+                        Keycode::Return => gb.release(Start),
+                        Keycode::Backspace => gb.release(Select),
                         _ => {}
                     };
                 }
@@ -213,20 +202,14 @@ pub fn render() -> Result<(), String> {
             ahead_by_cycles += cycles - min_cycles;
         }
 
-        let audio_buffer = gb.core_mut().mmu_mut().audio_mut().buffer_mut();
-        let required_input_frames = resampler.input_frames_next();
-        let required_input_samples = required_input_frames * 2; // stereo
-        while audio_buffer.len() >= required_input_samples {
-            let audio_sample = audio_buffer.drain(..required_input_samples).collect::<Vec<f32>>();
-            let input_adapter = InterleavedSlice::new(&audio_sample, 2, audio_sample.len() / 2)
-                .map_err(|e| format!("could not create input_adapter: {}", e))?;
-            let output_frames = resampler.output_frames_next();
-            let mut output_adapter =
-                InterleavedSlice::new_mut(&mut resampled_audio_buffer, audio_spec.channels as usize, output_frames * 2)
-                    .map_err(|e| format!("could not create output_adapter: {}", e))?;
-            let (_, frames_written) = resampler.process_into_buffer(&input_adapter, &mut output_adapter, None)
-                .map_err(|e| format!("Audio error: {}", e))?;
-            audio_queue.queue_audio(&resampled_audio_buffer[..frames_written * 2])?;
+        if gb.speed_multiplier() > 1.0 {
+            // drop audio while fast-forwarding rather than let the SDL queue fall behind
+            gb.core_mut().mmu_mut().audio_mut().buffer_mut().clear();
+        }
+        resampler.process(gb.core_mut().mmu_mut().audio_mut().buffer_mut(), &mut resampled_audio_buffer);
+        if !resampled_audio_buffer.is_empty() {
+            audio_queue.queue_audio(&resampled_audio_buffer)?;
+            resampled_audio_buffer.clear();
         }
 
         if since_last_render >= TARGET_FRAME_TIME {
@@ -234,21 +217,22 @@ pub fn render() -> Result<(), String> {
 
             canvas.clear();
 
-            // Copy LCD data to texture
-            lcd_texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
-                let lcd = gb.core().mmu().ppu().lcd();
-                for y in 0..LCD_HEIGHT {
-                    for x in 0..LCD_WIDTH {
-                        let [r, g, b] = lcd[y * LCD_WIDTH + x].to_rgb().0;
-                        let pixel_color = Color::RGB(r, g, b);
-                        let offset = y * pitch + x * 3;
-                        buffer[offset] = pixel_color.r;
-                        buffer[offset + 1] = pixel_color.g;
-                        buffer[offset + 2] = pixel_color.b;
-                    }
-                }
-            }).map_err(|e| e.to_string())?;
-            canvas.copy(&lcd_texture, None, None)
+            // Copy LCD data to texture, only when the PPU has completed a new frame (or the blanked LCD is switched off)
+            let ppu = gb.core().mmu().ppu();
+            let frame_count = ppu.frame_count();
+            if frame_count != presented_frame_count || !ppu.lcd_control().is_enabled() {
+                presented_frame_count = frame_count;
+                renderer.palette = ppu.palette().color_map();
+                renderer.render_into(ppu.lcd(), &mut frame);
+                lcd_texture.update(None, &frame, LCD_WIDTH * RGBA_BYTES)
+                    .map_err(|e| e.to_string())?;
+            }
+            let viewport = if config.integer_scaling {
+                Some(Viewport::integer_scaled(canvas.output_size()?, (LCD_WIDTH as u32, LCD_HEIGHT as u32)).rect())
+            } else {
+                None // stretch to fill the window
+            };
+            canvas.copy(&lcd_texture, None, viewport)
                 .map_err(|e| e.to_string())?;
 
             frame_timestamps.push_back(Instant::now());
@@ -277,6 +261,14 @@ pub fn render() -> Result<(), String> {
                 25
             )?;
 
+            let cycles_per_second = emulation_speed.update(gb.core().machine_cycles())?;
+            font.render_text(
+                &mut canvas,
+                &format!("Speed: {:.0}%", relative_speed(cycles_per_second) * 100.0),
+                5,
+                45
+            )?;
+
             canvas.present();
         }
 
@@ -286,3 +278,24 @@ pub fn render() -> Result<(), String> {
     Ok(())
 }
 
+/// Debug helper that adds a maxed out Charizard to the party
+fn add_charizard(gb: &mut GameBoy) -> Result<(), String> {
+    let mut pokemon_api = PokemonApi::new(gb)?;
+    let player_state = pokemon_api.player_state()?;
+    let mut party = pokemon_api.pokemon_party()?;
+    let charizard = crate::pokemon::pokemon::Pokemon::maxed(
+        crate::pokemon::species::PokemonSpecies::Charizard,
+        "CHARIZARD",
+        [
+            crate::pokemon::move_name::PokemonMoveName::Flamethrower,
+            crate::pokemon::move_name::PokemonMoveName::Slash,
+            crate::pokemon::move_name::PokemonMoveName::Fly,
+            crate::pokemon::move_name::PokemonMoveName::Earthquake,
+        ],
+        player_state.name,
+        player_state.player_id
+    );
+    party.push(charizard)?;
+    pokemon_api.write_pokemon_party(party);
+    Ok(())
+}
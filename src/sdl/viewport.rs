@@ -0,0 +1,106 @@
+use sdl2::rect::Rect;
+
+pub const MIN_SCALE: u32 = 1;
+pub const MAX_SCALE: u32 = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RenderConfig {
+    /// Initial window size as a multiple of the LCD size
+    pub scale: u32,
+    /// Letterbox the LCD to the largest integer multiple that fits the window, rather than stretching it
+    pub integer_scaling: bool,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            scale: 4,
+            integer_scaling: true,
+        }
+    }
+}
+
+impl RenderConfig {
+    pub fn increase_scale(&mut self) {
+        self.scale = (self.scale + 1).min(MAX_SCALE);
+    }
+
+    pub fn decrease_scale(&mut self) {
+        self.scale = self.scale.saturating_sub(1).max(MIN_SCALE);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Viewport {
+    pub scale: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    /// Centers the framebuffer in the window at the largest integer scale that fits, never less than 1x
+    pub fn integer_scaled(window: (u32, u32), framebuffer: (u32, u32)) -> Self {
+        let (window_width, window_height) = window;
+        let (framebuffer_width, framebuffer_height) = framebuffer;
+        let scale = (window_width / framebuffer_width)
+            .min(window_height / framebuffer_height)
+            .max(MIN_SCALE);
+        let width = framebuffer_width * scale;
+        let height = framebuffer_height * scale;
+        Self {
+            scale,
+            x: window_width.saturating_sub(width) / 2,
+            y: window_height.saturating_sub(height) / 2,
+            width,
+            height,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x as i32, self.y as i32, self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LCD: (u32, u32) = (160, 144);
+
+    #[test]
+    fn exact_fit() {
+        assert_eq!(
+            Viewport::integer_scaled((640, 576), LCD),
+            Viewport { scale: 4, x: 0, y: 0, width: 640, height: 576 }
+        );
+    }
+
+    #[test]
+    fn letterbox() {
+        // 1920x1080 fits 7x vertically (1008) and 12x horizontally, so the height limits the scale
+        assert_eq!(
+            Viewport::integer_scaled((1920, 1080), LCD),
+            Viewport { scale: 7, x: 400, y: 36, width: 1120, height: 1008 }
+        );
+    }
+
+    #[test]
+    fn smaller_than_framebuffer() {
+        assert_eq!(
+            Viewport::integer_scaled((100, 100), LCD),
+            Viewport { scale: 1, x: 0, y: 0, width: 160, height: 144 }
+        );
+    }
+
+    #[test]
+    fn config_scale_is_clamped() {
+        let mut config = RenderConfig { scale: MAX_SCALE, integer_scaling: true };
+        config.increase_scale();
+        assert_eq!(config.scale, MAX_SCALE);
+        config.scale = MIN_SCALE;
+        config.decrease_scale();
+        assert_eq!(config.scale, MIN_SCALE);
+    }
+}
@@ -1,4 +1,7 @@
-use bincode::{Decode, Encode};
+use std::fmt::{Debug, Formatter};
+use bincode::{BorrowDecode, Decode, Encode};
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::Encoder;
 use crate::cycles::MachineCycles;
 use crate::activation::Activation;
 
@@ -10,6 +13,7 @@ pub struct Serial {
     state: SerialState,
     buffer: Option<Vec<u8>>,
     interrupt_pending: bool,
+    link: SerialLink,
 }
 
 impl Default for Serial {
@@ -21,6 +25,7 @@ impl Default for Serial {
             state: SerialState::Idle,
             buffer: None,
             interrupt_pending: false,
+            link: SerialLink::default(),
         }
     }
 }
@@ -34,6 +39,27 @@ impl Serial {
         self.buffer.as_deref()
     }
 
+    /// Transmitted bytes as text, empty unless the buffer is enabled
+    pub fn output_text(&self) -> String {
+        self.buffered_bytes()
+            .map(|b| String::from_utf8_lossy(b).to_string())
+            .unwrap_or_default()
+    }
+
+    /// Connect the link port, the callback is called with each byte sent by an internally clocked transfer
+    /// and returns the byte received from the other side
+    pub fn set_transfer_callback(&mut self, callback: Box<dyn FnMut(u8) -> u8>) {
+        self.link = SerialLink(Some(callback));
+    }
+
+    pub(crate) fn take_link(&mut self) -> SerialLink {
+        std::mem::take(&mut self.link)
+    }
+
+    pub(crate) fn set_link(&mut self, link: SerialLink) {
+        self.link = link;
+    }
+
     pub fn set_data(&mut self, data: u8) {
         self.data = data;
     }
@@ -66,7 +92,8 @@ impl Serial {
                     buffer.push(self.data);
                 }
                 self.transfer_enable = false;
-                self.data = 0xFF;
+                // with nothing connected the data line is pulled high
+                self.data = self.link.0.as_mut().map_or(0xFF, |callback| callback(self.data));
                 self.interrupt_pending = true;
                 SerialState::Idle
             } else {
@@ -83,6 +110,48 @@ enum SerialState {
     Transferring { cycles: MachineCycles },
 }
 
+/// An external link cable partner, not part of the save state
+#[derive(Default)]
+pub struct SerialLink(Option<Box<dyn FnMut(u8) -> u8>>);
+
+impl Debug for SerialLink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SerialLink({})", if self.0.is_some() { "connected" } else { "disconnected" })
+    }
+}
+
+impl Clone for SerialLink {
+    fn clone(&self) -> Self {
+        Self::default() // callbacks cannot be cloned, the clone is disconnected
+    }
+}
+
+impl PartialEq for SerialLink {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.is_some() == other.0.is_some() // callbacks cannot be compared
+    }
+}
+
+impl Eq for SerialLink {}
+
+impl Encode for SerialLink {
+    fn encode<E: Encoder>(&self, _: &mut E) -> Result<(), bincode::error::EncodeError> {
+        Ok(())
+    }
+}
+
+impl<Context> Decode<Context> for SerialLink {
+    fn decode<D: Decoder<Context=Context>>(_: &mut D) -> Result<Self, bincode::error::DecodeError> {
+        Ok(Self::default())
+    }
+}
+
+impl<'de, Context> BorrowDecode<'de, Context> for SerialLink {
+    fn borrow_decode<D: BorrowDecoder<'de, Context=Context>>(_: &mut D) -> Result<Self, bincode::error::DecodeError> {
+        Ok(Self::default())
+    }
+}
+
 impl Activation for Serial {
     fn is_activation_pending(&self) -> bool {
         self.interrupt_pending
@@ -91,4 +160,65 @@ impl Activation for Serial {
     fn clear_activation(&mut self) {
         self.interrupt_pending = false
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(serial: &mut Serial, byte: u8) {
+        serial.set_data(byte);
+        serial.set_control(0x81); // start transfer with internal clock
+        serial.update(MachineCycles::PER_SERIAL_BYTE_TRANSFER);
+    }
+
+    #[test]
+    fn output_text() {
+        let mut serial = Serial::default();
+        transfer(&mut serial, b'X');
+        assert_eq!(serial.output_text(), ""); // buffer is disabled by default
+
+        serial.enable_buffer();
+        for byte in b"Passed" {
+            transfer(&mut serial, *byte);
+            assert!(serial.consume_pending_activation()); // each byte raises the serial interrupt
+            assert_eq!(serial.control() & 0x80, 0); // transfer complete
+        }
+        assert_eq!(serial.output_text(), "Passed");
+    }
+
+    #[test]
+    fn transfer_callback() {
+        let mut serial = Serial::default();
+        transfer(&mut serial, 0x12);
+        assert_eq!(serial.get_data(), 0xFF); // nothing connected
+        assert!(serial.consume_pending_activation());
+        assert_eq!(serial.clone(), serial);
+
+        let sent = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let sent_by_callback = sent.clone();
+        serial.set_transfer_callback(Box::new(move |byte| {
+            sent_by_callback.borrow_mut().push(byte);
+            byte.wrapping_add(1)
+        }));
+
+        serial.set_data(0x42);
+        serial.set_control(0x81);
+        serial.update(MachineCycles::PER_SERIAL_BYTE_TRANSFER - MachineCycles::ONE);
+        assert!(sent.borrow().is_empty()); // still shifting
+        assert!(!serial.is_activation_pending());
+
+        serial.update(MachineCycles::ONE);
+        assert_eq!(*sent.borrow(), vec![0x42]);
+        assert_eq!(serial.get_data(), 0x43);
+        assert!(serial.consume_pending_activation());
+        assert_ne!(serial.clone(), serial); // the clone is disconnected
+
+        // an externally clocked transfer waits for the other side
+        serial.set_data(0x10);
+        serial.set_control(0x80);
+        serial.update(MachineCycles::PER_SERIAL_BYTE_TRANSFER);
+        assert_eq!(*sent.borrow(), vec![0x42]);
+        assert_eq!(serial.get_data(), 0x10);
+    }
+}
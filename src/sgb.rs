@@ -0,0 +1,162 @@
+use bincode::{Decode, Encode};
+
+/// https://gbdev.io/pandocs/SGB_Command_Packet.html
+pub const SGB_PACKET_SIZE: usize = 16;
+const SGB_PACKET_BITS: u8 = SGB_PACKET_SIZE as u8 * 8;
+
+/// A complete SGB command, multi-packet commands are concatenated into a single data buffer
+#[derive(Debug, Clone, PartialEq, Eq, Decode, Encode)]
+pub struct SgbPacket {
+    data: Vec<u8>,
+}
+
+impl SgbPacket {
+    /// The command code from the upper 5 bits of the header byte
+    pub fn command(&self) -> u8 {
+        self.data[0] >> 3
+    }
+
+    /// The number of 16 byte packets in this command
+    pub fn length(&self) -> usize {
+        self.data.len() / SGB_PACKET_SIZE
+    }
+
+    /// The raw bytes of every packet including the header byte
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Decodes SGB command packets that are pulsed out through P14 & P15 of the joypad register
+#[derive(Debug, Clone, PartialEq, Eq, Default, Decode, Encode)]
+pub struct SgbReceiver {
+    lines: u8,
+    receiving: bool,
+    bit_index: u8,
+    buffer: [u8; SGB_PACKET_SIZE],
+    remaining_packets: usize,
+    data: Vec<u8>,
+    packets: Vec<SgbPacket>,
+}
+
+impl SgbReceiver {
+    /// Observe a write to 0xFF00
+    pub fn write(&mut self, value: u8) {
+        let lines = value & 0x30;
+        let previous = self.lines;
+        self.lines = lines;
+
+        if lines == 0x00 {
+            // reset pulse: P14 & P15 both low starts a new packet
+            self.receiving = true;
+            self.bit_index = 0;
+            self.buffer = [0; SGB_PACKET_SIZE];
+            return;
+        }
+
+        // bits are only sampled on the first write after both lines are released high
+        if !self.receiving || previous != 0x30 || lines == 0x30 {
+            return;
+        }
+
+        // P14 low = 0 bit, P15 low = 1 bit
+        let bit = lines == 0x10;
+        if self.bit_index < SGB_PACKET_BITS {
+            if bit {
+                self.buffer[(self.bit_index / 8) as usize] |= 1 << (self.bit_index % 8);
+            }
+            self.bit_index += 1;
+            return;
+        }
+
+        // 129th bit is the stop bit and must be a 0
+        self.receiving = false;
+        if bit {
+            self.remaining_packets = 0;
+            self.data.clear();
+            return;
+        }
+        self.receive_packet();
+    }
+
+    fn receive_packet(&mut self) {
+        if self.remaining_packets == 0 {
+            // first packet carries the header
+            self.remaining_packets = (self.buffer[0] & 0x7).max(1) as usize;
+            self.data.clear();
+        }
+        self.data.extend_from_slice(&self.buffer);
+        self.remaining_packets -= 1;
+
+        if self.remaining_packets == 0 {
+            self.packets.push(SgbPacket { data: std::mem::take(&mut self.data) });
+        }
+    }
+
+    pub fn take_packets(&mut self) -> Vec<SgbPacket> {
+        std::mem::take(&mut self.packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send_packet(receiver: &mut SgbReceiver, packet: [u8; SGB_PACKET_SIZE]) {
+        receiver.write(0x00);
+        receiver.write(0x30);
+        for byte in packet {
+            for bit in 0..8 {
+                receiver.write(if byte & (1 << bit) != 0 { 0x10 } else { 0x20 });
+                receiver.write(0x30);
+            }
+        }
+        receiver.write(0x20); // stop bit
+        receiver.write(0x30);
+    }
+
+    #[test]
+    fn single_packet() {
+        let mut receiver = SgbReceiver::default();
+        let mut packet = [0; SGB_PACKET_SIZE];
+        packet[0] = (0x11 << 3) | 1; // MLT_REQ, 1 packet
+        packet[1] = 0x01;
+        send_packet(&mut receiver, packet);
+
+        let packets = receiver.take_packets();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].command(), 0x11);
+        assert_eq!(packets[0].length(), 1);
+        assert_eq!(packets[0].data(), &packet);
+        assert!(receiver.take_packets().is_empty());
+    }
+
+    #[test]
+    fn multi_packet() {
+        let mut receiver = SgbReceiver::default();
+        let mut first = [0; SGB_PACKET_SIZE];
+        first[0] = (0x0A << 3) | 2; // PAL_SET, 2 packets
+        let second = [0xAB; SGB_PACKET_SIZE];
+
+        send_packet(&mut receiver, first);
+        assert!(receiver.take_packets().is_empty());
+        send_packet(&mut receiver, second);
+
+        let packets = receiver.take_packets();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].command(), 0x0A);
+        assert_eq!(packets[0].length(), 2);
+        assert_eq!(&packets[0].data()[SGB_PACKET_SIZE..], &second);
+    }
+
+    #[test]
+    fn joypad_polling_is_ignored() {
+        let mut receiver = SgbReceiver::default();
+        for _ in 0..200 {
+            receiver.write(0x20);
+            receiver.write(0x10);
+            receiver.write(0x30);
+        }
+        assert!(receiver.take_packets().is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+use bincode::{Decode, Encode};
+use crate::cycles::MachineCycles;
+
+/// CGB double speed mode, armed through KEY1 and performed by STOP
+/// https://gbdev.io/pandocs/CGB_Registers.html#ff4d--key1-cgb-mode-only-prepare-speed-switch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Decode, Encode)]
+pub struct SpeedSwitch {
+    double_speed: bool,
+    armed: bool,
+    odd_cycle: bool, // half a normal speed machine cycle carried over from the last update
+}
+
+impl SpeedSwitch {
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// Bit 7 is the current speed, bit 0 is set while a switch is armed, the rest read as 1
+    pub fn key1(&self) -> u8 {
+        let mut value = 0x7E;
+        if self.double_speed {
+            value |= 0x80;
+        }
+        if self.armed {
+            value |= 0x01;
+        }
+        value
+    }
+
+    pub fn set_key1(&mut self, value: u8) {
+        self.armed = value & 0x01 != 0;
+    }
+
+    /// Toggles the speed if a switch is armed, returns true if the speed changed
+    pub fn switch(&mut self) -> bool {
+        if !self.armed {
+            return false;
+        }
+        self.armed = false;
+        self.double_speed = !self.double_speed;
+        self.odd_cycle = false;
+        true
+    }
+
+    /// Converts CPU machine cycles into machine cycles of the normal speed clock that the PPU & APU run from
+    pub fn normal_speed_cycles(&mut self, cycles: MachineCycles) -> MachineCycles {
+        if !self.double_speed {
+            return cycles;
+        }
+        let half_cycles = cycles.m_cycles() + self.odd_cycle as usize;
+        self.odd_cycle = half_cycles % 2 == 1;
+        MachineCycles::from_m(half_cycles / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key1() {
+        let mut speed = SpeedSwitch::default();
+        assert_eq!(speed.key1(), 0x7E);
+        assert!(!speed.switch()); // not armed
+
+        speed.set_key1(0x01);
+        assert_eq!(speed.key1(), 0x7F);
+        assert!(speed.switch());
+        assert_eq!(speed.key1(), 0xFE);
+        assert!(speed.is_double_speed());
+
+        speed.set_key1(0xFF);
+        assert!(speed.switch());
+        assert_eq!(speed.key1(), 0x7E);
+    }
+
+    #[test]
+    fn normal_speed_cycles() {
+        let mut speed = SpeedSwitch::default();
+        assert_eq!(speed.normal_speed_cycles(MachineCycles::from_m(3)), MachineCycles::from_m(3));
+
+        speed.set_key1(0x01);
+        speed.switch();
+        assert_eq!(speed.normal_speed_cycles(MachineCycles::from_m(3)), MachineCycles::from_m(1));
+        assert_eq!(speed.normal_speed_cycles(MachineCycles::ONE), MachineCycles::from_m(1)); // odd cycle carried over
+        assert_eq!(speed.normal_speed_cycles(MachineCycles::ONE), MachineCycles::ZERO);
+        assert_eq!(speed.normal_speed_cycles(MachineCycles::from_m(4)), MachineCycles::from_m(2));
+    }
+}
@@ -2,13 +2,15 @@ use bincode::{Decode, Encode};
 use crate::cycles::MachineCycles;
 use crate::activation::Activation;
 
+/// TIMA is clocked by a falling edge of the selected divider counter bit ANDed with the TAC enable bit,
+/// so resetting DIV or changing TAC can also clock it https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
 #[derive(Debug, Clone, Default, Eq, PartialEq, Decode, Encode)]
 pub struct Timer {
     enabled: bool,
     mode: TimerMode,
     value: u8,
     modulo: u8,
-    cycles: MachineCycles,
+    reload: TimerReload,
     interrupt_pending: bool,
 }
 
@@ -25,9 +27,14 @@ impl Timer {
         self.mode as u8 | if self.enabled { 0b0100 } else { 0 }
     }
 
-    pub fn set_control(&mut self, value: u8) {
+    /// `counter` is the current internal divider counter
+    pub fn set_control(&mut self, value: u8, counter: u16) {
+        let input = self.input(counter);
         self.enabled = value & 0b0100 != 0;
         self.mode = TimerMode::from_repr(value & 0b11).unwrap_or_default();
+        if input && !self.input(counter) {
+            self.increment();
+        }
     }
 
     pub fn value(&self) -> u8 {
@@ -35,7 +42,16 @@ impl Timer {
     }
 
     pub fn set_value(&mut self, value: u8) {
-        self.value = value;
+        match self.reload {
+            // writing TIMA in the cycle after an overflow cancels the reload & interrupt
+            TimerReload::Pending => {
+                self.reload = TimerReload::None;
+                self.value = value;
+            }
+            // writing TIMA in the cycle it is reloaded is ignored
+            TimerReload::Reloaded => {}
+            TimerReload::None => self.value = value,
+        }
     }
 
     pub fn modulo(&self) -> u8 {
@@ -44,24 +60,57 @@ impl Timer {
 
     pub fn set_modulo(&mut self, value: u8) {
         self.modulo = value;
+        if self.reload == TimerReload::Reloaded {
+            // writing TMA in the cycle TIMA is reloaded also writes TIMA
+            self.value = value;
+        }
+    }
+
+    /// The internal divider counter is about to be reset from `counter`, which is a falling edge if the selected bit is set
+    pub fn reset_counter(&mut self, counter: u16) {
+        if self.input(counter) {
+            self.increment();
+        }
     }
 
-    pub fn update(&mut self, cycles: MachineCycles) {
-        if !self.enabled {
+    /// Advance by the specified number of cycles from `counter`, the internal divider counter before it was updated
+    pub fn update(&mut self, counter: u16, cycles: MachineCycles) {
+        if !self.enabled && self.reload == TimerReload::None {
             return;
         }
 
-        self.cycles += cycles;
+        // the selected bit falls every time the counter passes a multiple of twice its value
+        let period = self.mode.counter_bit() as usize * 2;
+        let mut start = counter as usize;
+        for _ in 0..cycles.m_cycles() {
+            self.reload = match self.reload {
+                TimerReload::Pending => {
+                    self.value = self.modulo;
+                    self.interrupt_pending = true;
+                    TimerReload::Reloaded
+                }
+                _ => TimerReload::None,
+            };
 
-        let cycles_per_tick = self.mode.cycles_per_tick();
-        while self.cycles >= cycles_per_tick {
-            self.cycles -= cycles_per_tick;
-            if self.value == 0xFF {
-                self.value = self.modulo;
-                self.interrupt_pending = true;
-            } else {
-                self.value += 1;
+            let end = start + MachineCycles::ONE.t_cycles();
+            if self.enabled && end / period != start / period {
+                self.increment();
             }
+            start = end;
+        }
+    }
+
+    fn input(&self, counter: u16) -> bool {
+        self.enabled && counter & self.mode.counter_bit() != 0
+    }
+
+    /// On overflow TIMA reads 0 for a machine cycle before it is reloaded from TMA & the interrupt is requested
+    fn increment(&mut self) {
+        if self.value == 0xFF {
+            self.value = 0;
+            self.reload = TimerReload::Pending;
+        } else {
+            self.value += 1;
         }
     }
 }
@@ -87,13 +136,136 @@ enum TimerMode {
 }
 
 impl TimerMode {
-    pub fn cycles_per_tick(self) -> MachineCycles {
+    /// The bit of the internal divider counter that clocks TIMA
+    pub fn counter_bit(self) -> u16 {
         match self {
-            TimerMode::M256 => MachineCycles::from_m(256),
-            TimerMode::M4 => MachineCycles::from_m(4),
-            TimerMode::M16 => MachineCycles::from_m(16),
-            TimerMode::M64 => MachineCycles::from_m(64),
+            TimerMode::M256 => 1 << 9,
+            TimerMode::M4 => 1 << 3,
+            TimerMode::M16 => 1 << 5,
+            TimerMode::M64 => 1 << 7,
         }
     }
 }
 
+/// https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html#timer-overflow-behavior
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Decode, Encode)]
+enum TimerReload {
+    #[default]
+    None,
+    /// TIMA overflowed & reads 0, it is reloaded on the next machine cycle
+    Pending,
+    /// TIMA was reloaded from TMA on the last machine cycle
+    Reloaded,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_control(control: u8) -> Timer {
+        let mut timer = Timer::default();
+        timer.set_control(control, 0);
+        timer
+    }
+
+    #[test]
+    fn update() {
+        let mut timer = with_control(0b101); // every 4 machine cycles
+        timer.update(0, MachineCycles::from_m(3));
+        assert_eq!(timer.value(), 0);
+        timer.update(12, MachineCycles::ONE);
+        assert_eq!(timer.value(), 1);
+        timer.update(16, MachineCycles::from_m(40));
+        assert_eq!(timer.value(), 11);
+
+        // disabled
+        let mut timer = with_control(0b001);
+        timer.update(0, MachineCycles::from_m(100));
+        assert_eq!(timer.value(), 0);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut timer = with_control(0b100); // every 256 machine cycles
+        timer.set_value(0xFF);
+        timer.set_modulo(0xAB);
+        timer.update(0, MachineCycles::from_m(255));
+        assert!(!timer.is_activation_pending());
+        timer.update(1020, MachineCycles::ONE);
+        assert_eq!(timer.value(), 0x00); // reload is delayed a machine cycle
+        assert!(!timer.is_activation_pending());
+        timer.update(1024, MachineCycles::ONE);
+        assert_eq!(timer.value(), 0xAB);
+        assert!(timer.consume_pending_activation());
+    }
+
+    fn overflowed() -> Timer {
+        let mut timer = with_control(0b101);
+        timer.set_value(0xFF);
+        timer.set_modulo(0x10);
+        timer.update(0, MachineCycles::from_m(4));
+        assert_eq!(timer.value(), 0x00);
+        timer
+    }
+
+    #[test]
+    fn write_tima_cancels_reload() {
+        let mut timer = overflowed();
+        timer.set_value(0x42);
+        timer.update(16, MachineCycles::ONE);
+        assert_eq!(timer.value(), 0x42);
+        assert!(!timer.is_activation_pending());
+    }
+
+    #[test]
+    fn write_tima_during_reload_is_ignored() {
+        let mut timer = overflowed();
+        timer.update(16, MachineCycles::ONE);
+        timer.set_value(0x42);
+        assert_eq!(timer.value(), 0x10);
+        assert!(timer.is_activation_pending());
+
+        // TIMA is writable again on the next cycle
+        timer.update(20, MachineCycles::ONE);
+        timer.set_value(0x42);
+        assert_eq!(timer.value(), 0x42);
+    }
+
+    #[test]
+    fn write_tma_during_reload() {
+        let mut timer = overflowed();
+        timer.set_modulo(0x20); // before the reload, the new TMA is loaded
+        timer.update(16, MachineCycles::ONE);
+        assert_eq!(timer.value(), 0x20);
+        timer.set_modulo(0x30); // during the reload, TIMA is also written
+        assert_eq!(timer.value(), 0x30);
+
+        timer.update(20, MachineCycles::ONE);
+        timer.set_modulo(0x40); // after the reload, TIMA is not affected
+        assert_eq!(timer.value(), 0x30);
+    }
+
+    #[test]
+    fn reset_counter_falling_edge() {
+        let mut timer = with_control(0b110); // bit 5
+        timer.reset_counter(0x001F);
+        assert_eq!(timer.value(), 0);
+        timer.reset_counter(0x0020);
+        assert_eq!(timer.value(), 1);
+    }
+
+    #[test]
+    fn set_control_falling_edge() {
+        // disabling the timer while the selected bit is set
+        let mut timer = with_control(0b101);
+        timer.set_control(0b001, 0x0008);
+        assert_eq!(timer.value(), 1);
+
+        // switching to a mode where the selected bit is clear
+        let mut timer = with_control(0b101);
+        timer.set_control(0b110, 0x0008);
+        assert_eq!(timer.value(), 1);
+        timer.set_control(0b101, 0x0008);
+        assert_eq!(timer.value(), 1); // rising edge
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::{Debug, Display, Formatter};
+
+/// CPU state before an instruction is executed.
+/// Displayed in the Gameboy Doctor log format https://github.com/robert/gameboy-doctor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub pc_mem: [u8; 4], // the bytes at PC, the instruction & its operands
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.sp, self.pc,
+            self.pc_mem[0], self.pc_mem[1], self.pc_mem[2], self.pc_mem[3]
+        )
+    }
+}
+
+pub type TraceFn = Box<dyn FnMut(&TraceEntry)>;
+
+/// Called with a `TraceEntry` before each executed instruction, not part of the save state
+#[derive(Default)]
+pub struct Tracer(Option<TraceFn>);
+
+impl Tracer {
+    pub fn new(callback: TraceFn) -> Self {
+        Self(Some(callback))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn trace(&mut self, entry: &TraceEntry) {
+        if let Some(callback) = self.0.as_mut() {
+            callback(entry);
+        }
+    }
+}
+
+impl Debug for Tracer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tracer({})", if self.is_enabled() { "enabled" } else { "disabled" })
+    }
+}
+
+impl Clone for Tracer {
+    fn clone(&self) -> Self {
+        Self::default() // callbacks cannot be cloned, the clone is not traced
+    }
+}
+
+impl PartialEq for Tracer {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Tracer {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gameboy_doctor_format() {
+        let entry = TraceEntry {
+            a: 0x01, f: 0xB0, b: 0x00, c: 0x13, d: 0x00, e: 0xD8, h: 0x01, l: 0x4D,
+            sp: 0xFFFE, pc: 0x0100, pc_mem: [0x00, 0xC3, 0x13, 0x02],
+        };
+        assert_eq!(
+            entry.to_string(),
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02"
+        );
+    }
+}
@@ -0,0 +1,82 @@
+pub const MIN_SPEED_MULTIPLIER: f32 = 0.1;
+pub const MAX_SPEED_MULTIPLIER: f32 = 16.0;
+
+/// Fast-forward (or slow motion), the number of emulated frames to run per real frame.
+/// Fractional multipliers carry the remainder over, so 1.5 alternates between 1 & 2 frames.
+#[derive(Debug, Clone, Copy)]
+pub struct Turbo {
+    multiplier: f32,
+    frame_budget: f32, // fraction of a frame carried over from the last real frame
+}
+
+impl Default for Turbo {
+    fn default() -> Self {
+        Self { multiplier: 1.0, frame_budget: 0.0 }
+    }
+}
+
+impl Turbo {
+    pub fn multiplier(&self) -> f32 {
+        self.multiplier
+    }
+
+    /// Clamped to `MIN_SPEED_MULTIPLIER..=MAX_SPEED_MULTIPLIER`, NaN is treated as normal speed
+    pub fn set_multiplier(&mut self, multiplier: f32) {
+        self.multiplier = if multiplier.is_nan() {
+            1.0
+        } else {
+            multiplier.clamp(MIN_SPEED_MULTIPLIER, MAX_SPEED_MULTIPLIER)
+        };
+        self.frame_budget = 0.0;
+    }
+
+    /// How many emulated frames to run for the next real frame
+    pub fn next_frames(&mut self) -> usize {
+        self.frame_budget += self.multiplier;
+        let frames = self.frame_budget.floor();
+        self.frame_budget -= frames;
+        frames as usize
+    }
+}
+
+impl PartialEq for Turbo {
+    fn eq(&self, other: &Self) -> bool {
+        self.multiplier.to_bits() == other.multiplier.to_bits()
+            && self.frame_budget.to_bits() == other.frame_budget.to_bits()
+    }
+}
+
+impl Eq for Turbo {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_frames() {
+        let mut turbo = Turbo::default();
+        assert_eq!(turbo.next_frames(), 1);
+
+        turbo.set_multiplier(2.0);
+        assert_eq!(turbo.next_frames(), 2);
+
+        turbo.set_multiplier(1.5);
+        let frames: Vec<_> = (0..4).map(|_| turbo.next_frames()).collect();
+        assert_eq!(frames, vec![1, 2, 1, 2]);
+
+        turbo.set_multiplier(0.5);
+        let frames: Vec<_> = (0..4).map(|_| turbo.next_frames()).collect();
+        assert_eq!(frames, vec![0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn set_multiplier() {
+        let mut turbo = Turbo::default();
+        turbo.set_multiplier(100.0);
+        assert_eq!(turbo.multiplier(), MAX_SPEED_MULTIPLIER);
+        turbo.set_multiplier(-1.0);
+        assert_eq!(turbo.multiplier(), MIN_SPEED_MULTIPLIER);
+        turbo.set_multiplier(f32::NAN);
+        assert_eq!(turbo.multiplier(), 1.0);
+    }
+}